@@ -3,7 +3,7 @@
 use fantoccini::{ClientBuilder, Locator};
 use image::{ImageFormat, ImageReader};
 use libfuzzer_sys::fuzz_target;
-use math_core::latex_to_mathml;
+use math_core::{latex_to_mathml, Display, MathCoreConfig};
 use std::io::Cursor;
 use std::io::Write;
 use std::process::Stdio;
@@ -12,20 +12,21 @@ use std::time::Duration;
 fuzz_target!(|data: &str| {
     // Parse with our parser
     let l2m = {
-        if let Ok(l2m) = latex_to_mathml(data, math_core::Display::Block, false) {
-            let l2m = l2m
-                .strip_prefix(r#"<math display="block">"#)
-                .unwrap()
-                .strip_suffix("</math>")
-                .unwrap()
+        let config = MathCoreConfig {
+            display: Display::Block,
+            xmlns: true,
+            annotation: true,
+            ..Default::default()
+        };
+        if let Ok(l2m) = latex_to_mathml(data, &config) {
+            l2m
                 // work around minor display differences
                 // we might want to fix these; I dunno
                 .replace(r##" lspace="0.2222em""##, "")
                 .replace(r##" rspace="0.2222em""##, "")
                 .replace(r##" lspace="0em""##, "")
                 .replace(r##" rspace="0em""##, "")
-                .replace(r##" mathvariant="normal""##, "");
-            format!(r##"<math xmlns="http://www.w3.org/1998/Math/MathML" display="block"><semantics><mrow>{l2m}</mrow><annotation encoding="application/x-tex">{data}</annotation></semantics></math>"##)
+                .replace(r##" mathvariant="normal""##, "")
         } else {
             return;
         }
@@ -21,14 +21,19 @@
 //! ```
 
 use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
     fs,
     io::{Read, Write},
     path::{Path, PathBuf},
 };
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
-use math_core::{Display, latex_to_mathml};
+use math_core::{
+    Arena, Display, LatexErrKind, LatexError, LatexToMathML, MathCoreConfig, TextRenderer,
+    latex_to_mathml, latex_to_mathml_with_errors, parse,
+};
 
 use crate::replace::{ConversionError, Replacer};
 
@@ -112,6 +117,34 @@ struct Args {
     /// Sets the display style for the formula to "block"
     #[arg(short, long, conflicts_with = "file", group = "mode")]
     block: bool,
+
+    /// Adds the LaTeX source as an annotation to the MathML output
+    #[arg(short, long)]
+    annotation: bool,
+
+    /// Renders invalid parts of formulas as errors and prints warnings, instead of aborting
+    #[arg(long)]
+    recover: bool,
+
+    /// Sets the kind of output for the formula
+    #[arg(
+        long,
+        visible_alias = "output",
+        value_enum,
+        default_value_t = Emit::Mathml,
+        conflicts_with = "file"
+    )]
+    emit: Emit,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Emit {
+    /// MathML markup
+    Mathml,
+    /// Plain Unicode text
+    Text,
+    /// The parsed syntax tree as JSON
+    AstJson,
 }
 
 fn main() {
@@ -130,16 +163,16 @@ fn main() {
         let mut replacer = Replacer::new(inline_delim, block_delim);
         if fpath == &PathBuf::from("-") {
             let input = read_stdin();
-            match replace(&mut replacer, &input) {
+            match replace(&mut replacer, &input, args.annotation, args.recover) {
                 Ok(mathml) => {
                     println!("{}", mathml);
                 }
                 Err(e) => exit_latex_error(e),
             };
         } else if args.recursive {
-            convert_html_recursive(fpath, &mut replacer);
+            convert_html_recursive(fpath, &mut replacer, args.annotation, args.recover);
         } else {
-            convert_html(fpath, &mut replacer);
+            convert_html(fpath, &mut replacer, args.annotation, args.recover);
         };
     } else if let Some(ref formula) = args.formula {
         convert_and_exit(&args, formula);
@@ -162,9 +195,47 @@ fn convert_and_exit(args: &Args, latex: &str) {
     } else {
         Display::Inline
     };
-    match latex_to_mathml(latex, display, false) {
+    let config = MathCoreConfig {
+        display,
+        annotation: args.annotation,
+        ..Default::default()
+    };
+    match args.emit {
+        Emit::Mathml => {}
+        Emit::Text => {
+            let converter =
+                LatexToMathML::new(&config).unwrap_or_else(|e| exit_latex_diagnostic(&e, latex));
+            let arena = Arena::new();
+            match converter.render(latex, &arena, TextRenderer::new()) {
+                Ok(text) => println!("{}", text),
+                Err(e) => exit_latex_diagnostic(&e, latex),
+            }
+            return;
+        }
+        Emit::AstJson => {
+            let arena = Arena::new();
+            match parse(latex, &arena) {
+                Ok(nodes) => println!(
+                    "{}",
+                    serde_json::to_string_pretty(nodes).expect("nodes can be serialized")
+                ),
+                Err(e) => exit_latex_diagnostic(&e, latex),
+            }
+            return;
+        }
+    }
+    if args.recover {
+        let (mathml, errors) = latex_to_mathml_with_errors(latex, &config)
+            .unwrap_or_else(|e| exit_latex_diagnostic(&e, latex));
+        for e in errors {
+            eprintln!("LaTeX2MathML Warning:\n{}", e.to_diagnostic(latex));
+        }
+        println!("{}", mathml);
+        return;
+    }
+    match latex_to_mathml(latex, &config) {
         Ok(mathml) => println!("{}", mathml),
-        Err(e) => exit_latex_error(e),
+        Err(e) => exit_latex_diagnostic(&e, latex),
     }
 }
 
@@ -192,15 +263,90 @@ fn convert_and_exit(args: &Args, latex: &str) {
 fn replace<'source, 'buf>(
     replacer: &'buf mut Replacer,
     input: &'source str,
+    annotation: bool,
+    recover: bool,
 ) -> Result<String, ConversionError<'buf>>
 where
     'source: 'buf,
 {
-    replacer.replace(input, |buf, latex, display| {
-        let result = latex_to_mathml(latex, display, matches!(display, Display::Block))?;
-        buf.push_str(result.as_str());
-        Ok(())
-    })
+    let inline_config = MathCoreConfig {
+        annotation,
+        ..Default::default()
+    };
+    let block_config = MathCoreConfig {
+        display: Display::Block,
+        pretty: true,
+        annotation,
+        ..Default::default()
+    };
+    // The config has no macros, so creating the converters cannot fail.
+    let inline_converter = LatexToMathML::new(&inline_config).expect("no macros to define");
+    let block_converter = LatexToMathML::new(&block_config).expect("no macros to define");
+    let converter = |display| match display {
+        Display::Inline => &inline_converter,
+        Display::Block => &block_converter,
+    };
+    // Equations are numbered throughout the whole file. As `\eqref` may refer to
+    // equations further down, the labels are collected in a first pass.
+    let labels = RefCell::new(HashMap::new());
+    let first_pass_counter = Cell::new(0);
+    let equation_counter = Cell::new(0);
+    replacer.replace_two_pass(
+        input,
+        |latex, display| {
+            with_numbering(
+                converter(display),
+                &first_pass_counter,
+                &labels,
+                |converter| converter.collect_labels(latex),
+            );
+        },
+        |buf, latex, display| {
+            if !recover {
+                let result = with_numbering(
+                    converter(display),
+                    &equation_counter,
+                    &labels,
+                    |converter| converter.convert(latex),
+                )?;
+                buf.push_str(result.as_str());
+                return Ok(());
+            }
+            let (result, mut errors) = with_numbering(
+                converter(display),
+                &equation_counter,
+                &labels,
+                |converter| converter.convert_with_errors(latex),
+            );
+            if let Some(idx) = errors
+                .iter()
+                .position(|e| matches!(e.1, LatexErrKind::UndefinedLabel(_)))
+            {
+                return Err(errors.swap_remove(idx));
+            }
+            for e in errors {
+                eprintln!("LaTeX2MathML Warning:\n{}", e.to_diagnostic(latex));
+            }
+            buf.push_str(result.as_str());
+            Ok(())
+        },
+    )
+}
+
+/// Process one formula of a document with `f`, continuing the numbering of the
+/// equations before it.
+fn with_numbering<T>(
+    converter: &LatexToMathML,
+    equation_counter: &Cell<usize>,
+    labels: &RefCell<HashMap<String, String>>,
+    f: impl FnOnce(&LatexToMathML) -> T,
+) -> T {
+    converter.set_equation_counter(equation_counter.get());
+    converter.set_labels(labels.take());
+    let result = f(converter);
+    equation_counter.set(converter.equation_counter());
+    labels.replace(converter.take_labels());
+    result
 }
 
 /// Convert all LaTeX expressions for all HTML files in a given directory.
@@ -228,24 +374,30 @@ where
 /// Then all LaTeX equations in HTML files under the directory `./target/doc`
 /// will be converted into MathML.
 ///
-fn convert_html_recursive<P: AsRef<Path>>(path: P, replacer: &mut Replacer) {
+fn convert_html_recursive<P: AsRef<Path>>(
+    path: P,
+    replacer: &mut Replacer,
+    annotation: bool,
+    recover: bool,
+) {
     if path.as_ref().is_dir() {
         let dir = fs::read_dir(path).unwrap_or_else(|e| exit_io_error(e));
         for entry in dir.filter_map(Result::ok) {
-            convert_html_recursive(entry.path(), replacer)
+            convert_html_recursive(entry.path(), replacer, annotation, recover)
         }
     } else if path.as_ref().is_file() {
         if let Some(ext) = path.as_ref().extension() {
             if ext == "html" {
-                convert_html(&path, replacer);
+                convert_html(&path, replacer, annotation, recover);
             }
         }
     }
 }
 
-fn convert_html<P: AsRef<Path>>(fp: P, replacer: &mut Replacer) {
+fn convert_html<P: AsRef<Path>>(fp: P, replacer: &mut Replacer, annotation: bool, recover: bool) {
     let original = fs::read_to_string(&fp).unwrap_or_else(|e| exit_io_error(e));
-    let converted = replace(replacer, &original).unwrap_or_else(|e| exit_latex_error(e));
+    let converted =
+        replace(replacer, &original, annotation, recover).unwrap_or_else(|e| exit_latex_error(e));
     if original != converted {
         let mut fp = fs::File::create(fp).unwrap_or_else(|e| exit_io_error(e));
         fp.write_all(converted.as_bytes())
@@ -258,6 +410,11 @@ fn exit_latex_error<E: std::error::Error>(e: E) -> ! {
     std::process::exit(2);
 }
 
+fn exit_latex_diagnostic(e: &LatexError, latex: &str) -> ! {
+    eprintln!("{}", e.to_diagnostic(latex));
+    std::process::exit(2);
+}
+
 fn exit_io_error(e: std::io::Error) -> ! {
     eprintln!("IO Error: {}", e);
     std::process::exit(1);
@@ -282,7 +439,41 @@ condition — when considered from the stationary system, the figure of a rotati
 $$R {\sqrt{1-{\frac {v^{2}}{c^{2}}}}}, \ R, \ R .$$
 "#;
         let mut replacer = crate::Replacer::new(("$", "$"), ("$$", "$$"));
-        let mathml = crate::replace(&mut replacer, text).unwrap();
+        let mathml = crate::replace(&mut replacer, text, false, false).unwrap();
         println!("{}", mathml);
     }
+
+    #[test]
+    fn references() {
+        let text = r#"By $\eqref{eq:energy}$ and $\eqref{eq:momentum}$,
+$$\begin{align} E &= mc^2 \label{eq:energy} \\ p &= mv \label{eq:momentum} \end{align}$$"#;
+        let mut replacer = crate::Replacer::new(("$", "$"), ("$$", "$$"));
+        let mathml = crate::replace(&mut replacer, text, false, false).unwrap();
+        assert!(mathml.contains(r##"<mrow href="#eq:energy"><mtext>(1)</mtext></mrow>"##));
+        assert!(mathml.contains(r##"<mrow href="#eq:momentum"><mtext>(2)</mtext></mrow>"##));
+        assert!(mathml.contains(r#"<mtr id="eq:energy">"#));
+
+        let text = r"$$E = mc^2 \tag{1} \label{eq:energy}$$ and $\eqref{eq:mass}$";
+        let err = crate::replace(&mut replacer, text, false, false).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::replace::ConversionError(
+                _,
+                crate::replace::ConvErrKind::UnresolvedReference("eq:mass")
+            )
+        ));
+    }
+
+    #[test]
+    fn recovery() {
+        let text = r"a $x + \foo$ b";
+        let mut replacer = crate::Replacer::new(("$", "$"), ("$$", "$$"));
+        let err = crate::replace(&mut replacer, text, false, false).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::replace::ConversionError(_, crate::replace::ConvErrKind::LatexError(_, _))
+        ));
+        let mathml = crate::replace(&mut replacer, text, false, true).unwrap();
+        assert!(mathml.contains("<merror><mtext>\\foo</mtext></merror>"));
+    }
 }
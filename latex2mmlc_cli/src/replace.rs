@@ -2,7 +2,7 @@ use std::fmt;
 
 use memchr::memmem::Finder;
 
-use math_core::{Display, LatexError};
+use math_core::{Display, LatexErrKind, LatexError};
 
 use crate::html_entities::replace_html_entities;
 
@@ -15,6 +15,8 @@ pub enum ConvErrKind<'source> {
     NestedDelimiters,
     MismatchedDelimiters(usize),
     LatexError(LatexError<'source>, &'source str),
+    /// An `\eqref` to a label which is not defined anywhere in the input.
+    UnresolvedReference(&'source str),
 }
 impl fmt::Display for ConversionError<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -28,7 +30,10 @@ impl fmt::Display for ConversionError<'_> {
                 write!(f, "Mismatched delimiters at {idx} and {close}")
             }
             ConvErrKind::LatexError(e, input) => {
-                write!(f, "Error at {} in '{}':\n{}", idx, input, e)
+                write!(f, "Error at {}:\n{}", idx, e.to_diagnostic(input))
+            }
+            ConvErrKind::UnresolvedReference(label) => {
+                write!(f, "Unresolved reference to \"{label}\" at {idx}")
             }
         }
     }
@@ -146,11 +151,13 @@ impl<'config> Replacer<'config> {
                 // This is quite unfortunate, but we only have to do this in the error case,
                 // which is hopefully not too common.
                 let replaced = replace_html_entities(&mut self.entity_buffer, content);
-                let latex_error = f(&mut result, replaced, open_typ).unwrap_err();
-                return Err(ConversionError(
-                    start,
-                    ConvErrKind::LatexError(latex_error, replaced),
-                ));
+                let kind = match f(&mut result, replaced, open_typ).unwrap_err() {
+                    LatexError(_, LatexErrKind::UndefinedLabel(label)) => {
+                        ConvErrKind::UnresolvedReference(label)
+                    }
+                    latex_error => ConvErrKind::LatexError(latex_error, replaced),
+                };
+                return Err(ConversionError(start, kind));
             }
             // Update current position
             current_pos = end + closing_delim_len;
@@ -159,6 +166,30 @@ impl<'config> Replacer<'config> {
         Ok(result)
     }
 
+    /// Like [`replace`](Self::replace), but first calls `collect` on the content of all
+    /// delimiters, before any of it is converted.
+    ///
+    /// This allows `f` to use information from the whole input, like the labels of
+    /// equations which are referred to before they appear.
+    pub(crate) fn replace_two_pass<'source, 'buf, C, F>(
+        &'buf mut self,
+        input: &'source str,
+        collect: C,
+        f: F,
+    ) -> Result<String, ConversionError<'buf>>
+    where
+        C: Fn(&str, Display),
+        F: for<'a> Fn(&mut String, &'a str, Display) -> Result<(), LatexError<'a>>,
+        'source: 'buf,
+    {
+        // Errors with the delimiters are reported by the second pass.
+        let _ = self.replace(input, |_buf, content, typ| {
+            collect(content, typ);
+            Ok(())
+        });
+        self.replace(input, f)
+    }
+
     /// Finds the next occurrence of either an inline or block delimiter.
     fn find_next_delimiter(&self, input: &str, opening: bool) -> Option<(Display, usize)> {
         let (inline_finder, block_finder) = if opening {
@@ -183,7 +214,7 @@ impl<'config> Replacer<'config> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use math_core::LatexErrKind;
+    use std::cell::RefCell;
     use std::fmt::Write;
 
     /// Mock convert function for testing
@@ -219,7 +250,9 @@ mod tests {
                 ConvErrKind::UnclosedDelimiter => {
                     Err(ConversionError(e.0, ConvErrKind::UnclosedDelimiter))
                 }
-                ConvErrKind::LatexError(_, _) => unreachable!(),
+                ConvErrKind::LatexError(_, _) | ConvErrKind::UnresolvedReference(_) => {
+                    unreachable!()
+                }
             },
         }
     }
@@ -417,15 +450,51 @@ mod tests {
         // This conversion function always returns an error.
         let err = replacer
             .replace(input, |_buf, _content, _typ| {
-                Err(LatexError(0, LatexErrKind::UnexpectedEOF))
+                Err(LatexError(0..0, LatexErrKind::UnexpectedEOF))
             })
             .unwrap_err();
         assert!(matches!(
             err,
             ConversionError(
                 6,
-                ConvErrKind::LatexError(LatexError(0, LatexErrKind::UnexpectedEOF), "&=1")
+                ConvErrKind::LatexError(LatexError(ref span, LatexErrKind::UnexpectedEOF), "&=1")
+            ) if *span == (0..0)
+        ));
+    }
+
+    #[test]
+    fn test_two_pass() {
+        let mut replacer = Replacer::new(("$", "$"), ("$$", "$$"));
+        let input = "see $b$ for $$a$$";
+        let collected = RefCell::new(String::new());
+        let result = replacer
+            .replace_two_pass(
+                input,
+                |content, _typ| collected.borrow_mut().push_str(content),
+                |buf, content, typ| {
+                    // Everything was collected before the first conversion.
+                    assert_eq!(*collected.borrow(), "ba");
+                    mock_convert(buf, content, typ)
+                },
             )
+            .unwrap();
+        assert_eq!(result, "see [T1:b] for [T2:a]");
+    }
+
+    #[test]
+    fn test_unresolved_reference() {
+        let mut replacer = Replacer::new(("$", "$"), ("$$", "$$"));
+        let input = r"see $$\eqref{eq:a}$$";
+        let err = replacer
+            .replace_two_pass(
+                input,
+                |_content, _typ| {},
+                |_buf, _content, _typ| Err(LatexError(0..0, LatexErrKind::UndefinedLabel("eq:a"))),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ConversionError(6, ConvErrKind::UnresolvedReference("eq:a"))
         ));
     }
 }
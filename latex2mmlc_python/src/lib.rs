@@ -1,37 +1,171 @@
 use pyo3::create_exception;
 use pyo3::exceptions::PyException;
 use pyo3::prelude::*;
-use pyo3::types::PyString;
+use pyo3::types::{PyDict, PyString};
 
-use math_core::{Display, latex_to_mathml};
+use math_core::{Display, ErrorPolicy, MathCoreConfig, latex_to_mathml};
 
 create_exception!(_latex2mmlc_rust, LatexError, PyException);
 
+/// The options of the conversion, given as a dict.
+///
+/// Options which are missing from the dict take their values from
+/// `MathCoreConfig::default()`.
+struct Options {
+    block: bool,
+    pretty: bool,
+    indent_width: usize,
+    xmlns: bool,
+    annotation: bool,
+    render_errors: bool,
+    equation_counter: usize,
+    macros: Vec<(String, String)>,
+}
+
+impl<'py> FromPyObject<'py> for Options {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let dict = ob.downcast::<PyDict>()?;
+        let defaults = MathCoreConfig::default();
+        Ok(Options {
+            block: item(dict, "block")?.unwrap_or(defaults.display == Display::Block),
+            pretty: item(dict, "pretty")?.unwrap_or(defaults.pretty),
+            indent_width: item(dict, "indent_width")?.unwrap_or(defaults.indent_width),
+            xmlns: item(dict, "xmlns")?.unwrap_or(defaults.xmlns),
+            annotation: item(dict, "annotation")?.unwrap_or(defaults.annotation),
+            render_errors: item(dict, "render_errors")?
+                .unwrap_or(defaults.error_policy == ErrorPolicy::Render),
+            equation_counter: item(dict, "equation_counter")?.unwrap_or(defaults.equation_counter),
+            macros: item(dict, "macros")?.unwrap_or_default(),
+        })
+    }
+}
+
+/// The value of `key` in `dict`, or `None` if the key is missing.
+fn item<'py, T: FromPyObject<'py>>(dict: &Bound<'py, PyDict>, key: &str) -> PyResult<Option<T>> {
+    dict.get_item(key)?.map(|value| value.extract()).transpose()
+}
+
+impl Options {
+    fn config(&self) -> MathCoreConfig<'_> {
+        MathCoreConfig {
+            display: display(self.block),
+            pretty: self.pretty,
+            indent_width: self.indent_width,
+            xmlns: self.xmlns,
+            annotation: self.annotation,
+            error_policy: if self.render_errors {
+                ErrorPolicy::Render
+            } else {
+                ErrorPolicy::Return
+            },
+            equation_counter: self.equation_counter,
+            macros: self
+                .macros
+                .iter()
+                .map(|(name, body)| (name.as_str(), body.as_str()))
+                .collect(),
+        }
+    }
+}
+
+/// Convert the error to a Python exception, with the span of the error in the `start`
+/// and `end` attributes, and its position in `source` in `line` and `column`.
+///
+/// `line` and `column` are `None` if the error did not occur in `source`.
+fn to_py_err(py: Python<'_>, latex_error: math_core::LatexError, source: Option<&str>) -> PyErr {
+    let err = LatexError::new_err(latex_error.to_string());
+    let (line, column) = source
+        .map(|source| latex_error.line_and_column(source))
+        .unzip();
+    let value = err.value(py);
+    let attrs = [
+        ("start", Some(latex_error.0.start)),
+        ("end", Some(latex_error.0.end)),
+        ("line", line),
+        ("column", column),
+    ];
+    for (name, attr) in attrs {
+        if let Err(e) = value.setattr(name, attr) {
+            return e;
+        }
+    }
+    err
+}
+
+fn display(block: bool) -> Display {
+    if block {
+        Display::Block
+    } else {
+        Display::Inline
+    }
+}
+
 /// Convert LaTeX equation to MathML.
 #[pyfunction]
 fn convert_latex<'a>(
     py: Python<'a>,
     latex: &str,
-    block: bool,
-    pretty: bool,
+    options: Options,
 ) -> PyResult<Bound<'a, PyString>> {
-    let result = latex_to_mathml(
-        latex,
-        if block {
-            Display::Block
-        } else {
-            Display::Inline
-        },
-        pretty,
-    )
-    .map_err(|latex_error| LatexError::new_err(latex_error.to_string()))?;
+    let result = latex_to_mathml(latex, &options.config())
+        .map_err(|latex_error| to_py_err(py, latex_error, Some(latex)))?;
     Ok(PyString::new(py, &result))
 }
 
+/// A converter which keeps user-defined commands around across many conversions.
+#[pyclass]
+struct LatexToMathML(math_core::LatexToMathML);
+
+#[pymethods]
+impl LatexToMathML {
+    #[new]
+    fn new(py: Python<'_>, options: Options) -> PyResult<Self> {
+        // The error is located in one of the macros, so there is no single source.
+        let converter = math_core::LatexToMathML::new(&options.config())
+            .map_err(|latex_error| to_py_err(py, latex_error, None))?;
+        Ok(LatexToMathML(converter))
+    }
+
+    /// Define commands from a preamble like `\newcommand{\R}{\mathbb{R}}`.
+    fn define_macros(&mut self, py: Python<'_>, preamble: &str) -> PyResult<()> {
+        self.0
+            .define_macros(preamble)
+            .map_err(|latex_error| to_py_err(py, latex_error, Some(preamble)))
+    }
+
+    /// The number of the last numbered equation so far.
+    #[getter]
+    fn equation_counter(&self) -> usize {
+        self.0.equation_counter()
+    }
+
+    #[setter]
+    fn set_equation_counter(&mut self, counter: usize) {
+        self.0.set_equation_counter(counter);
+    }
+
+    /// Convert LaTeX equation to MathML, with the commands defined so far.
+    #[pyo3(signature = (latex, block=None))]
+    fn convert<'a>(
+        &self,
+        py: Python<'a>,
+        latex: &str,
+        block: Option<bool>,
+    ) -> PyResult<Bound<'a, PyString>> {
+        let result = match block {
+            Some(block) => self.0.convert_with_display(latex, display(block)),
+            None => self.0.convert(latex),
+        }
+        .map_err(|latex_error| to_py_err(py, latex_error, Some(latex)))?;
+        Ok(PyString::new(py, &result))
+    }
+}
+
 /// A Python module implemented in Rust.
 #[pymodule]
 fn _latex2mmlc_rust(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add("LatexError", m.py().get_type::<LatexError>())?;
     m.add_function(wrap_pyfunction!(convert_latex, m)?)?;
+    m.add_class::<LatexToMathML>()?;
     Ok(())
 }
@@ -9,30 +9,164 @@ use lol_alloc::{AssumeSingleThreaded, FreeListAllocator};
 static ALLOCATOR: AssumeSingleThreaded<FreeListAllocator> =
     unsafe { AssumeSingleThreaded::new(FreeListAllocator::new()) };
 
-use math_core::{Display, latex_to_mathml};
+use math_core::{Display, ErrorPolicy, MathCoreConfig, latex_to_mathml};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen(getter_with_clone)]
 pub struct LatexError {
     pub error_message: JsValue,
+    /// The start of the span of the error, as a byte offset into the UTF-8 input.
     pub location: u32,
+    /// The end of the span of the error (exclusive), as a byte offset into the UTF-8 input.
+    pub end: u32,
 }
 
+impl From<math_core::LatexError<'_>> for LatexError {
+    fn from(e: math_core::LatexError<'_>) -> Self {
+        LatexError {
+            error_message: JsValue::from_str(&e.1.string()),
+            location: e.0.start as u32,
+            end: e.0.end as u32,
+        }
+    }
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const OPTIONS_TYPE: &'static str = r#"
+export interface ConvertOptions {
+    displayMode?: boolean;
+    pretty?: boolean;
+    indentWidth?: number;
+    xmlns?: boolean;
+    annotation?: boolean;
+    throwOnError?: boolean;
+    equationCounter?: number;
+    macros?: Record<string, string>;
+}
+"#;
+
 #[wasm_bindgen]
-pub fn convert(content: &str, block: bool, pretty: bool) -> Result<JsValue, LatexError> {
-    match latex_to_mathml(
-        content,
-        if block {
-            Display::Block
-        } else {
-            Display::Inline
-        },
-        pretty,
-    ) {
+extern "C" {
+    /// The options of the conversion, given as a JS object.
+    #[wasm_bindgen(typescript_type = "ConvertOptions")]
+    pub type ConvertOptions;
+
+    #[wasm_bindgen(method, getter, js_name = displayMode)]
+    fn display_mode(this: &ConvertOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter)]
+    fn pretty(this: &ConvertOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter, js_name = indentWidth)]
+    fn indent_width(this: &ConvertOptions) -> Option<u32>;
+    #[wasm_bindgen(method, getter)]
+    fn xmlns(this: &ConvertOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter)]
+    fn annotation(this: &ConvertOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter, js_name = throwOnError)]
+    fn throw_on_error(this: &ConvertOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter, js_name = equationCounter)]
+    fn equation_counter(this: &ConvertOptions) -> Option<u32>;
+    #[wasm_bindgen(method, getter)]
+    fn macros(this: &ConvertOptions) -> Option<js_sys::Object>;
+}
+
+impl ConvertOptions {
+    /// Read the macros as pairs of name and body, skipping any non-string entries.
+    fn macro_list(&self) -> Vec<(String, String)> {
+        let Some(macros) = self.macros() else {
+            return Vec::new();
+        };
+        js_sys::Object::entries(&macros)
+            .iter()
+            .filter_map(|entry| {
+                let entry = js_sys::Array::from(&entry);
+                Some((entry.get(0).as_string()?, entry.get(1).as_string()?))
+            })
+            .collect()
+    }
+
+    fn config<'a>(&self, macros: &'a [(String, String)]) -> MathCoreConfig<'a> {
+        let default = MathCoreConfig::default();
+        MathCoreConfig {
+            display: self.display_mode().map_or(default.display, display),
+            pretty: self.pretty().unwrap_or(default.pretty),
+            indent_width: self
+                .indent_width()
+                .map_or(default.indent_width, |width| width as usize),
+            xmlns: self.xmlns().unwrap_or(default.xmlns),
+            annotation: self.annotation().unwrap_or(default.annotation),
+            error_policy: if self.throw_on_error().unwrap_or(true) {
+                ErrorPolicy::Return
+            } else {
+                ErrorPolicy::Render
+            },
+            equation_counter: self
+                .equation_counter()
+                .map_or(default.equation_counter, |counter| counter as usize),
+            macros: macros
+                .iter()
+                .map(|(name, body)| (name.as_str(), body.as_str()))
+                .collect(),
+        }
+    }
+}
+
+fn display(block: bool) -> Display {
+    if block {
+        Display::Block
+    } else {
+        Display::Inline
+    }
+}
+
+#[wasm_bindgen]
+pub fn convert(content: &str, options: &ConvertOptions) -> Result<JsValue, LatexError> {
+    let macros = options.macro_list();
+    match latex_to_mathml(content, &options.config(&macros)) {
         Ok(result) => Ok(JsValue::from_str(&result)),
-        Err(e) => Err(LatexError {
-            error_message: JsValue::from_str(&e.1.string()),
-            location: e.0 as u32,
-        }),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A converter which keeps user-defined commands around across many conversions.
+#[wasm_bindgen]
+pub struct LatexToMathML(math_core::LatexToMathML);
+
+#[wasm_bindgen]
+impl LatexToMathML {
+    #[wasm_bindgen(constructor)]
+    pub fn new(options: &ConvertOptions) -> Result<LatexToMathML, LatexError> {
+        let macros = options.macro_list();
+        let converter = math_core::LatexToMathML::new(&options.config(&macros))?;
+        Ok(LatexToMathML(converter))
+    }
+
+    pub fn define_macros(&mut self, preamble: &str) -> Result<(), LatexError> {
+        self.0.define_macros(preamble).map_err(LatexError::from)
+    }
+
+    /// The number of the last numbered equation so far.
+    #[wasm_bindgen(getter = equationCounter)]
+    pub fn equation_counter(&self) -> u32 {
+        self.0.equation_counter() as u32
+    }
+
+    #[wasm_bindgen(setter = equationCounter)]
+    pub fn set_equation_counter(&mut self, counter: u32) {
+        self.0.set_equation_counter(counter as usize);
+    }
+
+    pub fn convert(
+        &self,
+        content: &str,
+        display_mode: Option<bool>,
+    ) -> Result<JsValue, LatexError> {
+        let result = match display_mode {
+            Some(block) => self.0.convert_with_display(content, display(block)),
+            None => self.0.convert(content),
+        };
+        match result {
+            Ok(result) => Ok(JsValue::from_str(&result)),
+            Err(e) => Err(e.into()),
+        }
     }
 }
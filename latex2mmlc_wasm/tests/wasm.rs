@@ -1,11 +1,13 @@
-use wasm_bindgen_test::*;
-
 use math_core::token::{TokLoc, Token};
 use mathml_renderer::ast::Node;
 
-#[wasm_bindgen_test]
+const WORD: usize = std::mem::size_of::<usize>();
+
+// On wasm32, a word has 4 bytes, so this checks for 12, 16 and 16 bytes there.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
 fn check_sizes() {
-    assert_eq!(std::mem::size_of::<Token>(), 12, "size of Token");
-    assert_eq!(std::mem::size_of::<TokLoc>(), 16, "size of TokLoc");
-    assert_eq!(std::mem::size_of::<Node>(), 16, "size of Node");
+    assert_eq!(std::mem::size_of::<Token>(), 3 * WORD, "size of Token");
+    assert_eq!(std::mem::size_of::<TokLoc>(), 4 * WORD, "size of TokLoc");
+    assert_eq!(std::mem::size_of::<Node>(), 4 * WORD, "size of Node");
 }
@@ -1,100 +1,230 @@
-use mathml_renderer::attribute::RowAttr;
+/// A color given by its red, green and blue components.
+pub(crate) type Rgb = (u8, u8, u8);
 
-static COLORS: phf::Map<&'static str, RowAttr> = phf::phf_map! {
+static COLORS: phf::Map<&'static str, Rgb> = phf::phf_map! {
     // RGB
-    "red" => RowAttr::Color(255, 0, 0),
-    "green" => RowAttr::Color(3, 255, 0),
-    "blue" => RowAttr::Color(0, 0, 255),
+    "red" => (255, 0, 0),
+    "green" => (3, 255, 0),
+    "blue" => (0, 0, 255),
     // CMYK (sRGB approximation)
-    "cyan" => RowAttr::Color(0, 185, 242),
-    "magenta" => RowAttr::Color(251, 49, 153),
-    "yellow" => RowAttr::Color(255, 235, 61),
-    "black" => RowAttr::Color(0, 0, 0),
+    "cyan" => (0, 185, 242),
+    "magenta" => (251, 49, 153),
+    "yellow" => (255, 235, 61),
+    "black" => (0, 0, 0),
     // Grayscale
-    "gray" => RowAttr::Color(128, 128, 128),
-    "white" => RowAttr::Color(255, 255, 255),
-    "darkgray" => RowAttr::Color(64, 64, 64),
-    "lightgray" => RowAttr::Color(191, 191, 191),
+    "gray" => (128, 128, 128),
+    "white" => (255, 255, 255),
+    "darkgray" => (64, 64, 64),
+    "lightgray" => (191, 191, 191),
     // Other
-    "brown" => RowAttr::Color(191, 128, 63),
-    "lime" => RowAttr::Color(191, 255, 4),
-    "olive" => RowAttr::Color(159, 140, 24),
-    "orange" => RowAttr::Color(255, 128, 1),
-    "pink" => RowAttr::Color(255, 191, 191),
-    "purple" => RowAttr::Color(191, 1, 64),
-    "teal" => RowAttr::Color(0, 128, 128),
-    "violet" => RowAttr::Color(128, 0, 128),
+    "brown" => (191, 128, 63),
+    "lime" => (191, 255, 4),
+    "olive" => (159, 140, 24),
+    "orange" => (255, 128, 1),
+    "pink" => (255, 191, 191),
+    "purple" => (191, 1, 64),
+    "teal" => (0, 128, 128),
+    "violet" => (128, 0, 128),
     // dvipsnames
-    "Apricot" => RowAttr::Color(255, 184, 130),
-    "Aquamarine" => RowAttr::Color(28, 191, 193),
-    "Bittersweet" => RowAttr::Color(208, 76, 23),
-    "Black" => RowAttr::Color(44, 46, 53),
-    "Blue" => RowAttr::Color(16, 38, 148),
-    "BlueGreen" => RowAttr::Color(20, 188, 188),
-    "BlueViolet" => RowAttr::Color(45, 52, 151),
-    "BrickRed" => RowAttr::Color(196, 49, 25),
-    "Brown" => RowAttr::Color(119, 34, 6),
-    "BurntOrange" => RowAttr::Color(255, 142, 39),
-    "CadetBlue" => RowAttr::Color(99, 114, 157),
-    "CarnationPink" => RowAttr::Color(252, 129, 188),
-    "Cerulean" => RowAttr::Color(0, 173, 231),
-    "CornflowerBlue" => RowAttr::Color(72, 187, 232),
-    "Cyan" => RowAttr::Color(0, 185, 242),
-    "Dandelion" => RowAttr::Color(255, 185, 76),
-    "DarkOrchid" => RowAttr::Color(158, 80, 143),
-    "Emerald" => RowAttr::Color(0, 177, 160),
-    "ForestGreen" => RowAttr::Color(18, 159, 87),
-    "Fuchsia" => RowAttr::Color(131, 55, 148),
-    "Goldenrod" => RowAttr::Color(255, 219, 87),
-    "Gray" => RowAttr::Color(155, 156, 159),
-    "Green" => RowAttr::Color(0, 171, 79),
-    "GreenYellow" => RowAttr::Color(225, 229, 123),
-    "JungleGreen" => RowAttr::Color(0, 177, 157),
-    "Lavender" => RowAttr::Color(253, 160, 204),
-    "LimeGreen" => RowAttr::Color(136, 201, 70),
-    "Magenta" => RowAttr::Color(251, 49, 153),
-    "Mahogany" => RowAttr::Color(180, 50, 30),
-    "Maroon" => RowAttr::Color(186, 49, 50),
-    "Melon" => RowAttr::Color(255, 157, 123),
-    "MidnightBlue" => RowAttr::Color(0, 104, 150),
-    "Mulberry" => RowAttr::Color(168, 62, 158),
-    "NavyBlue" => RowAttr::Color(8, 111, 189),
-    "OliveGreen" => RowAttr::Color(63, 126, 49),
-    "Orange" => RowAttr::Color(255, 124, 55),
-    "OrangeRed" => RowAttr::Color(255, 46, 88),
-    "Orchid" => RowAttr::Color(172, 114, 185),
-    "Peach" => RowAttr::Color(255, 147, 88),
-    "Periwinkle" => RowAttr::Color(106, 122, 191),
-    "PineGreen" => RowAttr::Color(5, 141, 116),
-    "Plum" => RowAttr::Color(135, 41, 150),
-    "ProcessBlue" => RowAttr::Color(0, 187, 242),
-    "Purple" => RowAttr::Color(143, 69, 163),
-    "RawSienna" => RowAttr::Color(158, 60, 14),
-    "Red" => RowAttr::Color(255, 46, 23),
-    "RedOrange" => RowAttr::Color(255, 92, 49),
-    "RedViolet" => RowAttr::Color(168, 44, 114),
-    "Rhodamine" => RowAttr::Color(252, 88, 170),
-    "RoyalBlue" => RowAttr::Color(0, 115, 192),
-    "RoyalPurple" => RowAttr::Color(72, 57, 158),
-    "RubineRed" => RowAttr::Color(253, 48, 136),
-    "Salmon" => RowAttr::Color(255, 144, 138),
-    "SeaGreen" => RowAttr::Color(71, 196, 160),
-    "Sepia" => RowAttr::Color(93, 22, 1),
-    "SkyBlue" => RowAttr::Color(80, 207, 226),
-    "SpringGreen" => RowAttr::Color(196, 220, 110),
-    "Tan" => RowAttr::Color(225, 157, 117),
-    "TealBlue" => RowAttr::Color(17, 184, 184),
-    "Thistle" => RowAttr::Color(222, 132, 191),
-    "Turquoise" => RowAttr::Color(16, 190, 210),
-    "Violet" => RowAttr::Color(61, 60, 159),
-    "VioletRed" => RowAttr::Color(252, 90, 171),
-    "White" => RowAttr::Color(255, 255, 255),
-    "WildStrawberry" => RowAttr::Color(255, 55, 105),
-    "Yellow" => RowAttr::Color(255, 235, 61),
-    "YellowGreen" => RowAttr::Color(146, 208, 116),
-    "YellowOrange" => RowAttr::Color(255, 159, 43),
+    "Apricot" => (255, 184, 130),
+    "Aquamarine" => (28, 191, 193),
+    "Bittersweet" => (208, 76, 23),
+    "Black" => (44, 46, 53),
+    "Blue" => (16, 38, 148),
+    "BlueGreen" => (20, 188, 188),
+    "BlueViolet" => (45, 52, 151),
+    "BrickRed" => (196, 49, 25),
+    "Brown" => (119, 34, 6),
+    "BurntOrange" => (255, 142, 39),
+    "CadetBlue" => (99, 114, 157),
+    "CarnationPink" => (252, 129, 188),
+    "Cerulean" => (0, 173, 231),
+    "CornflowerBlue" => (72, 187, 232),
+    "Cyan" => (0, 185, 242),
+    "Dandelion" => (255, 185, 76),
+    "DarkOrchid" => (158, 80, 143),
+    "Emerald" => (0, 177, 160),
+    "ForestGreen" => (18, 159, 87),
+    "Fuchsia" => (131, 55, 148),
+    "Goldenrod" => (255, 219, 87),
+    "Gray" => (155, 156, 159),
+    "Green" => (0, 171, 79),
+    "GreenYellow" => (225, 229, 123),
+    "JungleGreen" => (0, 177, 157),
+    "Lavender" => (253, 160, 204),
+    "LimeGreen" => (136, 201, 70),
+    "Magenta" => (251, 49, 153),
+    "Mahogany" => (180, 50, 30),
+    "Maroon" => (186, 49, 50),
+    "Melon" => (255, 157, 123),
+    "MidnightBlue" => (0, 104, 150),
+    "Mulberry" => (168, 62, 158),
+    "NavyBlue" => (8, 111, 189),
+    "OliveGreen" => (63, 126, 49),
+    "Orange" => (255, 124, 55),
+    "OrangeRed" => (255, 46, 88),
+    "Orchid" => (172, 114, 185),
+    "Peach" => (255, 147, 88),
+    "Periwinkle" => (106, 122, 191),
+    "PineGreen" => (5, 141, 116),
+    "Plum" => (135, 41, 150),
+    "ProcessBlue" => (0, 187, 242),
+    "Purple" => (143, 69, 163),
+    "RawSienna" => (158, 60, 14),
+    "Red" => (255, 46, 23),
+    "RedOrange" => (255, 92, 49),
+    "RedViolet" => (168, 44, 114),
+    "Rhodamine" => (252, 88, 170),
+    "RoyalBlue" => (0, 115, 192),
+    "RoyalPurple" => (72, 57, 158),
+    "RubineRed" => (253, 48, 136),
+    "Salmon" => (255, 144, 138),
+    "SeaGreen" => (71, 196, 160),
+    "Sepia" => (93, 22, 1),
+    "SkyBlue" => (80, 207, 226),
+    "SpringGreen" => (196, 220, 110),
+    "Tan" => (225, 157, 117),
+    "TealBlue" => (17, 184, 184),
+    "Thistle" => (222, 132, 191),
+    "Turquoise" => (16, 190, 210),
+    "Violet" => (61, 60, 159),
+    "VioletRed" => (252, 90, 171),
+    "White" => (255, 255, 255),
+    "WildStrawberry" => (255, 55, 105),
+    "Yellow" => (255, 235, 61),
+    "YellowGreen" => (146, 208, 116),
+    "YellowOrange" => (255, 159, 43),
 };
 
-pub fn get_color(name: &str) -> Option<RowAttr> {
+pub fn get_color(name: &str) -> Option<Rgb> {
     COLORS.get(name).copied()
 }
+
+pub(crate) enum ColorError {
+    UnknownModel,
+    InvalidSpec,
+}
+
+/// Parse a color specification in one of the color models of the `xcolor` package,
+/// like `0.1,0.2,0.3` in the `rgb` model.
+pub(crate) fn parse_color_in_model(model: &str, spec: &str) -> Result<Rgb, ColorError> {
+    let components = spec.split(',').map(str::trim);
+    let rgb = match model {
+        "rgb" => parse_fractions::<3>(components).map(|[r, g, b]| (to_u8(r), to_u8(g), to_u8(b))),
+        "RGB" => parse_components::<u8, 3>(components).map(|[r, g, b]| (r, g, b)),
+        "HTML" => {
+            let hex = spec.trim();
+            let component = |i: usize| {
+                hex.get(i..i + 2)
+                    .and_then(|c| u8::from_str_radix(c, 16).ok())
+            };
+            match (hex.len(), component(0), component(2), component(4)) {
+                (6, Some(r), Some(g), Some(b)) => Some((r, g, b)),
+                _ => None,
+            }
+        }
+        "gray" => parse_fractions::<1>(components).map(|[gray]| {
+            let gray = to_u8(gray);
+            (gray, gray, gray)
+        }),
+        "cmyk" => parse_fractions::<4>(components).map(|[c, m, y, k]| {
+            // This is the conversion which `xcolor` uses.
+            let component = |x: f32| to_u8(1.0 - (x + k).min(1.0));
+            (component(c), component(m), component(y))
+        }),
+        _ => return Err(ColorError::UnknownModel),
+    };
+    rgb.ok_or(ColorError::InvalidSpec)
+}
+
+/// Parse a color expression of the `xcolor` package, like `red!30!blue` for a mix of
+/// 30% red and 70% blue, or `red!30` for a mix with white.
+///
+/// The names in the expression are resolved with `lookup`.
+pub(crate) fn parse_color_expression(
+    expr: &str,
+    lookup: impl Fn(&str) -> Option<Rgb>,
+) -> Option<Rgb> {
+    let mut parts = expr.split('!').map(str::trim);
+    let mut color = lookup(parts.next()?)?;
+    while let Some(percentage) = parts.next() {
+        let percentage = percentage
+            .parse::<f32>()
+            .ok()
+            .filter(|p| (0.0..=100.0).contains(p))?;
+        let other = match parts.next() {
+            Some(name) => lookup(name)?,
+            None => (255, 255, 255),
+        };
+        let mix = |a: u8, b: u8| {
+            let fraction = percentage / 100.0;
+            (f32::from(a) * fraction + f32::from(b) * (1.0 - fraction)).round() as u8
+        };
+        color = (
+            mix(color.0, other.0),
+            mix(color.1, other.1),
+            mix(color.2, other.2),
+        );
+    }
+    Some(color)
+}
+
+/// Parse exactly `N` comma-separated components.
+fn parse_components<'a, T: std::str::FromStr, const N: usize>(
+    components: impl Iterator<Item = &'a str>,
+) -> Option<[T; N]> {
+    let components: Vec<T> = components.map(|c| c.parse().ok()).collect::<Option<_>>()?;
+    components.try_into().ok()
+}
+
+/// Parse `N` numbers between 0 and 1.
+fn parse_fractions<'a, const N: usize>(
+    components: impl Iterator<Item = &'a str>,
+) -> Option<[f32; N]> {
+    parse_components::<f32, N>(components)
+        .filter(|fractions| fractions.iter().all(|x| (0.0..=1.0).contains(x)))
+}
+
+fn to_u8(fraction: f32) -> u8 {
+    (fraction * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_models() {
+        let parse = |model, spec| parse_color_in_model(model, spec).ok();
+        assert_eq!(parse("rgb", "1, 0.5, 0"), Some((255, 128, 0)));
+        assert_eq!(parse("RGB", "255,128,0"), Some((255, 128, 0)));
+        assert_eq!(parse("HTML", "FF8000"), Some((255, 128, 0)));
+        assert_eq!(parse("HTML", "ff8000"), Some((255, 128, 0)));
+        assert_eq!(parse("gray", "0.5"), Some((128, 128, 128)));
+        assert_eq!(parse("cmyk", "0,0.5,1,0"), Some((255, 128, 0)));
+        assert_eq!(parse("rgb", "1,0.5"), None);
+        assert_eq!(parse("rgb", "2,0,0"), None);
+        assert_eq!(parse("RGB", "256,0,0"), None);
+        assert_eq!(parse("HTML", "FF80"), None);
+        assert_eq!(parse("HTML", "FF80ÄÄ"), None);
+        assert!(matches!(
+            parse_color_in_model("hsb", "0,0,0"),
+            Err(ColorError::UnknownModel)
+        ));
+    }
+
+    #[test]
+    fn color_expressions() {
+        assert_eq!(parse_color_expression("red", get_color), Some((255, 0, 0)));
+        assert_eq!(
+            parse_color_expression("red!30!blue", get_color),
+            Some((77, 0, 179))
+        );
+        assert_eq!(
+            parse_color_expression("black!50", get_color),
+            Some((128, 128, 128))
+        );
+        assert_eq!(parse_color_expression("red!x!blue", get_color), None);
+        assert_eq!(parse_color_expression("red!30!nocolor", get_color), None);
+    }
+}
@@ -1,11 +1,12 @@
 use mathml_renderer::ast::Node;
 use mathml_renderer::attribute::{
-    FracAttr, MathSpacing, MathVariant, OpAttr, RowAttr, Size, Style, TextTransform,
+    FracAttr, LineType, MathSpacing, MathVariant, Notation, OpAttr, PaddedAttr, RowAttr, Size,
+    Style, TextTransform,
 };
 use mathml_renderer::symbol::{self, Rel};
 
 use crate::predefined;
-use crate::token::Token;
+use crate::token::{InfixFrac, MathClass, Token};
 
 static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     " " => Token::NonBreakingSpace,
@@ -101,6 +102,8 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "Yright" => Token::Relation(symbol::RIGHTWARDS_ARROW_TAIL),
     "Zeta" => Token::UprightLetter('Ζ'),
     "a" => Token::Letter('å'),
+    "above" => Token::Infix(InfixFrac::Above),
+    "abovewithdelims" => Token::Infix(InfixFrac::AboveWithDelims),
     "acute" => Token::OverUnder(symbol::ACUTE_ACCENT, true, None),
     "ae" => Token::Letter('æ'),
     "aleph" => Token::Letter('ℵ'),
@@ -118,6 +121,8 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "ast" => Token::Relation(symbol::ASTERISK_OPERATOR),
     "astrosun" => Token::Letter(symbol::SUN),
     "asymp" => Token::Relation(symbol::EQUIVALENT_TO),
+    "atop" => Token::Infix(InfixFrac::Atop),
+    "atopwithdelims" => Token::Infix(InfixFrac::AtopWithDelims),
     "awint" => Token::Integral(symbol::ANTICLOCKWISE_INTEGRATION),
     "backdprime" => Token::Relation(symbol::REVERSED_DOUBLE_PRIME),
     "backepsilon" => Token::Relation(symbol::SMALL_CONTAINS_AS_MEMBER),
@@ -128,6 +133,7 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "backtrprime" => Token::Relation(symbol::REVERSED_TRIPLE_PRIME),
     "bar" => Token::OverUnder(symbol::MACRON, true, Some(OpAttr::StretchyFalse)),
     "barwedge" => Token::Relation(symbol::NAND),
+    "bcancel" => Token::Enclose(Notation::DownDiagonalStrike),
     "because" => Token::Relation(symbol::BECAUSE),
     "begin" => Token::Begin,
     "beta" => Token::Letter('β'),
@@ -175,13 +181,18 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "boxbox" => Token::Relation(symbol::SQUARED_SQUARE),
     "boxbslash" => Token::Relation(symbol::SQUARED_FALLING_DIAGONAL_SLASH),
     "boxdot" => Token::Relation(symbol::SQUARED_DOT_OPERATOR),
+    "boxed" => Token::Enclose(Notation::Box),
     "boxminus" => Token::Relation(symbol::SQUARED_MINUS),
     "boxplus" => Token::Relation(symbol::SQUARED_PLUS),
     "boxslash" => Token::Relation(symbol::SQUARED_RISING_DIAGONAL_SLASH),
     "boxtimes" => Token::Relation(symbol::SQUARED_TIMES),
+    "brace" => Token::Infix(InfixFrac::Brace),
+    "brack" => Token::Infix(InfixFrac::Brack),
     "breve" => Token::OverUnder(symbol::BREVE, true, None),
     "bullet" => Token::Relation(symbol::BULLET_OPERATOR),
     "bumpeq" => Token::Relation(symbol::DIFFERENCE_BETWEEN),
+    "cancel" => Token::Enclose(Notation::UpDiagonalStrike),
+    "cancelto" => Token::CancelTo,
     "cap" => Token::Relation(symbol::INTERSECTION),
     "cdot" => Token::BinaryOp(symbol::MIDDLE_DOT),
     "cdots" => Token::CustomCmd(0, &Node::Row {
@@ -201,6 +212,7 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "check" => Token::OverUnder(symbol::CARON, true, Some(OpAttr::StretchyFalse)),
     "checkmark" => Token::Letter('✓'),
     "chi" => Token::Letter('χ'),
+    "choose" => Token::Infix(InfixFrac::Choose),
     "circ" => Token::Relation(symbol::RING_OPERATOR),
     "circeq" => Token::Relation(symbol::RING_EQUAL_TO),
     "circlearrowleft" => Token::Relation(symbol::ANTICLOCKWISE_OPEN_CIRCLE_ARROW),
@@ -211,11 +223,14 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "circledcirc" => Token::Relation(symbol::CIRCLED_RING_OPERATOR),
     "circleddash" => Token::Relation(symbol::CIRCLED_DASH),
     "cirfnint" => Token::Integral(symbol::CIRCULATION_FUNCTION),
+    "clap" => Token::Lap(PaddedAttr::CenterOverlap),
+    "cline" => Token::CLine,
     "clubsuit" => Token::Letter(symbol::BLACK_CLUB_SUIT),
     "colon" => Token::Letter(symbol::COLON.as_op().as_char()),
     "coloneq" => Token::Relation(symbol::COLON_EQUALS),
     "coloneqq" => Token::Relation(symbol::COLON_EQUALS),
     "color" => Token::Color,
+    "colorbox" => Token::ColorBox,
     "complement" => Token::Letter(symbol::COMPLEMENT),
     "cong" => Token::Relation(symbol::APPROXIMATELY_EQUAL_TO),
     "coprod" => Token::BigOp(symbol::N_ARY_COPRODUCT),
@@ -247,6 +262,8 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "ddagger" => Token::Letter(symbol::DOUBLE_DAGGER),
     "ddot" => Token::OverUnder(symbol::DIAERESIS, true, None),
     "ddots" => Token::Relation(symbol::DOWN_RIGHT_DIAGONAL_ELLIPSIS),
+    "def" => Token::Def,
+    "definecolor" => Token::DefineColor,
     "deg" => Token::Function("deg"),
     "delta" => Token::Letter('δ'),
     "det" => Token::Function("det"),
@@ -277,11 +294,13 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "empty" => Token::Function("∅︀"), // these are two unicode characters
     "emptyset" => Token::Function("∅︀"), // these are two unicode characters
     "end" => Token::End,
+    "enspace" => Token::Space("0.5"),
     "epsilon" => Token::Letter('ϵ'),
     "eqcirc" => Token::Relation(symbol::RING_IN_EQUAL_TO),
     "eqcolon" => Token::Relation(symbol::EQUALS_COLON),
     "eqqcolon" => Token::Relation(symbol::EQUALS_COLON),
     "eqdef" => Token::Relation(symbol::EQUAL_TO_BY_DEFINITION), // from "stix"
+    "eqref" => Token::EqRef,
     "eqsim" => Token::Relation(symbol::MINUS_TILDE),
     "eqslantgtr" => Token::Relation(symbol::SLANTED_EQUAL_TO_OR_GREATER_THAN),
     "eqslantless" => Token::Relation(symbol::SLANTED_EQUAL_TO_OR_LESS_THAN),
@@ -294,11 +313,14 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "exists" => Token::Relation(symbol::THERE_EXISTS),
     "exp" => Token::Function("exp"),
     "fallingdotseq" => Token::Relation(symbol::APPROXIMATELY_EQUAL_TO_OR_THE_IMAGE_OF),
+    "fbox" => Token::FBox,
     "fcmp" => Token::Relation(symbol::Z_NOTATION_SCHEMA_COMPOSITION),
+    "fcolorbox" => Token::FColorBox,
     "fint" => Token::Integral(symbol::INTEGRAL_AVERAGE_WITH_SLASH),
     "flat" => Token::Letter('♭'),
     "forall" => Token::Relation(symbol::FOR_ALL),
     "frac" => Token::Frac(None),
+    "framebox" => Token::FBox,
     "frown" => Token::Relation(symbol::FROWN),
     "gamma" => Token::Letter('γ'),
     "gcd" => Token::Function("gcd"),
@@ -319,11 +341,16 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "gtrsim" => Token::Relation(symbol::GREATER_THAN_OR_EQUIVALENT_TO),
     "hat" => Token::OverUnder(symbol::CIRCUMFLEX_ACCENT, true, Some(OpAttr::StretchyFalse)),
     "hbar" => Token::Letter('ℏ'),
+    "hdashline" => Token::HLine(LineType::Dashed),
     "heartsuit" => Token::Letter(symbol::WHITE_HEART_SUIT),
+    "hline" => Token::HLine(LineType::Solid),
     "hom" => Token::Function("hom"),
     "hookleftarrow" => Token::Relation(symbol::LEFTWARDS_ARROW_WITH_HOOK),
     "hookrightarrow" => Token::Relation(symbol::RIGHTWARDS_ARROW_WITH_HOOK),
+    "hphantom" => Token::Phantom(Some(PaddedAttr::ZeroHeightDepth)),
+    "hskip" => Token::Skip,
     "hslash" => Token::Letter('ℏ'),
+    "hspace" => Token::Hspace,
     "iff" => Token::Relation(symbol::LONG_LEFT_RIGHT_DOUBLE_ARROW),
     "iiiint" => Token::Integral(symbol::QUADRUPLE_INTEGRAL_OPERATOR),
     "iiint" => Token::Integral(symbol::TRIPLE_INTEGRAL),
@@ -332,6 +359,7 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "impliedby" => Token::Relation(symbol::LONG_LEFTWARDS_DOUBLE_ARROW),
     "implies" => Token::Relation(symbol::LONG_RIGHTWARDS_DOUBLE_ARROW),
     "in" => Token::Relation(symbol::ELEMENT_OF),
+    "indices" => Token::Indices,
     "inf" => Token::Lim("inf"),
     "infty" => Token::Letter(symbol::INFINITY),
     "int" => Token::Integral(symbol::INTEGRAL),
@@ -339,15 +367,18 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "intbar" => Token::Integral(symbol::FINITE_PARTL_INTEGRAL),
     "intclockwise" => Token::Integral(symbol::CLOCKWISE_INTEGRAL),
     "intercal" => Token::Relation(symbol::INTERCALATE),
+    "intertext" => Token::Intertext,
     "iota" => Token::Letter('ι'),
     "jmath" => Token::Letter('ȷ'),
     "jupiter" => Token::Letter(symbol::JUPITER),
     "kappa" => Token::Letter('κ'),
     "ker" => Token::Function("ker"),
+    "kern" => Token::Kern,
     "kernelcontraction" => Token::Relation(symbol::HOMOTHETIC),
     "l" => Token::Letter('ł'),
     "lBrace" => Token::Delimiter(symbol::LEFT_WHITE_CURLY_BRACKET),
     "lVert" => Token::Delimiter(symbol::DOUBLE_VERTICAL_LINE),
+    "label" => Token::Label,
     "lambda" => Token::Letter('λ'),
     "land" => Token::Relation(symbol::LOGICAL_AND),
     "langle" => Token::Delimiter(symbol::MATHEMATICAL_LEFT_ANGLE_BRACKET),
@@ -387,6 +418,7 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "limsup" => Token::Lim("lim sup"),
     "ll" => Token::Relation(symbol::MUCH_LESS_THAN),
     "llangle" => Token::Delimiter(symbol::Z_NOTATION_LEFT_BINDING_BRACKET),
+    "llap" => Token::Lap(PaddedAttr::LeftOverlap),
     "llbracket" => Token::Delimiter(symbol::MATHEMATICAL_LEFT_WHITE_SQUARE_BRACKET),
     "llcorner" => Token::Letter(symbol::BOTTOM_LEFT_CORNER),
     "lll" => Token::Relation(symbol::VERY_MUCH_LESS_THAN),
@@ -413,13 +445,24 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "mars" => Token::Letter(symbol::MALE_SIGN),
     "mathbb" => Token::Transform(MathVariant::Transform(TextTransform::DoubleStruck)),
     "mathbf" => Token::Transform(MathVariant::Transform(TextTransform::Bold)),
+    "mathbin" => Token::MathClass(MathClass::Bin),
     "mathcal" => Token::Transform(MathVariant::Transform(TextTransform::Script)),
+    "mathclap" => Token::MathLap(PaddedAttr::CenterOverlap),
+    "mathclose" => Token::MathClass(MathClass::Close),
     "mathfrak" => Token::Transform(MathVariant::Transform(TextTransform::Fraktur)),
+    "mathinner" => Token::MathClass(MathClass::Inner),
     "mathit" => Token::Transform(MathVariant::Transform(TextTransform::Italic)),
+    "mathllap" => Token::MathLap(PaddedAttr::LeftOverlap),
+    "mathop" => Token::MathClass(MathClass::Op),
+    "mathopen" => Token::MathClass(MathClass::Open),
+    "mathord" => Token::MathClass(MathClass::Ord),
+    "mathpunct" => Token::MathClass(MathClass::Punct),
+    "mathrel" => Token::MathClass(MathClass::Rel),
+    "mathrlap" => Token::MathLap(PaddedAttr::ZeroWidth),
     "mathrm" => Token::Transform(MathVariant::Normal),
     "mathscr" => Token::Transform(MathVariant::Transform(TextTransform::Script)),
     "mathsf" => Token::Transform(MathVariant::Transform(TextTransform::SansSerif)),
-    "mathstrut" => Token::HardcodedMathML(r#"<mpadded width="0" style="visibility:hidden"><mo stretchy="false">(</mo></mpadded>"#),
+    "mathstrut" => Token::CustomCmd(0, &predefined::MATHSTRUT),
     "mathtt" => Token::Transform(MathVariant::Transform(TextTransform::Monospace)),
     "max" => Token::Lim("max"),
     "measeq" => Token::Relation(symbol::MEASURED_BY), // from "stix"
@@ -429,9 +472,12 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "mid" => Token::Relation(symbol::DIVIDES),
     "middle" => Token::Middle,
     "min" => Token::Lim("min"),
+    "mkern" => Token::Kern,
     "mod" => Token::CustomCmd(1, &predefined::MOD),
     "models" => Token::Relation(symbol::TRUE),
     "mp" => Token::BinaryOp(symbol::MINUS_OR_PLUS_SIGN),
+    "mskip" => Token::Skip,
+    "mspace" => Token::Hspace,
     "mu" => Token::Letter('μ'),
     "multimap" => Token::Relation(symbol::MULTIMAP),
     "nLeftarrow" => Token::Relation(symbol::LEFTWARDS_DOUBLE_ARROW_WITH_STROKE),
@@ -445,6 +491,7 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "neptune" => Token::Letter(symbol::NEPTUNE),
     "neq" => Token::Relation(symbol::NOT_EQUAL_TO),
     "nequiv" => Token::Relation(symbol::NOT_IDENTICAL_TO),
+    "newcommand" => Token::NewCommand,
     "nexists" => Token::Relation(symbol::THERE_DOES_NOT_EXIST),
     "ng" => Token::Letter('ŋ'),
     "ngeq" => Token::Relation(symbol::NEITHER_GREATER_THAN_NOR_EQUAL_TO),
@@ -459,7 +506,10 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "nlessgt" => Token::Relation(symbol::NEITHER_LESS_THAN_NOR_GREATER_THAN),
     "nlesssim" => Token::Relation(symbol::NEITHER_LESS_THAN_NOR_EQUIVALENT_TO),
     "nmid" => Token::Relation(symbol::DOES_NOT_DIVIDE),
+    "nolimits" => Token::NoLimits,
+    "nonumber" => Token::NoTag,
     "not" => Token::Not,
+    "notag" => Token::NoTag,
     "notin" => Token::Relation(symbol::NOT_AN_ELEMENT_OF),
     "nparallel" => Token::Relation(symbol::NOT_PARALLEL_TO),
     "nprec" => Token::Relation(symbol::DOES_NOT_PRECEDE),
@@ -493,6 +543,7 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "oplus" => Token::Relation(symbol::CIRCLED_PLUS),
     "oslash" => Token::Relation(symbol::CIRCLED_DIVISION_SLASH),
     "otimes" => Token::Relation(symbol::CIRCLED_TIMES),
+    "over" => Token::Infix(InfixFrac::Over),
     "overbrace" => Token::OverUnderBrace(symbol::TOP_CURLY_BRACKET, true),
     "overbracket" => Token::OverUnderBrace(symbol::TOP_SQUARE_BRACKET, true),
     "overleftarrow" => Token::OverUnder(symbol::LEFTWARDS_ARROW.as_op(), true, None),
@@ -500,9 +551,11 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "overparen" => Token::OverUnderBrace(symbol::TOP_PARENTHESIS, true),
     "overrightarrow" => Token::OverUnder(symbol::RIGHTWARDS_ARROW.as_op(), true, None),
     "overset" => Token::Overset,
+    "overwithdelims" => Token::Infix(InfixFrac::OverWithDelims),
     "parallel" => Token::Relation(symbol::PARALLEL_TO),
     "partial" => Token::Letter(symbol::PARTIAL_DIFFERENTIAL),
     "perp" => Token::Relation(symbol::PERPENDICULAR),
+    "phantom" => Token::Phantom(None),
     "phi" => Token::Letter('ϕ'),
     "pi" => Token::Letter('π'),
     "pitchfork" => Token::Relation(symbol::PITCHFORK),
@@ -517,6 +570,7 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "precneqq" => Token::Relation(symbol::PRECEDES_ABOVE_NOT_EQUAL_TO),
     "precnsim" => Token::Relation(symbol::PRECEDES_BUT_NOT_EQUIVALENT_TO),
     "precsim" => Token::Relation(symbol::PRECEDES_OR_EQUIVALENT_TO),
+    "prescript" => Token::Prescript,
     "prime" => Token::Relation(symbol::PRIME),
     "prod" => Token::BigOp(symbol::N_ARY_PRODUCT),
     "propto" => Token::Relation(symbol::PROPORTIONAL_TO),
@@ -531,6 +585,7 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "rbrace" => Token::Delimiter(symbol::RIGHT_CURLY_BRACKET),
     "rbrack" => Token::Delimiter(symbol::RIGHT_SQUARE_BRACKET),
     "rceil" => Token::Delimiter(symbol::RIGHT_CEILING),
+    "renewcommand" => Token::RenewCommand,
     "rfloor" => Token::Delimiter(symbol::RIGHT_FLOOR),
     "rgroup" => Token::Delimiter(symbol::MATHEMATICAL_RIGHT_FLATTENED_PARENTHESIS),
     "rhd" => Token::Relation(symbol::CONTAINS_AS_NORMAL_SUBGROUP),
@@ -546,11 +601,13 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "rightsquigarrow" => Token::Relation(symbol::RIGHTWARDS_SQUIGGLE_ARROW),
     "rightthreetimes" => Token::Relation(symbol::RIGHT_SEMIDIRECT_PRODUCT),
     "risingdotseq" => Token::Relation(symbol::IMAGE_OF_OR_APPROXIMATELY_EQUAL_TO),
+    "rlap" => Token::Lap(PaddedAttr::ZeroWidth),
     "rq" => Token::Letter('’'),
     "rrangle" => Token::Delimiter(symbol::Z_NOTATION_RIGHT_BINDING_BRACKET),
     "rrbracket" => Token::Delimiter(symbol::MATHEMATICAL_RIGHT_WHITE_SQUARE_BRACKET),
     "rrparenthesis" => Token::Delimiter(symbol::Z_NOTATION_RIGHT_IMAGE_BRACKET),
     "rtimes" => Token::Relation(symbol::RIGHT_NORMAL_FACTOR_SEMIDIRECT_PRODUCT),
+    "rule" => Token::Rule,
     "rupee" => Token::Letter('₹'),
     "rvert" => Token::Delimiter(symbol::VERTICAL_LINE),
     "saturn" => Token::Letter(symbol::SATURN),
@@ -561,6 +618,8 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "setminus" => Token::Relation(symbol::SET_MINUS),
     "sgn" => Token::Function("sgn"),
     "sharp" => Token::Letter('♯'),
+    "shortintertext" => Token::Intertext,
+    "sideset" => Token::Sideset,
     "sigma" => Token::Letter('σ'),
     "sim" => Token::Relation(symbol::TILDE_OPERATOR),
     "simeq" => Token::Relation(symbol::ASYMPTOTICALLY_EQUAL_TO),
@@ -568,7 +627,9 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "sinh" => Token::Function("sinh"),
     "slashed" => Token::Slashed,
     "smallsetminus" => Token::Relation(symbol::SMALL_REVERSE_SOLIDUS),
+    "smash" => Token::Smash,
     "smile" => Token::Relation(symbol::SMILE),
+    "sout" => Token::Enclose(Notation::HorizontalStrike),
     "spadesuit" => Token::Letter(symbol::BLACK_SPADE_SUIT),
     "sphericalangle" => Token::Letter(symbol::SPHERICAL_ANGLE),
     "sqcap" => Token::Relation(symbol::SQUARE_CAP),
@@ -587,6 +648,7 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "subseteq" => Token::Relation(symbol::SUBSET_OF_OR_EQUAL_TO),
     "subsetneq" => Token::Relation(symbol::SUBSET_OF_WITH_NOT_EQUAL_TO),
     "subsetneqq" => Token::Relation(symbol::SUBSET_OF_ABOVE_NOT_EQUAL_TO),
+    "substack" => Token::Substack,
     "succ" => Token::Relation(symbol::SUCCEEDS),
     "succapprox" => Token::Relation(symbol::SUCCEEDS_ABOVE_ALMOST_EQUAL_TO),
     "succcurlyeq" => Token::Relation(symbol::SUCCEEDS_OR_EQUAL_TO),
@@ -605,12 +667,15 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "supsetneqq" => Token::Relation(symbol::SUPERSET_OF_ABOVE_NOT_EQUAL_TO),
     "swarrow" => Token::Relation(symbol::SOUTH_WEST_ARROW),
     "symbf" => Token::Transform(MathVariant::Transform(TextTransform::BoldItalic)),
+    "tag" => Token::Tag,
     "tan" => Token::Function("tan"),
     "tanh" => Token::Function("tanh"),
     "tau" => Token::Letter('τ'),
     "tbinom" => Token::Binom(Some(FracAttr::DisplayStyleFalse)),
+    "tensor" => Token::Tensor,
     "text" => Token::Text(None),
     "textbf" => Token::Text(Some(TextTransform::Bold)),
+    "textcolor" => Token::TextColor,
     "textit" => Token::Text(Some(TextTransform::Italic)),
     "textstyle" => Token::Style(Style::TextStyle),
     "texttt" => Token::Text(Some(TextTransform::Monospace)),
@@ -619,6 +684,7 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "th" => Token::Letter('þ'),
     "therefore" => Token::Relation(symbol::THEREFORE),
     "theta" => Token::Letter('θ'),
+    "thinspace" => Token::Space("0.1667"),
     "tilde" => Token::OverUnder(symbol::TILDE, true, Some(OpAttr::StretchyFalse)),
     "times" => Token::BinaryOp(symbol::MULTIPLICATION_SIGN),
     "to" => Token::Relation(symbol::RIGHTWARDS_ARROW),
@@ -676,15 +742,28 @@ static COMMANDS: phf::Map<&'static str, Token> = phf::phf_map! {
     "veeeq" => Token::Relation(symbol::EQUIANGULAR_TO), // from "stix"
     "venus" => Token::Letter(symbol::FEMALE_SIGN),
     "vert" => Token::Delimiter(symbol::VERTICAL_LINE),
+    "vphantom" => Token::Phantom(Some(PaddedAttr::ZeroWidth)),
     "wedge" => Token::Relation(symbol::LOGICAL_AND),
     "wedgeq" => Token::Relation(symbol::ESTIMATES), // from "stix"
     "widehat" => Token::OverUnder(symbol::CIRCUMFLEX_ACCENT, true, None),
     "widetilde" => Token::OverUnder(symbol::TILDE, true, None),
     "wp" => Token::Function("℘"),
     "wr" => Token::Relation(symbol::WREATH_PRODUCT),
+    "xLeftarrow" => Token::ExtensibleArrow(symbol::LEFTWARDS_DOUBLE_ARROW.as_op()),
+    "xLeftrightarrow" => Token::ExtensibleArrow(symbol::LEFT_RIGHT_DOUBLE_ARROW.as_op()),
+    "xRightarrow" => Token::ExtensibleArrow(symbol::RIGHTWARDS_DOUBLE_ARROW.as_op()),
+    "xcancel" => Token::Enclose(Notation::CrossedOut),
+    "xhookleftarrow" => Token::ExtensibleArrow(symbol::LEFTWARDS_ARROW_WITH_HOOK.as_op()),
+    "xhookrightarrow" => Token::ExtensibleArrow(symbol::RIGHTWARDS_ARROW_WITH_HOOK.as_op()),
     "xi" => Token::Letter('ξ'),
-    "xleftarrow" => Token::CustomCmd(1, &predefined::XLEFTARROW),
-    "xrightarrow" => Token::CustomCmd(1, &predefined::XRIGHTARROW),
+    "xleftarrow" => Token::ExtensibleArrow(symbol::LEFTWARDS_ARROW.as_op()),
+    "xleftrightarrow" => Token::ExtensibleArrow(symbol::LEFT_RIGHT_ARROW.as_op()),
+    "xlongequal" => Token::ExtensibleArrow(symbol::EQUALS_SIGN.as_op()),
+    "xmapsto" => Token::ExtensibleArrow(symbol::RIGHTWARDS_ARROW_FROM_BAR.as_op()),
+    "xrightarrow" => Token::ExtensibleArrow(symbol::RIGHTWARDS_ARROW.as_op()),
+    "xrightleftharpoons" => Token::ExtensibleArrow(symbol::RIGHTWARDS_HARPOON_OVER_LEFTWARDS_HARPOON.as_op()),
+    "xtofrom" => Token::ExtensibleArrow(symbol::RIGHTWARDS_ARROW_OVER_LEFTWARDS_ARROW.as_op()),
+    "xtwoheadrightarrow" => Token::ExtensibleArrow(symbol::RIGHTWARDS_TWO_HEADED_ARROW.as_op()),
     "zeta" => Token::Letter('ζ'),
     "{" => Token::Delimiter(symbol::LEFT_CURLY_BRACKET),
     "|" => Token::Delimiter(symbol::DOUBLE_VERTICAL_LINE),
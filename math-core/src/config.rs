@@ -0,0 +1,81 @@
+use crate::Display;
+
+/// Options for the conversion from LaTeX to MathML.
+///
+/// ```rust
+/// use math_core::{Display, MathCoreConfig};
+///
+/// let config = MathCoreConfig {
+///     display: Display::Block,
+///     pretty: true,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MathCoreConfig<'a> {
+    /// Whether the equation is rendered inline or as a block.
+    pub display: Display,
+    /// Whether to put every element on its own line.
+    pub pretty: bool,
+    /// The number of spaces per level of indentation, if `pretty` is set.
+    pub indent_width: usize,
+    /// Whether to add the MathML namespace to the `<math>` element.
+    pub xmlns: bool,
+    /// Whether to wrap the content in `<semantics>`, with the LaTeX source as an
+    /// `application/x-tex` annotation.
+    pub annotation: bool,
+    /// What to do if the input could not be converted.
+    pub error_policy: ErrorPolicy,
+    /// The number of equations which come before the input, e.g. earlier in the same
+    /// document. Numbered environments like `align` continue counting from here.
+    pub equation_counter: usize,
+    /// Commands to define before the conversion, as pairs of name and body,
+    /// e.g., `("\\R", "\\mathbb{R}")`.
+    ///
+    /// The number of arguments is the highest parameter (like `#2`) in the body.
+    pub macros: Vec<(&'a str, &'a str)>,
+}
+
+impl MathCoreConfig<'_> {
+    /// Copy all the options except for the macros.
+    pub(crate) fn without_macros(&self) -> MathCoreConfig<'static> {
+        MathCoreConfig {
+            display: self.display,
+            pretty: self.pretty,
+            indent_width: self.indent_width,
+            xmlns: self.xmlns,
+            annotation: self.annotation,
+            error_policy: self.error_policy,
+            equation_counter: self.equation_counter,
+            macros: Vec::new(),
+        }
+    }
+}
+
+impl Default for MathCoreConfig<'_> {
+    fn default() -> Self {
+        MathCoreConfig {
+            display: Display::Inline,
+            pretty: false,
+            indent_width: 4,
+            xmlns: false,
+            annotation: false,
+            error_policy: ErrorPolicy::default(),
+            equation_counter: 0,
+            macros: Vec::new(),
+        }
+    }
+}
+
+/// What to do if the input could not be converted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Return the error.
+    #[default]
+    Return,
+    /// Render the invalid parts of the input in `<merror>` elements instead.
+    ///
+    /// If the parser cannot recover from the error, the whole input is rendered as
+    /// an error.
+    Render,
+}
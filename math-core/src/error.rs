@@ -1,4 +1,5 @@
 use std::fmt;
+use std::ops::Range;
 
 use strum_macros::AsRefStr;
 
@@ -6,8 +7,10 @@ use strum_macros::AsRefStr;
 
 use crate::token::Token;
 
+/// An error which occurred during the conversion, together with the span of the
+/// input (in bytes) where it occurred.
 #[derive(Debug)]
-pub struct LatexError<'source>(pub usize, pub LatexErrKind<'source>);
+pub struct LatexError<'source>(pub Range<usize>, pub LatexErrKind<'source>);
 
 #[derive(Debug)]
 pub enum LatexErrKind<'source> {
@@ -25,7 +28,14 @@ pub enum LatexErrKind<'source> {
     UnparsableEnvName,
     UnknownEnvironment(&'source str),
     UnknownCommand(&'source str),
+    CommandAlreadyDefined(&'source str),
     UnknownColor(&'source str),
+    UnknownColorModel(&'source str),
+    InvalidColorSpec(&'source str),
+    UnknownColumnSpec(&'source str),
+    UndefinedLabel(&'source str),
+    DuplicateLabel(&'source str),
+    UnnumberedLabel(&'source str),
     MismatchedEnvironment {
         expected: &'source str,
         got: &'source str,
@@ -36,6 +46,10 @@ pub enum LatexErrKind<'source> {
     },
     ExpectedText(&'static str),
     ExpectedLength(&'source str),
+    ExpectedCmdName,
+    AmbiguousInfix,
+    TooManyColumns(usize),
+    InvalidColumnRange(u8, u8),
 }
 
 #[derive(Debug, AsRefStr)]
@@ -47,6 +61,14 @@ pub enum Place {
     BeforeSomeOps,
     #[strum(serialize = r"after an identifier or operator")]
     AfterOpOrIdent,
+    #[strum(serialize = r"in the body of a command definition")]
+    InCmdDefinition,
+    #[strum(serialize = r"outside of command arguments")]
+    OutsideOfArgument,
+    #[strum(serialize = r"in a CD environment")]
+    InCDEnvironment,
+    #[strum(serialize = r"in an array")]
+    InArray,
 }
 
 impl LatexErrKind<'_> {
@@ -82,7 +104,26 @@ impl LatexErrKind<'_> {
                 "Unknown environment \"".to_string() + environment + "\"."
             }
             LatexErrKind::UnknownCommand(cmd) => "Unknown command \"\\".to_string() + cmd + "\".",
+            LatexErrKind::CommandAlreadyDefined(cmd) => {
+                "Command \"\\".to_string() + cmd + "\" is already defined."
+            }
             LatexErrKind::UnknownColor(color) => "Unknown color \"".to_string() + color + "\".",
+            LatexErrKind::UnknownColorModel(model) => {
+                "Unknown color model \"".to_string() + model + "\"."
+            }
+            LatexErrKind::InvalidColorSpec(spec) => {
+                "Invalid color specification \"".to_string() + spec + "\"."
+            }
+            LatexErrKind::UnknownColumnSpec(spec) => {
+                "Unknown column specification \"".to_string() + spec + "\"."
+            }
+            LatexErrKind::UndefinedLabel(label) => "Undefined label \"".to_string() + label + "\".",
+            LatexErrKind::DuplicateLabel(label) => {
+                "Label \"".to_string() + label + "\" is defined more than once."
+            }
+            LatexErrKind::UnnumberedLabel(label) => {
+                "Label \"".to_string() + label + "\" is not on a numbered equation."
+            }
             LatexErrKind::MismatchedEnvironment { expected, got } => {
                 "Expected \"\\end{".to_string() + expected + "}\", but got \"\\end{" + got + "}\"."
             }
@@ -97,13 +138,81 @@ impl LatexErrKind<'_> {
             LatexErrKind::ExpectedLength(got) => {
                 "Expected length with units, got \"".to_string() + got + "\"."
             }
+            LatexErrKind::ExpectedCmdName => "Expected the name of a command.".to_string(),
+            LatexErrKind::AmbiguousInfix => {
+                "Ambiguous fraction: a group can only contain one of \\over, \\atop, \\above, ..."
+                    .to_string()
+            }
+            LatexErrKind::TooManyColumns(num_columns) => {
+                format!("Extra column separator: the array has only {num_columns} columns.")
+            }
+            LatexErrKind::InvalidColumnRange(first, last) => {
+                format!("Invalid range of columns \"{first}-{last}\".")
+            }
         }
     }
 }
 
+impl LatexError<'_> {
+    /// Returns the line and column (both starting at 1) of the start of the error.
+    ///
+    /// The column is counted in characters, not bytes.
+    /// `source` has to be the input which produced the error.
+    pub fn line_and_column(&self, source: &str) -> (usize, usize) {
+        line_and_column(source, self.0.start)
+    }
+
+    /// Renders the error as a diagnostic which shows the offending line of `source`,
+    /// with the span of the error underlined by carets:
+    ///
+    /// ```text
+    /// error: Unknown command "\asdf".
+    ///  --> 1:5
+    ///   |
+    /// 1 | a+b\asdf
+    ///   |    ^^^^^
+    /// ```
+    ///
+    /// `source` has to be the input which produced the error.
+    pub fn to_diagnostic(&self, source: &str) -> String {
+        let (line, column) = self.line_and_column(source);
+        let start = self.0.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line_text = &source[line_start..line_end];
+        // Only underline the part of the span which is on the first line,
+        // but always show at least one caret.
+        let end = self.0.end.clamp(start, line_end);
+        let carets = source[start..end].chars().count().max(1);
+
+        let line_number = line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        format!(
+            "error: {}\n{gutter}--> {line}:{column}\n{gutter} |\n{line_number} | {line_text}\n{gutter} | {}{}",
+            self.1.string(),
+            " ".repeat(column - 1),
+            "^".repeat(carets),
+        )
+    }
+}
+
+/// Returns the line and column (both starting at 1) of the byte offset `loc` in `source`.
+///
+/// The column is counted in characters, not bytes.
+pub fn line_and_column(source: &str, loc: usize) -> (usize, usize) {
+    let loc = loc.min(source.len());
+    let before = source.get(..loc).unwrap_or(source);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.matches('\n').count() + 1;
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
+
 impl fmt::Display for LatexError<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}: {}", self.0, self.1.string())
+        write!(f, "{}: {}", self.0.start, self.1.string())
     }
 }
 
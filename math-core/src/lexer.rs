@@ -1,24 +1,34 @@
 use std::mem;
 use std::num::NonZero;
+use std::ops::Range;
 use std::str::CharIndices;
 
 use mathml_renderer::symbol;
 
 use crate::commands::get_command;
 use crate::error::GetUnwrap;
-use crate::token::{Digit, TokLoc, Token};
+use crate::token::{CDArrow, Digit, TokLoc, Token};
 
 /// Lexer
 #[derive(Debug, Clone)]
-pub(crate) struct Lexer<'source> {
+pub(crate) struct Lexer<'cmds, 'source> {
     input: CharIndices<'source>,
     peek: (usize, char),
     input_string: &'source str,
     pub input_length: usize,
     pub text_mode: bool,
+    /// Whether we are in a `CD` environment, where `@` starts an arrow.
+    pub cd_mode: bool,
+    /// Names of the commands defined by the user, which take precedence over the
+    /// predefined commands.
+    pub user_cmds: Vec<&'cmds str>,
+    /// While reading the labels of an arrow like `@>a>b>` in a commutative diagram:
+    /// the character which ends a label, the number of labels which are left, and
+    /// the current depth of braces.
+    cd_label: Option<(char, u8, usize)>,
 }
 
-impl<'source> Lexer<'source> {
+impl<'source> Lexer<'_, 'source> {
     /// Receive the input source code and generate a LEXER instance.
     pub(crate) fn new(input: &'source str) -> Self {
         let mut lexer = Lexer {
@@ -27,6 +37,9 @@ impl<'source> Lexer<'source> {
             input_string: input,
             input_length: input.len(),
             text_mode: false,
+            cd_mode: false,
+            user_cmds: Vec::new(),
+            cd_label: None,
         };
         lexer.read_char(); // Initialize `peek`.
         lexer
@@ -96,6 +109,132 @@ impl<'source> Lexer<'source> {
         }
     }
 
+    /// Read a color or a color model, like `red!30!blue` or `0.1, 0.2, 0.3`, until the
+    /// given closing character.
+    ///
+    /// If anything other than ASCII alphanumeric characters, spaces and `.`, `,`, `!`
+    /// or `-` comes before the closing character, the text up to and including the
+    /// offending character is returned as the error.
+    pub(crate) fn read_color_spec(&mut self, closing: char) -> Result<&'source str, &'source str> {
+        let start = self.peek.0;
+
+        while self.peek.1.is_ascii_alphanumeric()
+            || matches!(self.peek.1, ' ' | '.' | ',' | '!' | '-')
+        {
+            self.read_char();
+        }
+
+        let (end, next) = self.read_char();
+        if next == closing {
+            // SAFETY: we got `start` and `end` from `CharIndices`, so they are valid bounds.
+            Ok(self.input_string.get_unwrap(start..end))
+        } else {
+            Err(self.input_string.get_unwrap(start..self.peek.0))
+        }
+    }
+
+    /// Read the name of a label, like `eq:energy`, until the next `}`.
+    ///
+    /// Returns `None` if the name is empty or contains anything other than ASCII
+    /// alphanumeric characters and `:`, `-`, `_`, `.` or `/`.
+    pub(crate) fn read_label_name(&mut self) -> Option<&'source str> {
+        self.skip_whitespace();
+        let start = self.peek.0;
+
+        while self.peek.1.is_ascii_alphanumeric()
+            || matches!(self.peek.1, ':' | '-' | '_' | '.' | '/')
+        {
+            self.read_char();
+        }
+        let end = self.peek.0;
+
+        self.skip_whitespace();
+        if start < end && self.read_char().1 == '}' {
+            // SAFETY: we got `start` and `end` from `CharIndices`, so they are valid bounds.
+            Some(self.input_string.get_unwrap(start..end))
+        } else {
+            None
+        }
+    }
+
+    /// Read the name of a command which is being defined, up to the next `}`.
+    ///
+    /// Returns `None` if the group contains anything other than a single command.
+    pub(crate) fn read_cmd_name_group(&mut self) -> Option<&'source str> {
+        self.skip_whitespace();
+        if self.read_char().1 != '\\' {
+            return None;
+        }
+        let name = self.read_command();
+        self.skip_whitespace();
+        if self.read_char().1 == '}' {
+            Some(name)
+        } else {
+            None
+        }
+    }
+
+    /// Get the name of the command which starts at `loc` and which was the last
+    /// thing that was read by the lexer.
+    ///
+    /// Returns `None` if there is no command at `loc`.
+    pub(crate) fn cmd_name_at(&self, loc: usize) -> Option<&'source str> {
+        self.input_string
+            .get(loc..self.peek.0)
+            .and_then(|s| s.strip_prefix('\\'))
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Get the span of the token which starts at `loc`.
+    ///
+    /// For `\\begin{...}` and `\\end{...}`, the span includes the environment name.
+    pub(crate) fn token_span(&self, loc: usize) -> Range<usize> {
+        let rest = self.input_string.get(loc..).unwrap_or_default();
+        let first_char_len = |s: &str| s.chars().next().map_or(0, char::len_utf8);
+        let len = if let Some(cmd) = rest.strip_prefix('\\') {
+            let name_len = match cmd.bytes().take_while(u8::is_ascii_alphabetic).count() {
+                0 => first_char_len(cmd),
+                n => n,
+            };
+            let mut len = 1 + name_len;
+            if matches!(&cmd[..name_len], "begin" | "end") {
+                // Same characters as in `read_length_or_env_name`.
+                if let Some(env) = rest[len..].strip_prefix('{') {
+                    let env_len = env
+                        .bytes()
+                        .take_while(|b| {
+                            b.is_ascii_alphanumeric() || matches!(b, b'*' | b'.' | b' ')
+                        })
+                        .count();
+                    if env[env_len..].starts_with('}') {
+                        len += env_len + 2;
+                    }
+                }
+            }
+            len
+        } else {
+            first_char_len(rest)
+        };
+        loc..loc + len
+    }
+
+    /// The source text of the token at the given location, as covered by `token_span`.
+    pub(crate) fn token_text(&self, loc: usize) -> &'source str {
+        self.input_string
+            .get(self.token_span(loc))
+            .unwrap_or_default()
+    }
+
+    /// The source text within the given span.
+    pub(crate) fn span_text(&self, span: Range<usize>) -> &'source str {
+        self.input_string.get(span).unwrap_or_default()
+    }
+
+    /// The source text from the given location to the end of the input.
+    pub(crate) fn text_from(&self, loc: usize) -> &'source str {
+        self.input_string.get(loc..).unwrap_or_default()
+    }
+
     /// Check if the next character is a digit.
     pub(crate) fn is_next_digit(&mut self) -> bool {
         if !self.text_mode {
@@ -123,6 +262,20 @@ impl<'source> Lexer<'source> {
             }
             return self.next_token();
         }
+        if let Some((end, remaining, depth)) = &mut self.cd_label {
+            match ch {
+                '{' => *depth += 1,
+                '}' => *depth = depth.saturating_sub(1),
+                c if c == *end && *depth == 0 => {
+                    *remaining -= 1;
+                    if *remaining == 0 {
+                        self.cd_label = None;
+                    }
+                    return TokLoc(loc, Token::CDLabelEnd);
+                }
+                _ => {}
+            }
+        }
         let tok = match ch {
             '\u{0}' => Token::EOF,
             ' ' => Token::Letter('\u{A0}'),
@@ -149,8 +302,37 @@ impl<'source> Lexer<'source> {
             '|' => Token::Delimiter(symbol::VERTICAL_LINE),
             '}' => Token::GroupEnd,
             '~' => Token::NonBreakingSpace,
+            '@' if self.cd_mode
+                && !self.text_mode
+                && matches!(self.peek.1, '>' | '<' | 'V' | 'A' | '=' | '|' | '.') =>
+            {
+                let (_, c) = self.read_char();
+                let arrow = match c {
+                    '>' => CDArrow::Right,
+                    '<' => CDArrow::Left,
+                    'V' => CDArrow::Down,
+                    'A' => CDArrow::Up,
+                    '=' => CDArrow::Equals,
+                    '|' => CDArrow::Parallel,
+                    _ => CDArrow::Empty,
+                };
+                if matches!(c, '>' | '<' | 'V' | 'A') {
+                    // The arrow is followed by two labels, like in `@>above>below>`.
+                    self.cd_label = Some((c, 2, 0));
+                }
+                Token::CDArrow(arrow)
+            }
+            '#' if matches!(self.peek.1, '1'..='9') => {
+                let (_, digit) = self.read_char();
+                Token::CustomCmdArg(digit as u8 - b'0')
+            }
             '\\' => {
-                let cmd = get_command(self.read_command());
+                let name = self.read_command();
+                let cmd = if self.user_cmds.contains(&name) {
+                    Token::UserCmd(name)
+                } else {
+                    get_command(name)
+                };
                 if self.text_mode {
                     // After a command, all whitespace is skipped, even in text mode.
                     self.skip_whitespace();
@@ -183,6 +365,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::write_with_newline)]
     fn lexer_test() {
         let problems = [
             ("simple_number", r"3", false),
@@ -197,11 +380,14 @@ mod tests {
             ("space_and_number", r"\ 1", false),
             ("space_in_text", r"  x   y z", true),
             ("comment", "ab%hello\ncd", false),
+            ("cd_arrows", r"A @>f>> B @VV{g>h}V @= @.", false),
         ];
 
         for (name, problem, text_mode) in problems.into_iter() {
             let mut lexer = Lexer::new(problem);
             lexer.text_mode = text_mode;
+            // Arrows are only recognized in a `CD` environment.
+            lexer.cd_mode = name == "cd_arrows";
             // Call `lexer.next_token(false)` until we get `Token::EOF`.
             let mut tokens = String::new();
             if text_mode {
@@ -21,12 +21,14 @@
 //! - Matrix, e.g. `\begin{matrix}`, `\begin{pmatrix}`, `\begin{bmatrix}`, `\begin{vmatrix}`.
 //! - Multi-line equation `\begin{align}` (experimental).
 //! - Feynman slash notation: `\slashed{\partial}`.
+//! - User-defined commands via `\newcommand`, `\renewcommand` and `\def`, e.g.,
+//!   `\newcommand{\abs}[1]{\left|#1\right|}`. The body of a definition is parsed right away,
+//!   so it can only use commands which have been defined before.
 //!
 //! ## Unsupported LaTeX commands
 //!
 //! - New line `\\`, except for ones in a matrix or align environment.
 //! - Alignment `&`, except for ones in a matrix or align environment.
-//! - Complicated sub/superscripts (`<mmultiscripts>`).
 //!
 //!
 //! # Usage
@@ -35,30 +37,50 @@
 //! [`replace`](./fn.replace.html).
 //!
 //! ```rust
-//! use math_core::{latex_to_mathml, Display};
+//! use math_core::{latex_to_mathml, Display, MathCoreConfig};
 //!
 //! let latex = r#"\erf ( x ) = \frac{ 2 }{ \sqrt{ \pi } } \int_0^x e^{- t^2} \, dt"#;
-//! let mathml = latex_to_mathml(latex, Display::Block, true).unwrap();
+//! let config = MathCoreConfig {
+//!     display: Display::Block,
+//!     pretty: true,
+//!     ..Default::default()
+//! };
+//! let mathml = latex_to_mathml(latex, &config).unwrap();
 //! println!("{}", mathml);
 //! ```
 //!
+//! To define commands once and use them in many equations, use [`LatexToMathML`].
+//! To get the syntax tree instead of MathML, use [`parse`]. The tree can be traversed
+//! and rewritten with the traits in [`visit`], and turned into other output formats
+//! with a [`Renderer`] (see [`LatexToMathML::render`]). With the `serde` feature, the nodes implement
+//! `serde::Serialize`.
+//!
 //! For more examples and list of supported LaTeX commands, please check
 //! [`examples/equations.rs`](https://github.com/osanshouo/latex2mathml/blob/master/examples/equations.rs)
 //! and [`examples/document.rs`](https://github.com/osanshouo/latex2mathml/blob/master/examples/document.rs).
 //!
-use mathml_renderer::arena::Arena;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Mutex, MutexGuard};
 
 mod color_defs;
 pub(crate) mod commands;
+mod config;
 mod error;
 pub(crate) mod lexer;
 pub(crate) mod parse;
 pub(crate) mod predefined;
+mod spacing;
 pub(crate) mod specifications;
 pub mod token;
 
-pub use error::{LatexErrKind, LatexError};
-pub use mathml_renderer::ast::MathMLEmitter;
+pub use config::{ErrorPolicy, MathCoreConfig};
+pub use error::{LatexErrKind, LatexError, line_and_column};
+pub use mathml_renderer::arena::Arena;
+pub use mathml_renderer::ast::{MathMLEmitter, Node};
+pub use mathml_renderer::render::Renderer;
+pub use mathml_renderer::text::TextRenderer;
+pub use mathml_renderer::visit;
 
 /// display
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -67,10 +89,23 @@ pub enum Display {
     Inline,
 }
 
+/// Parse the input into nodes.
+///
+/// If `errors` is given, the parser recovers from errors where possible and collects
+/// them there.
+///
+/// Numbered equations continue counting after `equation_counter`, which is updated
+/// to the number of the last one. `\eqref` can refer to the entries of `labels`,
+/// and the labels of numbered equations are added to it. Both are only updated if
+/// the input could be parsed, so that they only refer to equations which are shown.
 fn get_nodes<'arena, 'source>(
     latex: &'source str,
     arena: &'arena Arena,
-) -> Result<&'arena [&'arena mathml_renderer::ast::Node<'arena>], error::LatexError<'source>>
+    definitions: parse::Definitions<'arena>,
+    errors: Option<&mut Vec<error::LatexError<'source>>>,
+    equation_counter: &mut usize,
+    labels: &mut HashMap<String, String>,
+) -> Result<&'arena [&'arena Node<'arena>], error::LatexError<'source>>
 where
     'source: 'arena, // 'source outlives 'arena
 {
@@ -78,68 +113,531 @@ where
     // the string buffer.
     // let buffer = Buffer::new(latex.len());
 
-    let l = lexer::Lexer::new(latex);
-    let mut p = parse::Parser::new(l, arena);
-    let nodes = p.parse()?;
-    Ok(nodes)
+    let mut p = parse::Parser::with_definitions(lexer::Lexer::new(latex), arena, definitions);
+    p.set_equation_counter(*equation_counter);
+    p.set_labels(labels.clone());
+    let nodes = if let Some(errors) = errors {
+        p.enable_recovery();
+        let nodes = p.parse();
+        errors.append(&mut p.take_errors());
+        nodes
+    } else {
+        p.parse()
+    };
+    if nodes.is_ok() {
+        *equation_counter = p.equation_counter();
+        *labels = p.take_labels();
+    }
+    nodes
+}
+
+/// Parse LaTeX text into a tree of nodes, without converting it to MathML.
+///
+/// The nodes are allocated in `arena` and live as long as it does.
+/// With the `serde` feature, the nodes implement `serde::Serialize`, so they can,
+/// for example, be serialized to JSON.
+///
+/// ```rust
+/// use math_core::{parse, Arena, Node};
+///
+/// let arena = Arena::new();
+/// let nodes = parse(r#"x^2"#, &arena).unwrap();
+/// assert!(matches!(nodes, [Node::Superscript { .. }]));
+/// ```
+pub fn parse<'arena, 'source: 'arena>(
+    latex: &'source str,
+    arena: &'arena Arena,
+) -> Result<&'arena [&'arena Node<'arena>], error::LatexError<'source>> {
+    get_nodes(
+        latex,
+        arena,
+        parse::Definitions::default(),
+        None,
+        &mut 0,
+        &mut HashMap::new(),
+    )
 }
 
 /// Convert LaTeX text to MathML.
 ///
-/// The second argument specifies whether it is inline-equation or block-equation.
+/// The second argument specifies the options of the conversion, e.g. whether it is
+/// inline-equation or block-equation.
 ///
 /// ```rust
-/// use math_core::{latex_to_mathml, Display};
+/// use math_core::{latex_to_mathml, Display, MathCoreConfig};
 ///
 /// let latex = r#"(n + 1)! = \Gamma ( n + 1 )"#;
-/// let mathml = latex_to_mathml(latex, Display::Inline, true).unwrap();
+/// let mathml = latex_to_mathml(latex, &MathCoreConfig::default()).unwrap();
 /// println!("{}", mathml);
 ///
 /// let latex = r#"x = \frac{ - b \pm \sqrt{ b^2 - 4 a c } }{ 2 a }"#;
-/// let mathml = latex_to_mathml(latex, Display::Block, true).unwrap();
+/// let config = MathCoreConfig {
+///     display: Display::Block,
+///     pretty: true,
+///     ..Default::default()
+/// };
+/// let mathml = latex_to_mathml(latex, &config).unwrap();
 /// println!("{}", mathml);
 /// ```
 ///
-pub fn latex_to_mathml<'source, 'emitter>(
+pub fn latex_to_mathml<'source>(
     latex: &'source str,
-    display: Display,
-    pretty: bool,
-) -> Result<String, error::LatexError<'source>>
-where
-    'source: 'emitter,
-{
-    let arena = Arena::new();
-    let nodes = get_nodes(latex, &arena)?;
+    config: &MathCoreConfig<'source>,
+) -> Result<String, error::LatexError<'source>> {
+    LatexToMathML::new(config)?.convert(latex)
+}
 
-    let mut output = MathMLEmitter::new();
-    match display {
-        Display::Block => output.push_str("<math display=\"block\">"),
-        Display::Inline => output.push_str("<math>"),
-    };
+/// Convert LaTeX text to MathML, recovering from errors where possible.
+///
+/// Unknown commands, unknown environments and unbalanced braces are rendered in
+/// `<merror>` elements, and the rest of the input is rendered as usual. If the input
+/// cannot be recovered from, the whole input is rendered as an error.
+/// All encountered errors are returned alongside the MathML.
+///
+/// An error is only returned if one of the macros in the config is invalid.
+///
+/// ```rust
+/// use math_core::{latex_to_mathml_with_errors, MathCoreConfig};
+///
+/// let config = MathCoreConfig::default();
+/// let (mathml, errors) = latex_to_mathml_with_errors(r"x + \foo", &config).unwrap();
+/// assert_eq!(mathml, "<math><mi>x</mi><mo>+</mo><merror><mtext>\\foo</mtext></merror></math>");
+/// assert_eq!(errors.len(), 1);
+/// ```
+pub fn latex_to_mathml_with_errors<'source>(
+    latex: &'source str,
+    config: &MathCoreConfig<'source>,
+) -> Result<(String, Vec<error::LatexError<'source>>), error::LatexError<'source>> {
+    Ok(LatexToMathML::new(config)?.convert_with_errors(latex))
+}
+
+/// The number of parameters of a command body, i.e., the highest parameter like `#2`.
+fn num_params(body: &str) -> u8 {
+    body.as_bytes()
+        .windows(2)
+        .filter_map(|w| match w {
+            [b'#', digit @ b'1'..=b'9'] => Some(digit - b'0'),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Renders the nodes as the content of a `<math>` element, with the options of the config.
+struct MathRenderer<'arena, 'config> {
+    output: MathMLEmitter<'arena>,
+    latex: &'config str,
+    config: &'config MathCoreConfig<'static>,
+    base_indent: usize,
+}
+
+impl<'config> MathRenderer<'_, 'config> {
+    /// Start the `<math>` element.
+    fn new(
+        latex: &'config str,
+        display: Display,
+        config: &'config MathCoreConfig<'static>,
+    ) -> Self {
+        let mut output = MathMLEmitter::with_indent_width(config.indent_width);
+        output.push_str("<math");
+        if config.xmlns {
+            output.push_str(" xmlns=\"http://www.w3.org/1998/Math/MathML\"");
+        }
+        if matches!(display, Display::Block) {
+            output.push_str(" display=\"block\"");
+        }
+        output.push('>');
+
+        let level = if config.pretty { 1 } else { 0 };
+        let base_indent = if config.annotation {
+            output.new_line_and_indent(level);
+            output.push_str("<semantics>");
+            output.new_line_and_indent(2 * level);
+            output.push_str("<mrow>");
+            3 * level
+        } else {
+            level
+        };
+        MathRenderer {
+            output,
+            latex,
+            config,
+            base_indent,
+        }
+    }
+
+    /// Render the LaTeX source as an error, for `ErrorPolicy::Render`.
+    fn render_error(&mut self) {
+        self.output.new_line_and_indent(self.base_indent);
+        self.output.push_str("<merror><mtext>");
+        self.output.push_escaped(self.latex);
+        self.output.push_str("</mtext></merror>");
+    }
+}
+
+impl<'arena> Renderer<'arena> for MathRenderer<'arena, '_> {
+    type Output = String;
+
+    fn render_node(&mut self, node: &'arena Node<'arena>) {
+        self.output.emit(node, self.base_indent);
+    }
+
+    /// Close the `<math>` element.
+    fn finish(mut self) -> String {
+        let output = &mut self.output;
+        let level = if self.config.pretty { 1 } else { 0 };
+        if self.config.annotation {
+            output.new_line_and_indent(2 * level);
+            output.push_str("</mrow>");
+            output.new_line_and_indent(2 * level);
+            output.push_str("<annotation encoding=\"application/x-tex\">");
+            output.push_escaped(self.latex);
+            output.push_str("</annotation>");
+            output.new_line_and_indent(level);
+            output.push_str("</semantics>");
+        }
+        if self.config.pretty {
+            output.push('\n');
+        }
+        output.push_str("</math>");
+        self.output.into_inner()
+    }
+}
+
+/// A converter which keeps user-defined commands around across many conversions.
+///
+/// This is useful when converting a whole document, where the commands are defined
+/// once in a preamble and then used by all the equations.
+///
+/// ```rust
+/// use math_core::{Display, LatexToMathML, MathCoreConfig};
+///
+/// let config = MathCoreConfig::default();
+/// let mut converter = LatexToMathML::new(&config).unwrap();
+/// converter
+///     .define_macros(r"\newcommand{\abs}[1]{\left|#1\right|}")
+///     .unwrap();
+/// let mathml = converter.convert(r"\abs{x}").unwrap();
+/// println!("{}", mathml);
+/// let mathml = converter.convert_with_display(r"\abs{y}", Display::Block).unwrap();
+/// println!("{}", mathml);
+/// ```
+pub struct LatexToMathML {
+    /// The preambles and commands defined so far, in order. The nodes of the commands
+    /// live in the arena of a conversion, so the definitions are parsed again for each
+    /// conversion.
+    definitions: Vec<Definition>,
+    config: MathCoreConfig<'static>,
+    /// The numbering of equations across all conversions. It is locked for the whole
+    /// conversion, so that concurrent conversions never give out the same number.
+    numbering: Mutex<Numbering>,
+}
+
+enum Definition {
+    /// A preamble which was given to `define_macros`.
+    Preamble(String),
+    /// A command which was given to `define_macro`, with the name without backslash.
+    Cmd { name: String, body: String },
+}
+
+#[derive(Default)]
+struct Numbering {
+    /// The number of the last numbered equation.
+    equation_counter: usize,
+    /// The numbers of the equations with a `\label`.
+    labels: HashMap<String, String>,
+}
+
+impl LatexToMathML {
+    /// Create a converter with the given options, including the commands in
+    /// `config.macros`.
+    pub fn new<'source>(
+        config: &MathCoreConfig<'source>,
+    ) -> Result<Self, error::LatexError<'source>> {
+        let mut converter = LatexToMathML {
+            definitions: Vec::new(),
+            config: config.without_macros(),
+            numbering: Mutex::new(Numbering {
+                equation_counter: config.equation_counter,
+                labels: HashMap::new(),
+            }),
+        };
+        for &(name, body) in config.macros.iter() {
+            converter.define_macro(name, body)?;
+        }
+        Ok(converter)
+    }
+
+    /// Define commands from a preamble like `\newcommand{\R}{\mathbb{R}}`.
+    ///
+    /// The commands, and colors defined with `\definecolor`, are available in all
+    /// subsequent conversions and in later calls of this method. Anything in the
+    /// preamble other than command and color definitions is ignored.
+    pub fn define_macros<'source>(
+        &mut self,
+        preamble: &'source str,
+    ) -> Result<(), error::LatexError<'source>> {
+        {
+            let arena = Arena::new();
+            let l = lexer::Lexer::new(preamble);
+            let mut p = parse::Parser::with_definitions(l, &arena, self.definitions(&arena));
+            p.parse()?;
+        }
+        self.definitions
+            .push(Definition::Preamble(preamble.to_string()));
+        Ok(())
+    }
+
+    /// Define a single command, given its name (with or without the backslash)
+    /// and its body.
+    ///
+    /// The number of arguments is the highest parameter (like `#2`) in the body.
+    pub fn define_macro<'source>(
+        &mut self,
+        name: &str,
+        body: &'source str,
+    ) -> Result<(), error::LatexError<'source>> {
+        {
+            let arena = Arena::new();
+            let l = lexer::Lexer::new(body);
+            let mut p = parse::Parser::with_definitions(l, &arena, self.definitions(&arena));
+            p.parse_cmd_body(num_params(body))?;
+        }
+        let name = name.strip_prefix('\\').unwrap_or(name);
+        self.definitions.push(Definition::Cmd {
+            name: name.to_string(),
+            body: body.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Parse the definitions so far into the given arena.
+    fn definitions<'arena>(&'arena self, arena: &'arena Arena) -> parse::Definitions<'arena> {
+        let mut definitions = parse::Definitions::default();
+        for definition in self.definitions.iter() {
+            definitions = match definition {
+                Definition::Preamble(preamble) => {
+                    let l = lexer::Lexer::new(preamble);
+                    let mut p = parse::Parser::with_definitions(l, arena, definitions);
+                    p.parse()
+                        .expect("the preamble was parsed in the same way when it was defined");
+                    p.into_definitions()
+                }
+                Definition::Cmd { name, body } => {
+                    let l = lexer::Lexer::new(body);
+                    let mut p = parse::Parser::with_definitions(l, arena, definitions);
+                    let cmd = p
+                        .parse_cmd_body(num_params(body))
+                        .expect("the body was parsed in the same way when it was defined");
+                    let mut definitions = p.into_definitions();
+                    definitions.push_cmd(name, cmd);
+                    definitions
+                }
+            };
+        }
+        definitions
+    }
+
+    /// The number of the last numbered equation so far.
+    ///
+    /// Numbered environments like `align` continue counting from here in the next
+    /// conversion.
+    pub fn equation_counter(&self) -> usize {
+        self.lock_numbering().equation_counter
+    }
+
+    /// Let the numbering of equations continue after the given number.
+    pub fn set_equation_counter(&self, counter: usize) {
+        self.lock_numbering().equation_counter = counter;
+    }
+
+    /// Take the labels of the numbered equations so far, mapped to their numbers,
+    /// like `"eq:energy"` to `"(1)"`.
+    ///
+    /// `\eqref` in later conversions can only refer to labels which are known to the
+    /// converter, so a document with references to later equations has to be
+    /// converted twice. The labels are taken out of the converter, so put them back
+    /// with [`set_labels`](Self::set_labels) if they are still needed.
+    pub fn take_labels(&self) -> HashMap<String, String> {
+        mem::take(&mut self.lock_numbering().labels)
+    }
 
-    let base_indent = if pretty { 1 } else { 0 };
-    for node in nodes.iter() {
-        output.emit(node, base_indent);
+    /// Replace the labels which `\eqref` can refer to.
+    pub fn set_labels(&self, labels: HashMap<String, String>) {
+        self.lock_numbering().labels = labels;
     }
-    if pretty {
-        output.push('\n');
+
+    fn lock_numbering(&self) -> MutexGuard<'_, Numbering> {
+        // The numbering is always left in a valid state, even after a panic.
+        self.numbering.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Parse LaTeX text into a tree of nodes, with the commands defined so far.
+    ///
+    /// Equations are numbered after [`equation_counter`](Self::equation_counter),
+    /// but neither the counter nor the labels are updated. See [`parse`](crate::parse)
+    /// for details.
+    pub fn parse<'arena, 'source: 'arena>(
+        &'arena self,
+        latex: &'source str,
+        arena: &'arena Arena,
+    ) -> Result<&'arena [&'arena Node<'arena>], error::LatexError<'source>> {
+        let numbering = self.lock_numbering();
+        let mut counter = numbering.equation_counter;
+        let mut labels = numbering.labels.clone();
+        drop(numbering);
+        get_nodes(
+            latex,
+            arena,
+            self.definitions(arena),
+            None,
+            &mut counter,
+            &mut labels,
+        )
+    }
+
+    /// Number the equations in LaTeX text and collect their labels, without converting it.
+    ///
+    /// This is a cheap first pass over a document, after which `\eqref` can also refer
+    /// to equations further down. Errors are ignored here; they are reported when the
+    /// text is converted.
+    pub fn collect_labels(&self, latex: &str) {
+        let arena = Arena::new();
+        let numbering = &mut *self.lock_numbering();
+        let _ = get_nodes(
+            latex,
+            &arena,
+            self.definitions(&arena),
+            Some(&mut Vec::new()),
+            &mut numbering.equation_counter,
+            &mut numbering.labels,
+        );
+    }
+
+    /// Parse LaTeX text with the commands defined so far and render it with the given
+    /// backend.
+    ///
+    /// This is what [`convert`](Self::convert) does with a backend that emits the
+    /// `<math>` element, so the numbering of equations continues in the same way.
+    /// Other backends don't wrap the output in anything.
+    ///
+    /// ```rust
+    /// use math_core::{Arena, LatexToMathML, MathCoreConfig, MathMLEmitter, TextRenderer};
+    ///
+    /// let mut converter = LatexToMathML::new(&MathCoreConfig::default()).unwrap();
+    /// converter.define_macros(r"\newcommand{\sq}[1]{#1^2}").unwrap();
+    /// let arena = Arena::new();
+    /// let mathml = converter.render(r#"\sq{x}"#, &arena, MathMLEmitter::new()).unwrap();
+    /// assert_eq!(mathml, "<msup><mi>x</mi><mn>2</mn></msup>");
+    /// let text = converter.render(r#"\sqrt{x^2 + 1}"#, &arena, TextRenderer::new()).unwrap();
+    /// assert_eq!(text, "√(x²+1)");
+    /// ```
+    pub fn render<'arena, 'source: 'arena, R: Renderer<'arena>>(
+        &'arena self,
+        latex: &'source str,
+        arena: &'arena Arena,
+        mut renderer: R,
+    ) -> Result<R::Output, error::LatexError<'source>> {
+        let numbering = &mut *self.lock_numbering();
+        let nodes = get_nodes(
+            latex,
+            arena,
+            self.definitions(arena),
+            None,
+            &mut numbering.equation_counter,
+            &mut numbering.labels,
+        )?;
+        renderer.render_nodes(nodes);
+        Ok(renderer.finish())
+    }
+
+    /// Convert LaTeX text to MathML, with the commands defined so far.
+    ///
+    /// Commands defined within `latex` only apply to this conversion.
+    pub fn convert<'source>(
+        &self,
+        latex: &'source str,
+    ) -> Result<String, error::LatexError<'source>> {
+        self.convert_with_display(latex, self.config.display)
+    }
+
+    /// Like [`convert`](Self::convert), but with a display style that differs from
+    /// the one in the config.
+    pub fn convert_with_display<'source>(
+        &self,
+        latex: &'source str,
+        display: Display,
+    ) -> Result<String, error::LatexError<'source>> {
+        if matches!(self.config.error_policy, ErrorPolicy::Render) {
+            return Ok(self.convert_recovering(latex, display).0);
+        }
+        let arena = Arena::new();
+        let renderer = MathRenderer::new(latex, display, &self.config);
+        self.render(latex, &arena, renderer)
+    }
+
+    /// Convert LaTeX text to MathML, recovering from errors where possible.
+    ///
+    /// See [`latex_to_mathml_with_errors`] for details.
+    pub fn convert_with_errors<'source>(
+        &self,
+        latex: &'source str,
+    ) -> (String, Vec<error::LatexError<'source>>) {
+        self.convert_recovering(latex, self.config.display)
+    }
+
+    fn convert_recovering<'source>(
+        &self,
+        latex: &'source str,
+        display: Display,
+    ) -> (String, Vec<error::LatexError<'source>>) {
+        let arena = Arena::new();
+        let mut errors = Vec::new();
+        let mut renderer = MathRenderer::new(latex, display, &self.config);
+        let numbering = &mut *self.lock_numbering();
+        let nodes = get_nodes(
+            latex,
+            &arena,
+            self.definitions(&arena),
+            Some(&mut errors),
+            &mut numbering.equation_counter,
+            &mut numbering.labels,
+        );
+        match nodes {
+            Ok(nodes) => renderer.render_nodes(nodes),
+            Err(e) => {
+                errors.push(e);
+                renderer.render_error();
+            }
+        }
+        (renderer.finish(), errors)
     }
-    output.push_str("</math>");
-    Ok(output.into_inner())
 }
 
 #[cfg(test)]
 mod tests {
-    use insta::assert_snapshot;
+    use insta::{assert_json_snapshot, assert_snapshot};
 
-    use crate::{LatexError, error, latex_to_mathml};
+    use crate::{
+        Display, ErrorPolicy, LatexErrKind, LatexError, LatexToMathML, MathCoreConfig, Node,
+        TextRenderer, error, latex_to_mathml, latex_to_mathml_with_errors, parse,
+    };
     use mathml_renderer::ast::MathMLEmitter;
 
-    use super::{Arena, get_nodes};
+    use std::collections::HashMap;
+
+    use super::{Arena, get_nodes, parse::Definitions};
 
-    fn convert_content(latex: &str) -> Result<String, error::LatexError> {
+    fn convert_content(latex: &str) -> Result<String, error::LatexError<'_>> {
         let arena = Arena::new();
-        let nodes = get_nodes(latex, &arena)?;
+        let nodes = get_nodes(
+            latex,
+            &arena,
+            Definitions::default(),
+            None,
+            &mut 0,
+            &mut HashMap::new(),
+        )?;
         let mut emitter = MathMLEmitter::new();
         for node in nodes.iter() {
             emitter.emit(node, 0);
@@ -247,7 +745,45 @@ mod tests {
                 "cases",
                 r"f(x):=\begin{cases}0 &\text{if } x\geq 0\\1 &\text{otherwise.}\end{cases}",
             ),
+            (
+                "dcases",
+                r"\begin{dcases}\frac{1}{2} & x > 0\\0 & \text{else}\end{dcases}",
+            ),
+            (
+                "rcases",
+                r"\begin{rcases}a &\text{if } x\\b &\text{if } y\end{rcases} = f",
+            ),
+            (
+                "drcases",
+                r"\begin{drcases}\frac{a}{b}\\c\end{drcases}\Rightarrow d",
+            ),
+            (
+                "cases_star",
+                r"\begin{cases*}1 & if $x \geq 0$, \\-1 & otherwise\end{cases*}",
+            ),
             ("mathstrut", r"\mathstrut"),
+            (
+                "hspace",
+                r"a\hspace{1cm}b\hspace*{-2.5 mm}c\mspace{18mu}d\enspace e\thinspace f",
+            ),
+            ("kern", r"a\kern3pt b\mkern-3mu c\hskip 1in d\mskip+.5em e"),
+            (
+                "glue",
+                r"a\hskip 1em plus 2pt minus 1pt b\mskip 3mu plus 1fill c",
+            ),
+            (
+                "hspace_in_text",
+                r"\text{a\hspace{1cm}b} \textbf{\hspace*{1em}c}",
+            ),
+            ("rule", r"\rule{1em}{2pt} \rule[-1ex]{0.5pt}{1in}"),
+            ("phantom", r"a + \phantom{b} + c"),
+            ("hphantom_vphantom", r"\hphantom{xx} \vphantom{\frac12} y"),
+            (
+                "smash",
+                r"\sqrt{\smash{y}} \sqrt{\smash[b]{y}} \sqrt{\smash[t]{y}}",
+            ),
+            ("llap_rlap_clap", r"\llap{a} b \rlap{c} \clap{d}"),
+            ("mathclap", r"\sum_{\mathclap{1 \le i \le n}} x_i"),
             ("greater_than", r"x > y"),
             ("text_transform_sup", r"\mathbb{N} \cup \mathbb{N}^+"),
             ("overbrace", r"\overbrace{a+b+c}^{d}"),
@@ -327,16 +863,228 @@ mod tests {
             ("RR_command", r"\RR"),
             ("odv", r"\odv{f}{x}"),
             ("xrightarrow", r"\xrightarrow{x}"),
+            ("xrightarrow_below", r"A \xrightarrow[below]{above} B"),
+            ("xlongequal", r"a \xlongequal{\text{def}} b"),
+            (
+                "extensible_arrows",
+                r"\xLeftarrow{a} \xRightarrow[b]{} \xmapsto{f} \xhookrightarrow{i} \xtwoheadrightarrow{p} \xtofrom[y]{x}",
+            ),
             ("slashed", r"\slashed{\partial}"),
             ("plus_after_equal", r"x = +4"),
             ("plus_after_equal_subscript", r"x =_+4"),
             ("plus_after_equal_subscript2", r"x =_2 +4"),
             ("color", r"{\color{Blue}x^2}"),
+            ("color_rgb", r"{\color[rgb]{0.1,0.2,0.3}x}"),
+            (
+                "color_html",
+                r"{\color[HTML]{FF8800}x} {\color[RGB]{0, 128, 255}y}",
+            ),
+            (
+                "color_gray_cmyk",
+                r"{\color[gray]{0.5}x} {\color[cmyk]{0,1,1,0.5}y}",
+            ),
+            ("color_mix", r"{\color{red!30!blue}x} {\color{green!50}y}"),
+            (
+                "textcolor",
+                r"\textcolor{red}{x^2} + \textcolor[HTML]{0000FF}y",
+            ),
+            (
+                "definecolor",
+                r"\definecolor{myorange}{rgb}{1,0.5,0}\textcolor{myorange!50!black}{x}",
+            ),
+            ("colorbox", r"\colorbox{yellow}{some text $x^2$}"),
+            ("fcolorbox", r"\fcolorbox{red}[rgb]{0.9,0.9,1}{text}"),
+            ("boxed", r"\boxed{E = mc^2}"),
+            ("fbox", r"\fbox{result: $x = 1$} \framebox[2cm][l]{text}"),
+            (
+                "cancel",
+                r"\cancel{x} + \bcancel{y} + \xcancel{z} + \sout{w}",
+            ),
+            ("cancelto", r"\cancelto{0}{x^2}"),
+            ("sideset", r"\sideset{_a^b}{_c^d}\sum"),
+            ("sideset_limits", r"\sideset{}{'}\sum_{n=0}^\infty a_n"),
+            (
+                "sideset_big_op_and_letter",
+                r"\sideset{^1}{_2}\prod \sideset{_3}{^4_5}X",
+            ),
+            ("prescript", r"\prescript{14}{6}{C} \prescript{}{2}{F_1}"),
+            ("tensor", r"\tensor{T}{^a_b^c} \tensor[^1_2]{M}{_3}"),
+            (
+                "indices",
+                r"R\indices{^\rho_\sigma_\mu_\nu} = g_{\mu\alpha}\indices{^\alpha_\nu}",
+            ),
+            ("math_class_bin", r"a \mathbin{x} b \mathbin{\to} c"),
+            ("math_class_rel", r"a \mathrel{+} b \mathrel{:} c"),
+            ("math_class_ord", r"a = \mathord{-} b \mathord{=} c"),
+            ("math_class_op", r"\mathop{T} x + 2\mathop{\mathrm{Tr}}(A)"),
+            ("math_class_op_limits", r"\mathop{\sum}\limits_i x_i"),
+            (
+                "math_class_op_display",
+                r"\displaystyle\mathop{T}_{n} \mathop{\sum}_{i=1}^n",
+            ),
+            (
+                "user_cmd_spacing",
+                r"\newcommand{\tr}{\operatorname{tr}} \tr A + \tr(B)",
+            ),
+            (
+                "math_class_delims",
+                r"\mathopen{|} x \mathclose{|} \mathpunct{.} \mathinner{a}",
+            ),
+            ("unary_minus", r"-a + (-b) \cdot {-c}"),
+            ("binary_after_relation", r"a \le -b, c \to +d"),
+            ("binary_after_factorial", r"n! - 1"),
+            ("binary_at_end", r"{a+} \cup {}"),
+            (
+                "operator_spacing",
+                r"2\sin x \operatorname{tr} A \lim_{n} a_n",
+            ),
+            ("relation_before_punct", r"a = b, c \le d;"),
+            ("infix_over", r"{a+b \over c} + 1 \over 2"),
+            ("infix_choose", r"{n \choose k} = {n \brace k} {n \brack k}"),
+            ("infix_atop_above", r"{a \atop b} {a \above 1pt b}"),
+            (
+                "infix_withdelims",
+                r"{a \overwithdelims ( ] b} {a \atopwithdelims . | b} {a \abovewithdelims \{ \} 2pt b}",
+            ),
+            ("infix_in_left_right", r"\left( a \over b \right)"),
+            (
+                "infix_in_matrix",
+                r"\begin{matrix} a \over b & {1 \choose 2} \\ c & d \atop e \end{matrix}",
+            ),
+            ("infix_empty_numerator", r"{\over x}"),
+            ("newcommand", r"\newcommand{\R}{\mathbb{R}} x \in \R^2"),
+            (
+                "newcommand_with_args",
+                r"\newcommand\abs[1]{\left|#1\right|} \abs{x} + \abs y",
+            ),
+            (
+                "newcommand_optional_arg",
+                r"\newcommand{\seq}[2][n]{#2_#1} \seq{a}, \seq[k]{b}",
+            ),
+            ("newcommand_star", r"\newcommand*{\e}{\mathrm{e}} \e^x"),
+            (
+                "renewcommand_predefined",
+                r"\renewcommand{\vec}[1]{\mathbf{#1}} \vec{v}",
+            ),
+            ("def_with_params", r"\def\pair#1#2{(#1, #2)} \pair{a}{b}"),
+            (
+                "user_cmd_nested",
+                r"\newcommand{\sq}[1]{{#1}^2}\newcommand{\norm}[1]{\sq{\left\|#1\right\|}} \norm{\sq{x}}",
+            ),
+            ("user_cmd_redefined", r"\def\a{x} \a \def\a{y} \a"),
+            (
+                "newcommand_text_arg",
+                r"\newcommand{\t}[1]{\text{#1}} x \t{if } x > 0 \t{and\hspace{1em}so}",
+            ),
+            (
+                "newcommand_operatorname_arg",
+                r"\newcommand{\op}[1]{\operatorname{#1}} \op{sgn} x + \op f(x)",
+            ),
+            (
+                "array_rules",
+                r"\begin{array}{|l|cr} \hline a & b & c \\ \hline d & e & f \\ \hline \end{array}",
+            ),
+            (
+                "array_separator",
+                r"\begin{array}{r@{.}l} 3 & 14 \\ 2 & 72 \end{array}",
+            ),
+            (
+                "array_dashed_cline",
+                r"\begin{array}{c:cc} 1 & 2 & 3 \\ \cline{2-3} 4 & 5 & 6 \\ \hdashline 7 & 8 & 9 \end{array}",
+            ),
+            (
+                "pmatrix_star_align",
+                r"\begin{pmatrix*}[r] -1 & 2 \\ 3 & -4 \end{pmatrix*}",
+            ),
+            ("matrix_star", r"\begin{matrix*} x & y \\ z \end{matrix*}"),
+            ("gather", r"\begin{gather} a = b \\ c = d + e \end{gather}"),
+            (
+                "multline",
+                r"\begin{multline} a + b + c \\ + d + e \\ + f \end{multline}",
+            ),
+            (
+                "flalign",
+                r"\begin{flalign*} x &= y & a &= b \end{flalign*}",
+            ),
+            (
+                "alignat",
+                r"\begin{alignat}{2} x &= y &\quad a &= b \\ x' &= y' & a' &= b' \end{alignat}",
+            ),
+            (
+                "split_in_equation",
+                r"\begin{equation} \begin{split} a &= b \\ &= c \end{split} \end{equation}",
+            ),
+            (
+                "intertext",
+                r"\begin{align} a &= b \\ \intertext{and therefore} c &= d \end{align}",
+            ),
+            (
+                "shortintertext_mid_row",
+                r"\begin{align} a &= b \shortintertext{so} c &= d \\ e &= f \end{align}",
+            ),
+            (
+                "align_tag_notag",
+                r"\begin{align} a &= b \tag{b} \\ c &= d \notag \\ e &= f \\ g &= h \tag*{A.1} \end{align}",
+            ),
+            (
+                "equation_numbered",
+                r"\begin{equation} E = mc^2 \end{equation}",
+            ),
+            ("top_level_tag", r"x = y \tag{3a}"),
+            (
+                "gather_nonumber",
+                r"\begin{gather} a = b \nonumber \\ c = d \end{gather}",
+            ),
+            (
+                "cd",
+                r"\begin{CD} A @>f>> B \\ @VgVV @VVhV \\ C @>>k> D \end{CD}",
+            ),
+            (
+                "cd_equals_and_empty",
+                r"\begin{CD} A @= B @<<< C \\ @| @. @AA{x_1}A \\ A @. @>>> E \end{CD}",
+            ),
+            ("cd_starting_with_arrow", r"\begin{CD} @>>> B \end{CD}"),
+            ("cd_text_label", r"\begin{CD} A @>\text{a@.b}>> B \end{CD}"),
+            ("at_sign_in_math", r"x @= y @> z"),
+            ("at_sign_in_text", r"\text{mail@Alice.org} \text{a@.b}"),
+            ("substack", r"\sum_{\substack{0<i<m\\0<j<n}} P(i,j)"),
+            (
+                "subarray",
+                r"\sum_{\begin{subarray}{l} i\in\Lambda \\ 0<j<n \end{subarray}} P(i,j)",
+            ),
+            (
+                "smallmatrix",
+                r"\bigl(\begin{smallmatrix} a & b \\ c & d \end{smallmatrix}\bigr)",
+            ),
+            (
+                "psmallmatrix",
+                r"\begin{psmallmatrix} 1 & 0 \\ 0 & 1 \end{psmallmatrix}",
+            ),
+            (
+                "bsmallmatrix",
+                r"\begin{bsmallmatrix} x \\ y \end{bsmallmatrix}",
+            ),
+            (
+                "label_eqref",
+                r"\begin{equation} E = mc^2 \label{eq:energy} \end{equation} \text{by } \eqref{eq:energy}",
+            ),
+            (
+                "label_rows",
+                r"\begin{align} a &= b \label{eq:a} \\ c &= d \\ e &= f \label{eq:e} \end{align}",
+            ),
+            (
+                "label_nested",
+                r"\begin{equation} \begin{aligned} a &= b \label{eq:ab} \end{aligned} \end{equation}",
+            ),
         ];
 
+        let config = MathCoreConfig {
+            pretty: true,
+            ..Default::default()
+        };
         for (name, problem) in problems.into_iter() {
-            let mathml = latex_to_mathml(problem, crate::Display::Inline, true)
-                .expect(format!("failed to convert `{}`", problem).as_str());
+            let mathml = latex_to_mathml(problem, &config)
+                .unwrap_or_else(|_| panic!("failed to convert `{}`", problem));
             assert_snapshot!(name, &mathml, problem);
         }
     }
@@ -345,6 +1093,10 @@ mod tests {
     fn error_test() {
         let problems = [
             ("end_without_open", r"\end{matrix}"),
+            (
+                "cases_star_unclosed_dollar",
+                r"\begin{cases*}1 & if $x \end{cases*}",
+            ),
             ("curly_close_without_open", r"}"),
             ("unsupported_command", r"\asdf"),
             (
@@ -352,6 +1104,21 @@ mod tests {
                 r"\begin{xmatrix} 1 \end{xmatrix}",
             ),
             ("incorrect_bracket", r"\operatorname[lim}"),
+            ("hspace_without_unit", r"\hspace{3}"),
+            ("kern_unknown_unit", r"\kern2xy"),
+            ("rule_unclosed_raise", r"\rule[1pt{1em}{1em}"),
+            ("smash_unknown_option", r"\smash[x]{y}"),
+            ("fbox_unclosed", r"\fbox{text"),
+            ("sideset_not_a_script", r"\sideset{a}{b}\sum"),
+            ("tensor_unclosed", r"\tensor{T}{^a"),
+            ("infix_twice", r"{a \over b \over c}"),
+            ("infix_as_argument", r"\frac\over b"),
+            ("infix_withdelims_no_delim", r"{a \atopwithdelims x b}"),
+            ("color_unknown", r"\color{nocolor}x"),
+            ("color_unknown_model", r"\color[hsb]{0.1,0.2,0.3}x"),
+            ("color_invalid_spec", r"\color[rgb]{0.1,0.2}x"),
+            ("color_invalid_char", r"\textcolor{red;}{x}"),
+            ("xrightarrow_unclosed_bracket", r"\xrightarrow[a{b}"),
             ("unclosed_bracket", r"\sqrt[lim"),
             ("mismatched_begin_end", r"\begin{matrix} 1 \end{bmatrix}"),
             (
@@ -383,12 +1150,459 @@ mod tests {
             ("sub_sub", "x_2_3 y"),
             ("no_rbrack_instead_of_bracket", r"\sqrt[3\rbrack{1}"),
             ("genfrac_wrong_unit", r"\genfrac(]{1pg}{2}{a+b}{c+d}"),
+            ("cmd_arg_outside_definition", r"x^#1"),
+            ("cmd_arg_out_of_range", r"\newcommand{\a}[1]{#1 + #2}"),
+            ("newcommand_without_name", r"\newcommand{x}{y}"),
+            ("newcommand_invalid_num_args", r"\newcommand{\a}[x]{y}"),
+            ("def_params_out_of_order", r"\def\a#2{y}"),
+            ("newcommand_as_argument", r"\frac\newcommand{\a}{b}"),
+            ("newcommand_already_defined", r"\newcommand{\alpha}{a}"),
+            ("renewcommand_undefined", r"\renewcommand{\foo}{x}"),
+            (
+                "definecolor_as_argument",
+                r"\sqrt\definecolor{a}{gray}{0.5}",
+            ),
+            (
+                "user_cmd_used_before_definition",
+                r"\foo \newcommand{\foo}{b}",
+            ),
+            (
+                "array_unknown_column_spec",
+                r"\begin{array}{lx} a \end{array}",
+            ),
+            ("array_unclosed_column_spec", r"\begin{array}{l"),
+            ("array_without_column_spec", r"\begin{array} a \end{array}"),
+            (
+                "matrix_star_unknown_align",
+                r"\begin{bmatrix*}[x] a \end{bmatrix*}",
+            ),
+            (
+                "cline_without_range",
+                r"\begin{array}{cc} \cline{2} a \end{array}",
+            ),
+            (
+                "cline_out_of_range",
+                r"\begin{array}{cc} \cline{0-9} a & b \end{array}",
+            ),
+            (
+                "array_too_many_cells",
+                r"\begin{array}{c} a & b & c \end{array}",
+            ),
+            (
+                "hline_in_matrix",
+                r"\begin{matrix} a \\ \hline b\end{matrix}",
+            ),
+            (
+                "hline_in_matrix_in_array",
+                r"\begin{array}{c} \begin{matrix} \hline a \end{matrix} \end{array}",
+            ),
+            ("cd_arrow_in_group", r"\begin{CD} {A @>>> B} \end{CD}"),
+            ("cd_unclosed_label", r"\begin{CD} A @>f> B \end{CD}"),
+            (
+                "subarray_unknown_spec",
+                r"\begin{subarray}{r} a \end{subarray}",
+            ),
+            ("eqref_undefined", r"x \eqref{eq:nowhere}"),
+            (
+                "label_unnumbered",
+                r"\begin{align*} a \label{eq:a} \\ b \end{align*}",
+            ),
+            (
+                "duplicate_label",
+                r"\begin{align} a \label{eq:a} \\ b \label{eq:a} \end{align}",
+            ),
+            ("label_empty", r"\label{}"),
+            ("label_with_space", r"\label{a b}"),
+        ];
+
+        for (name, problem) in problems.into_iter() {
+            let LatexError(span, error) = convert_content(problem).unwrap_err();
+            let output = format!("Span: {:?}\n{:#?}", span, error);
+            assert_snapshot!(name, &output, problem);
+        }
+    }
+    #[test]
+    fn equation_counter() {
+        let config = MathCoreConfig {
+            equation_counter: 4,
+            ..Default::default()
+        };
+        let converter = LatexToMathML::new(&config).unwrap();
+        let mathml = converter
+            .convert(r"\begin{align} a &= b \\ c &= d \end{align}")
+            .unwrap();
+        assert!(mathml.contains("<mtext>(5)</mtext>"));
+        assert!(mathml.contains("<mtext>(6)</mtext>"));
+        // Tags and starred environments don't count.
+        converter
+            .convert(
+                r"\begin{equation} x \tag{A} \end{equation} \begin{equation*} y \end{equation*}",
+            )
+            .unwrap();
+        let mathml = converter
+            .convert(r"\begin{equation} z \end{equation}")
+            .unwrap();
+        assert!(mathml.contains("<mtext>(7)</mtext>"));
+        assert_eq!(converter.equation_counter(), 7);
+
+        converter.set_equation_counter(0);
+        let mathml = converter
+            .convert(r"\begin{equation} z \end{equation}")
+            .unwrap();
+        assert!(mathml.contains("<mtext>(1)</mtext>"));
+    }
+
+    #[test]
+    fn equation_counter_across_threads() {
+        let converter = LatexToMathML::new(&MathCoreConfig::default()).unwrap();
+        let mut numbers: Vec<String> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    s.spawn(|| {
+                        (0..25)
+                            .map(|_| {
+                                converter
+                                    .convert(r"\begin{equation} x \end{equation}")
+                                    .unwrap()
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        numbers.sort();
+        numbers.dedup();
+        assert_eq!(numbers.len(), 200);
+        assert_eq!(converter.equation_counter(), 200);
+    }
+
+    #[test]
+    fn labels() {
+        let converter = LatexToMathML::new(&MathCoreConfig::default()).unwrap();
+        converter
+            .convert(
+                r"\begin{align} a &= b \label{eq:a} \\ c &= d \tag{C} \label{eq:c} \end{align}",
+            )
+            .unwrap();
+        let mathml = converter.convert(r"\eqref{eq:a} \eqref{eq:c}").unwrap();
+        assert!(mathml.contains(r##"<mrow href="#eq:a"><mtext>(1)</mtext></mrow>"##));
+        assert!(mathml.contains(r##"<mrow href="#eq:c"><mtext>(C)</mtext></mrow>"##));
+        // Unnumbered equations can't be referred to.
+        assert!(converter.convert(r"x \label{eq:x} \eqref{eq:x}").is_err());
+
+        let labels = converter.take_labels();
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels["eq:c"], "(C)");
+        assert!(converter.convert(r"\eqref{eq:a}").is_err());
+        converter.set_labels(labels);
+        let (mathml, errors) = converter.convert_with_errors(r"\eqref{eq:a} \eqref{eq:b}");
+        assert!(mathml.contains(r##"<mrow href="#eq:b"><mtext>(??)</mtext></mrow>"##));
+        assert!(matches!(
+            errors.as_slice(),
+            [LatexError(_, LatexErrKind::UndefinedLabel("eq:b"))]
+        ));
+    }
+
+    #[test]
+    fn failed_conversion_keeps_numbering() {
+        let converter = LatexToMathML::new(&MathCoreConfig::default()).unwrap();
+        let equation = r"\begin{equation} a \label{eq:a} \end{equation}";
+        let failing = format!(r"{equation} \foo");
+        assert!(converter.convert(&failing).is_err());
+        assert_eq!(converter.equation_counter(), 0);
+        let LatexError(_, error) = converter.convert(r"\eqref{eq:a}").unwrap_err();
+        assert!(matches!(error, LatexErrKind::UndefinedLabel("eq:a")));
+
+        // The same holds for a formula that can't be recovered from.
+        let (_, errors) = converter.convert_with_errors(r"\begin{equation} a \label{eq:a}");
+        assert!(!errors.is_empty());
+        assert_eq!(converter.equation_counter(), 0);
+        let LatexError(_, error) = converter.convert(r"\eqref{eq:a}").unwrap_err();
+        assert!(matches!(error, LatexErrKind::UndefinedLabel("eq:a")));
+
+        let mathml = converter.convert(equation).unwrap();
+        assert!(mathml.contains("<mtext>(1)</mtext>"));
+        let mathml = converter.convert(r"\eqref{eq:a}").unwrap();
+        assert!(mathml.contains("<mtext>(1)</mtext>"));
+    }
+
+    #[test]
+    fn collect_labels() {
+        let document = [
+            r"\eqref{eq:b}",
+            r"\begin{equation} a \label{eq:a} \end{equation}",
+            r"\begin{equation} b \label{eq:b} \end{equation}",
+        ];
+        let converter = LatexToMathML::new(&MathCoreConfig::default()).unwrap();
+        for latex in document {
+            converter.collect_labels(latex);
+        }
+        assert_eq!(converter.equation_counter(), 2);
+        converter.set_equation_counter(0);
+        let mathml: Vec<_> = document
+            .iter()
+            .map(|latex| converter.convert(latex).unwrap())
+            .collect();
+        assert!(mathml[0].contains(r##"<mrow href="#eq:b"><mtext>(2)</mtext></mrow>"##));
+        assert!(mathml[2].contains("<mtext>(2)</mtext>"));
+
+        // Defining a label again is an error, also in a later conversion.
+        let LatexError(_, error) = converter
+            .convert(r"\begin{equation} c \label{eq:a} \end{equation}")
+            .unwrap_err();
+        assert!(matches!(error, LatexErrKind::DuplicateLabel("eq:a")));
+    }
+
+    #[test]
+    fn converter_with_macros() {
+        let config = MathCoreConfig {
+            macros: vec![("\\R", "\\mathbb{R}")],
+            ..Default::default()
+        };
+        let mut converter = LatexToMathML::new(&config).unwrap();
+        converter
+            .define_macros(r"\def\abs#1{\left|#1\right|}")
+            .unwrap();
+        converter
+            .define_macros(r"\newcommand{\norm}[2][2]{\abs{#2}_{#1}}")
+            .unwrap();
+        converter
+            .define_macro("inner", r"\langle #1, #2 \rangle")
+            .unwrap();
+        let output = converter
+            .convert(r"x \in \R, \norm{x} \norm[\infty]{y} \inner{a}{b}")
+            .unwrap();
+        assert_snapshot!("converter_with_macros", &output);
+
+        // Definitions in a formula only apply to that formula.
+        let local = converter
+            .convert(r"\renewcommand{\R}{x}\newcommand{\foo}{y}\R\foo")
+            .unwrap();
+        assert_eq!(local, "<math><mi>x</mi><mi>y</mi></math>");
+        let global = converter
+            .convert_with_display(r"\R", Display::Block)
+            .unwrap();
+        assert_eq!(global, "<math display=\"block\"><mi>ℝ</mi></math>");
+        let LatexError(_, error) = converter.convert(r"\foo").unwrap_err();
+        assert!(matches!(error, LatexErrKind::UnknownCommand("foo")));
+    }
+
+    #[test]
+    fn render_with_converter() {
+        let mut converter = LatexToMathML::new(&MathCoreConfig::default()).unwrap();
+        converter
+            .define_macros(r"\newcommand{\sq}[1]{#1^2}")
+            .unwrap();
+        let arena = Arena::new();
+        let text = converter
+            .render(r"\sq{x} + \sq{y}", &arena, TextRenderer::new())
+            .unwrap();
+        assert_eq!(text, "x²+y²");
+        // Rendering continues the numbering of equations like a conversion.
+        converter
+            .render(
+                r"\begin{equation}x\end{equation}",
+                &arena,
+                TextRenderer::new(),
+            )
+            .unwrap();
+        assert_eq!(converter.equation_counter(), 1);
+        let mathml = converter
+            .convert(r"\begin{equation}\sq{x}\end{equation}")
+            .unwrap();
+        assert!(mathml.contains("(2)"));
+    }
+
+    #[test]
+    fn converter_outlives_preamble() {
+        let mut converter = LatexToMathML::new(&MathCoreConfig::default()).unwrap();
+        let preamble =
+            String::from(r"\newcommand{\lbl}{\begin{equation}x\label{eq:first}\end{equation}}");
+        converter.define_macros(&preamble).unwrap();
+        drop(preamble);
+        let body = String::from(r"#1 \text{and}");
+        converter.define_macro("txt", &body).unwrap();
+        drop(body);
+        // Fill the memory of the dropped strings with something else.
+        let _garbage = vec![String::from("garbage").repeat(16); 16];
+        let output = converter.convert(r"\lbl \txt{y}").unwrap();
+        assert!(output.contains("eq:first"));
+        assert!(output.contains("<mtext>and</mtext>"));
+    }
+
+    #[test]
+    fn converter_with_colors() {
+        let mut converter = LatexToMathML::new(&MathCoreConfig::default()).unwrap();
+        converter
+            .define_macros(r"\definecolor{accent}{HTML}{FF8800}")
+            .unwrap();
+        let output = converter.convert(r"\textcolor{accent!50}{x}").unwrap();
+        assert_eq!(
+            output,
+            "<math><mrow style=\"color:#FFC480;\"><mi>x</mi></mrow></math>"
+        );
+        // Colors defined in a formula only apply to that formula.
+        converter
+            .convert(r"\definecolor{local}{gray}{0.5}\color{local}x")
+            .unwrap();
+        let LatexError(_, error) = converter.convert(r"\color{local}x").unwrap_err();
+        assert!(matches!(error, LatexErrKind::UnknownColor("local")));
+    }
+
+    #[test]
+    fn config_options() {
+        let config = MathCoreConfig {
+            display: Display::Block,
+            pretty: true,
+            indent_width: 2,
+            xmlns: true,
+            ..Default::default()
+        };
+        let output = latex_to_mathml(r"\frac{1}{x}", &config).unwrap();
+        assert_snapshot!("config_options", &output);
+    }
+
+    #[test]
+    fn mathop_limits() {
+        let problem = r"\mathop{x}_1^2 + \mathop{\mathrm{Tr}}_A + \mathop{\mathrm{d}}^2 + \mathop{T}\limits_n + \mathop{T}\nolimits_n + \sum\nolimits_i";
+        for (name, display) in [
+            ("mathop_limits_inline", Display::Inline),
+            ("mathop_limits_display", Display::Block),
+        ] {
+            let config = MathCoreConfig {
+                display,
+                pretty: true,
+                ..Default::default()
+            };
+            let output = latex_to_mathml(problem, &config).unwrap();
+            assert_snapshot!(name, &output, problem);
+        }
+    }
+
+    #[test]
+    fn annotation() {
+        let config = MathCoreConfig {
+            annotation: true,
+            ..Default::default()
+        };
+        let output = latex_to_mathml(r"a<b \& c", &config).unwrap();
+        assert_snapshot!("annotation", &output);
+        let config = MathCoreConfig {
+            display: Display::Block,
+            pretty: true,
+            annotation: true,
+            ..Default::default()
+        };
+        let output = latex_to_mathml(r"x^2 > 0", &config).unwrap();
+        assert_snapshot!("annotation_pretty", &output);
+    }
+
+    #[test]
+    fn config_render_errors() {
+        let config = MathCoreConfig {
+            error_policy: ErrorPolicy::Render,
+            ..Default::default()
+        };
+        let output = latex_to_mathml(r"a<b \asdf", &config).unwrap();
+        assert_eq!(
+            output,
+            "<math><mi>a</mi><mo>&lt;</mo><mi>b</mi><merror><mtext>\\asdf</mtext></merror></math>"
+        );
+        // Errors which can't be recovered from are rendered as a whole.
+        let output = latex_to_mathml(r"a<b^", &config).unwrap();
+        assert_eq!(
+            output,
+            "<math><merror><mtext>a&lt;b^</mtext></merror></math>"
+        );
+    }
+
+    #[test]
+    fn recovery_test() {
+        let problems = [
+            ("recover_unknown_command", r"x + \asdf + y"),
+            ("recover_unknown_command_as_arg", r"\frac{\asdf}{2}"),
+            (
+                "recover_unknown_environment",
+                r"\begin{xmatrix} 1 & 2 \end{xmatrix} = 3",
+            ),
+            ("recover_unclosed_group", r"x^{2 + {y"),
+            ("recover_unclosed_argument", r"\frac{"),
+            ("recover_unexpected_close", r"x } + y"),
+            ("recover_multiple_errors", r"\foo + x } y"),
+            ("recover_unrecoverable", r"\sqrt[3 \asdf"),
+            (
+                "recover_unnumbered_label",
+                r"\begin{gather} a \notag \label{eq:a} \end{gather}",
+            ),
         ];
 
+        let config = MathCoreConfig {
+            pretty: true,
+            ..Default::default()
+        };
         for (name, problem) in problems.into_iter() {
-            let LatexError(loc, error) = convert_content(problem).unwrap_err();
-            let output = format!("Position: {}\n{:#?}", loc, error);
+            let (mathml, errors) = latex_to_mathml_with_errors(problem, &config).unwrap();
+            let output = format!("{}\n\nErrors: {:#?}", mathml, errors);
             assert_snapshot!(name, &output, problem);
         }
     }
+
+    #[test]
+    fn error_spans() {
+        let problems = [
+            (r"x + \asdf + y", 4..9),
+            (r"\begin{xmatrix} 1 \end{xmatrix}", 0..15),
+            (r"\begin{matrix} 1 \end{pmatrix}", 17..30),
+            (r"α + }", 5..6),
+            (r"x^{2", 4..4),
+        ];
+        for (problem, expected) in problems.into_iter() {
+            let LatexError(span, _) = convert_content(problem).unwrap_err();
+            assert_eq!(span, expected, "{}", problem);
+        }
+    }
+
+    #[test]
+    fn error_line_and_column() {
+        let source = "x + y\n\\frac{α}{\\asdf}";
+        let error = convert_content(source).unwrap_err();
+        assert_eq!(error.line_and_column(source), (2, 10));
+        assert_eq!(error::line_and_column(source, 0), (1, 1));
+        assert_eq!(error::line_and_column(source, source.len()), (2, 16));
+    }
+
+    #[test]
+    fn error_diagnostic() {
+        let problems = [
+            ("diagnostic_unknown_command", "x + y\n= \\frac{α}{\\asdf}"),
+            (
+                "diagnostic_mismatched_environment",
+                r"\begin{matrix} 1 \end{pmatrix}",
+            ),
+            ("diagnostic_unclosed_group", r"x^{2"),
+        ];
+        for (name, problem) in problems.into_iter() {
+            let error = convert_content(problem).unwrap_err();
+            assert_snapshot!(name, error.to_diagnostic(problem), problem);
+        }
+    }
+
+    #[test]
+    fn parse_to_json() {
+        let arena = Arena::new();
+        let nodes = parse(r"\frac{a}{2} \leq \sqrt{x}", &arena).unwrap();
+        assert_json_snapshot!(nodes);
+
+        let mut converter = LatexToMathML::new(&MathCoreConfig::default()).unwrap();
+        converter
+            .define_macros(r"\newcommand{\R}{\mathbb{R}}")
+            .unwrap();
+        let arena = Arena::new();
+        let nodes = converter.parse(r"\R", &arena).unwrap();
+        assert!(matches!(nodes, [Node::CustomCmd { args: [], .. }]));
+    }
 }
@@ -1,46 +1,126 @@
+use std::collections::HashMap;
 use std::mem;
+use std::ops::Range;
 
 use mathml_renderer::{
     arena::{Arena, Buffer, StringBuilder},
-    ast::Node,
+    ast::{ArraySpec, ColumnSpec, Node, ScriptPair},
     attribute::{
-        Align, FracAttr, MathSpacing, MathVariant, OpAttr, RowAttr, StretchMode, Style,
-        TextTransform,
+        Align, ColumnAlign, FracAttr, LineType, MathSpacing, MathVariant, Notation, OpAttr,
+        PaddedAttr, RowAttr, StretchMode, Style, TextTransform,
     },
-    length::AbsoluteLength,
-    symbol,
+    length::{AbsoluteLength, SpecifiedLength},
+    symbol::{self, ParenOp},
+    visit::{self, Visit},
 };
 
 use crate::{
-    color_defs::get_color,
-    commands::get_negated_op,
+    color_defs::{ColorError, Rgb, get_color, parse_color_expression, parse_color_in_model},
+    commands::{get_command, get_negated_op},
     error::{LatexErrKind, LatexError, Place},
     lexer::Lexer,
+    predefined,
+    spacing::{Atom, apply_spacing},
     specifications::parse_length_specification,
-    token::{TokLoc, Token},
+    token::{CDArrow, Digit, InfixFrac, MathClass, TokLoc, Token},
 };
 
 pub(crate) struct Parser<'arena, 'source> {
-    l: Lexer<'source>,
+    l: Lexer<'arena, 'source>,
     peek: TokLoc<'source>,
     buffer: Buffer,
     arena: &'arena Arena,
     collector: LetterCollector<'arena>,
     is_bold_italic: bool,
+    /// The commands defined by the user so far; later definitions shadow earlier ones.
+    user_cmds: Vec<(&'arena str, UserCmd<'arena>)>,
+    /// The number of parameters of the command whose body is currently being parsed.
+    num_params: u8,
+    /// How the parameters of the command whose body is currently being parsed are used.
+    param_kinds: [ArgKind; 9],
+    /// Whether to recover from errors where possible, instead of returning them.
+    recover: bool,
+    /// The errors which were recovered from.
+    errors: Vec<LatexError<'source>>,
+    /// The number of the last numbered equation.
+    equation_counter: usize,
+    /// The numbers of the equations with a `\label`, indexed by the label.
+    labels: HashMap<String, String>,
+    /// The columns of the innermost environment, if it is laid out as an array.
+    /// Rules like `\hline` can only be used in such an environment.
+    array_layout: Option<ArrayLayout>,
+    /// The `\label`s in this input and their locations, to find duplicates.
+    label_locs: Vec<(&'source str, usize)>,
+    /// The colors defined with `\definecolor` so far; later definitions shadow earlier ones.
+    colors: Vec<(&'arena str, Rgb)>,
 }
 
+/// The columns of an environment which is laid out as an array.
+#[derive(Debug, Clone, Copy)]
+struct ArrayLayout {
+    /// The number of columns, if it is fixed by a column specification.
+    num_columns: Option<usize>,
+    /// The index of the current column.
+    column: usize,
+}
+
+/// A command defined with `\newcommand`, `\renewcommand` or `\def`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct UserCmd<'arena> {
+    num_args: u8,
+    /// The default value of the first argument, if it is optional.
+    default: Option<&'arena Node<'arena>>,
+    body: &'arena Node<'arena>,
+    /// The atom of the first token of the body, for the spacing around the command.
+    atom: Option<Atom>,
+    /// How each argument is used in the body, which determines how it is parsed.
+    arg_kinds: [ArgKind; 9],
+}
+
+/// How an argument of a user-defined command is used in the body of the command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArgKind {
+    /// The argument is math, like in `\newcommand{\sq}[1]{#1^2}`.
+    Math,
+    /// The argument is text, like in `\newcommand{\t}[1]{\text{#1}}`.
+    Text,
+    /// The argument consists of letters which form an identifier, like in
+    /// `\newcommand{\op}[1]{\operatorname{#1}}`.
+    Letters,
+}
+
+/// The commands and colors which were defined before the input, e.g. in a preamble,
+/// in the order of their definition.
+#[derive(Debug, Default)]
+pub(crate) struct Definitions<'arena> {
+    cmds: Vec<(&'arena str, UserCmd<'arena>)>,
+    colors: Vec<(&'arena str, Rgb)>,
+}
+
+impl<'arena> Definitions<'arena> {
+    /// Add a command, which shadows any earlier command with the same name.
+    pub(crate) fn push_cmd(&mut self, name: &'arena str, cmd: UserCmd<'arena>) {
+        self.cmds.push((name, cmd));
+    }
+}
+
+/// The delimiters and the thickness of the rule of a fraction from an infix command.
+type InfixParams = (
+    Option<(&'static ParenOp, &'static ParenOp)>,
+    Option<SpecifiedLength>,
+);
+
 /// A struct for managing the state of the sequence parser.
 #[derive(Debug, Default)]
 struct SequenceState {
     is_colon: bool,
-    is_relation: bool,
 }
 
 impl<'arena, 'source> Parser<'arena, 'source>
 where
     'source: 'arena, // The reference to the source string will live as long as the arena.
 {
-    pub(crate) fn new(l: Lexer<'source>, arena: &'arena Arena) -> Self {
+    pub(crate) fn new(l: Lexer<'arena, 'source>, arena: &'arena Arena) -> Self {
         let input_length = l.input_length;
         let mut p = Parser {
             l,
@@ -49,6 +129,16 @@ where
             arena,
             collector: LetterCollector::Inactive,
             is_bold_italic: false,
+            user_cmds: Vec::new(),
+            num_params: 0,
+            param_kinds: [ArgKind::Math; 9],
+            recover: false,
+            errors: Vec::new(),
+            equation_counter: 0,
+            labels: HashMap::new(),
+            array_layout: None,
+            label_locs: Vec::new(),
+            colors: Vec::new(),
         };
         // Discard the EOF token we just stored in `peek_token`.
         // This loads the first real token into `peek_token`.
@@ -56,6 +146,20 @@ where
         p
     }
 
+    /// Create a parser for input which can use the given commands and colors.
+    pub(crate) fn with_definitions(
+        mut l: Lexer<'arena, 'source>,
+        arena: &'arena Arena,
+        definitions: Definitions<'arena>,
+    ) -> Self {
+        // The lexer has to know the names of the commands before it reads the first token.
+        l.user_cmds = definitions.cmds.iter().map(|(name, _)| *name).collect();
+        let mut p = Parser::new(l, arena);
+        p.user_cmds = definitions.cmds;
+        p.colors = definitions.colors;
+        p
+    }
+
     fn next_token(&mut self) -> TokLoc<'source> {
         if matches!(self.collector, LetterCollector::Collecting) {
             let first_loc = self.peek.location();
@@ -104,9 +208,122 @@ where
 
     pub(crate) fn parse(&mut self) -> Result<&'arena [&'arena Node<'arena>], LatexError<'source>> {
         let nodes = self.parse_sequence(Token::EOF, true)?;
+        if nodes.iter().any(|node| is_tag(node)) {
+            // A `\tag` outside of any environment needs a table to be placed in.
+            self.register_labels(&nodes)?;
+            let table = self.commit(Node::Table {
+                content: self.arena.push_slice(&nodes),
+                align: Align::Center,
+                attr: Some(FracAttr::DisplayStyleTrue),
+            });
+            self.check_labels_numbered()?;
+            return Ok(self.arena.push_slice(&[table]));
+        }
+        self.check_labels_numbered()?;
         Ok(self.arena.push_slice(&nodes))
     }
 
+    /// Report the `\label`s in this input which did not get a number, because the row
+    /// they are in has no tag, like in `align*` or after `\notag`.
+    ///
+    /// `\eqref` can't refer to them, but they are kept as anchors.
+    fn check_labels_numbered(&mut self) -> Result<(), LatexError<'source>> {
+        let unnumbered: Vec<_> = self
+            .label_locs
+            .iter()
+            .filter(|(label, _)| !self.labels.contains_key(*label))
+            .copied()
+            .collect();
+        for (label, loc) in unnumbered {
+            let error = LatexError(self.l.token_span(loc), LatexErrKind::UnnumberedLabel(label));
+            if !self.recover {
+                return Err(error);
+            }
+            self.errors.push(error);
+        }
+        Ok(())
+    }
+
+    /// Continue the numbering of equations after the given number.
+    pub(crate) fn set_equation_counter(&mut self, counter: usize) {
+        self.equation_counter = counter;
+    }
+
+    /// The number of the last numbered equation.
+    pub(crate) fn equation_counter(&self) -> usize {
+        self.equation_counter
+    }
+
+    /// Make the given labels available to `\eqref`.
+    pub(crate) fn set_labels(&mut self, labels: HashMap<String, String>) {
+        self.labels = labels;
+    }
+
+    /// The labels given so far, including the ones from this input.
+    pub(crate) fn take_labels(&mut self) -> HashMap<String, String> {
+        mem::take(&mut self.labels)
+    }
+
+    /// Parse the whole input as the body of a command which takes `num_args` arguments.
+    pub(crate) fn parse_cmd_body(
+        &mut self,
+        num_args: u8,
+    ) -> Result<UserCmd<'arena>, LatexError<'source>> {
+        self.num_params = num_args;
+        self.param_kinds = [ArgKind::Math; 9];
+        let atom = self.atom_of(self.peek.token());
+        let content = self.parse_sequence(Token::EOF, true)?;
+        Ok(UserCmd {
+            num_args,
+            default: None,
+            body: self.node_vec_to_node(content),
+            atom,
+            arg_kinds: self.param_kinds,
+        })
+    }
+
+    /// The command with the given name which was defined by the user, if any.
+    fn user_cmd(&self, name: &str) -> Option<UserCmd<'arena>> {
+        self.user_cmds
+            .iter()
+            .rev()
+            .find(|(n, _)| *n == name)
+            .map(|(_, cmd)| *cmd)
+    }
+
+    /// The atom which is produced by the given token, if we know its class.
+    ///
+    /// Unlike `Atom::from_token`, this also knows the commands defined by the user.
+    fn atom_of(&self, token: &Token) -> Option<Atom> {
+        match token {
+            Token::UserCmd(name) => self.user_cmd(name).and_then(|cmd| cmd.atom),
+            Token::CustomCmd(_, predefined) => Atom::from_node(predefined),
+            _ => Atom::from_token(token),
+        }
+    }
+
+    /// Recover from unknown commands, unknown environments and unbalanced groups,
+    /// instead of returning the error.
+    ///
+    /// The errors can be retrieved with `take_errors`.
+    pub(crate) fn enable_recovery(&mut self) {
+        self.recover = true;
+    }
+
+    /// Take the errors which the parser has recovered from so far.
+    pub(crate) fn take_errors(&mut self) -> Vec<LatexError<'source>> {
+        mem::take(&mut self.errors)
+    }
+
+    /// Consume the parser and return the commands and colors which were defined so far,
+    /// including the ones it was created with.
+    pub(crate) fn into_definitions(self) -> Definitions<'arena> {
+        Definitions {
+            cmds: self.user_cmds,
+            colors: self.colors,
+        }
+    }
+
     /// Parse a sequence of tokens until the given end token is encountered.
     ///
     /// If `eof_as_end_token` is `false`, an error is returned if the input ends before the end
@@ -118,44 +335,188 @@ where
         &mut self,
         end_token: Token<'static>,
         eof_as_end_token: bool,
+    ) -> Result<Vec<&'arena Node<'arena>>, LatexError<'source>> {
+        self.parse_sequence_until(
+            |token| token.is_same_kind_as(&end_token),
+            end_token,
+            eof_as_end_token,
+        )
+    }
+
+    /// Like `parse_sequence`, but the sequence ends at any token for which `is_end`
+    /// returns `true`. `end_token` is only used in error messages.
+    fn parse_sequence_until(
+        &mut self,
+        is_end: impl Fn(&Token) -> bool,
+        end_token: Token<'static>,
+        eof_as_end_token: bool,
     ) -> Result<Vec<&'arena Node<'arena>>, LatexError<'source>> {
         let mut nodes = Vec::new();
+        // The atoms of the nodes, for the spacing between them.
+        let mut atoms = Vec::new();
+        // The numerator and the fraction parameters, after an infix command like `\over`.
+        let mut infix = None;
+        // The index of the first node in the current cell, if the sequence is a table.
+        let mut cell_start = 0;
         let mut sequence_state = SequenceState::default();
 
         // Because we don't want to consume the end token, we just peek here.
-        while !self.peek.token().is_same_kind_as(&end_token) {
+        while !is_end(self.peek.token()) {
             let cur_tokloc = self.next_token();
             if matches!(cur_tokloc.token(), Token::EOF) {
                 if eof_as_end_token {
                     break;
+                } else if self.recover && matches!(end_token, Token::GroupEnd) {
+                    self.errors.push(LatexError(
+                        self.l.token_span(cur_tokloc.location()),
+                        LatexErrKind::UnclosedGroup(end_token),
+                    ));
+                    break;
                 } else {
                     // When the input ends without the closing token.
                     return Err(LatexError(
-                        cur_tokloc.location(),
+                        self.l.token_span(cur_tokloc.location()),
                         LatexErrKind::UnclosedGroup(end_token),
                     ));
                 }
             }
+            // Command definitions don't produce any output.
+            if matches!(
+                cur_tokloc.token(),
+                Token::NewCommand | Token::RenewCommand | Token::Def
+            ) {
+                self.parse_cmd_definition(cur_tokloc)?;
+                continue;
+            }
+            if matches!(cur_tokloc.token(), Token::DefineColor) {
+                self.parse_color_definition()?;
+                continue;
+            }
+            // An infix command turns everything before it into the numerator.
+            if let Token::Infix(kind) = cur_tokloc.token() {
+                if infix.is_some() {
+                    return Err(LatexError(
+                        self.l.token_span(cur_tokloc.location()),
+                        LatexErrKind::AmbiguousInfix,
+                    ));
+                }
+                let params = self.parse_infix_params(*kind)?;
+                let num = apply_spacing(
+                    self.arena,
+                    nodes.split_off(cell_start),
+                    &atoms.split_off(cell_start),
+                );
+                infix = Some((num, params));
+                continue;
+            }
+            // Every cell of a table is a group of its own.
+            if matches!(cur_tokloc.token(), Token::Ampersand | Token::NewLine) {
+                if let Some((num, params)) = infix.take() {
+                    self.finish_infix_frac(&mut nodes, &mut atoms, cell_start, num, params);
+                }
+                cell_start = nodes.len() + 1;
+            }
+            let atom = self.atom_of(cur_tokloc.token());
             // Parse the token.
             let target = self.parse_token(cur_tokloc, false, Some(&mut sequence_state))?;
 
             // Check if there are any superscripts or subscripts following the parsed node.
             let bounds = self.get_bounds()?;
+            atoms.push(if matches!(bounds, Bounds(None, None)) {
+                atom
+            } else {
+                atom.map(Atom::with_scripts)
+            });
 
             // If there are superscripts or subscripts, we need to wrap the node we just got into
             // one of the node types for superscripts and subscripts.
-            let node = self.commit(match bounds {
-                Bounds(Some(sub), Some(sup)) => Node::SubSup { target, sub, sup },
-                Bounds(Some(symbol), None) => Node::Subscript { target, symbol },
-                Bounds(None, Some(symbol)) => Node::Superscript { target, symbol },
-                Bounds(None, None) => {
-                    nodes.push(target);
-                    continue;
-                }
-            });
-            nodes.push(node);
+            let node = match bounds {
+                Bounds(Some(sub), Some(sup)) => self.commit(Node::SubSup { target, sub, sup }),
+                Bounds(Some(symbol), None) => self.commit(Node::Subscript { target, symbol }),
+                Bounds(None, Some(symbol)) => self.commit(Node::Superscript { target, symbol }),
+                Bounds(None, None) => target,
+            };
+
+            // `\indices` attaches tensor indices to the node before it.
+            if matches!(self.peek.token(), Token::Indices) {
+                self.next_token(); // Discard the `\indices` token.
+                let post = self.parse_tensor_indices()?;
+                nodes.push(self.commit(self.multiscript(node, &post, &[])));
+            } else {
+                nodes.push(node);
+            }
+        }
+        if let Some((num, params)) = infix {
+            self.finish_infix_frac(&mut nodes, &mut atoms, cell_start, num, params);
+        }
+        Ok(apply_spacing(self.arena, nodes, &atoms))
+    }
+
+    /// Replace the nodes from `start` onwards with a fraction, of which they are the denominator.
+    fn finish_infix_frac(
+        &self,
+        nodes: &mut Vec<&'arena Node<'arena>>,
+        atoms: &mut Vec<Option<Atom>>,
+        start: usize,
+        num: Vec<&'arena Node<'arena>>,
+        (delims, lt): InfixParams,
+    ) {
+        let den = apply_spacing(self.arena, nodes.split_off(start), &atoms.split_off(start));
+        let frac = Node::Frac {
+            num: self.node_vec_to_node(num),
+            den: self.node_vec_to_node(den),
+            lt,
+            attr: None,
+        };
+        let node = match delims {
+            Some((open, close)) => Node::Fenced {
+                open,
+                close,
+                content: self.commit(frac),
+                style: None,
+            },
+            None => frac,
+        };
+        nodes.push(self.commit(node));
+        atoms.push(None);
+    }
+
+    /// Parse the parameters which follow an infix command like `\abovewithdelims`,
+    /// and return the delimiters and the thickness of the rule.
+    fn parse_infix_params(&mut self, kind: InfixFrac) -> Result<InfixParams, LatexError<'source>> {
+        let delims = match kind {
+            InfixFrac::Over | InfixFrac::Atop | InfixFrac::Above => None,
+            InfixFrac::Choose => Some((symbol::LEFT_PARENTHESIS, symbol::RIGHT_PARENTHESIS)),
+            InfixFrac::Brace => Some((symbol::LEFT_CURLY_BRACKET, symbol::RIGHT_CURLY_BRACKET)),
+            InfixFrac::Brack => Some((symbol::LEFT_SQUARE_BRACKET, symbol::RIGHT_SQUARE_BRACKET)),
+            InfixFrac::OverWithDelims | InfixFrac::AtopWithDelims | InfixFrac::AboveWithDelims => {
+                Some((self.parse_delimiter()?, self.parse_delimiter()?))
+            }
+        };
+        let lt = match kind {
+            InfixFrac::Over | InfixFrac::OverWithDelims => None,
+            InfixFrac::Above | InfixFrac::AboveWithDelims => Some(self.parse_dimension()?),
+            _ => Some(AbsoluteLength::from_twip(0).into()),
+        };
+        Ok((delims, lt))
+    }
+
+    /// Parse a delimiter, where `.` stands for no delimiter.
+    fn parse_delimiter(&mut self) -> Result<&'static ParenOp, LatexError<'source>> {
+        let TokLoc(loc, next_token) = self.next_token();
+        match next_token {
+            Token::Delimiter(paren) => Ok(paren),
+            Token::SquareBracketOpen => Ok(symbol::LEFT_SQUARE_BRACKET),
+            Token::SquareBracketClose => Ok(symbol::RIGHT_SQUARE_BRACKET),
+            Token::Letter(symbol::FULL_STOP) => Ok(symbol::NULL),
+            _ => Err(LatexError(
+                self.l.token_span(loc),
+                LatexErrKind::UnexpectedToken {
+                    expected: &Token::Delimiter(symbol::NULL),
+                    got: next_token,
+                },
+            )),
         }
-        Ok(nodes)
     }
 
     /// Put the node onto the heap in the arena and return a reference to it.
@@ -220,9 +581,6 @@ where
             Token::Letter(x) => Node::SingleLetterIdent(x, false),
             Token::UprightLetter(x) => Node::SingleLetterIdent(x, true),
             Token::Relation(relation) => {
-                if let Some(state) = sequence_state {
-                    state.is_relation = true;
-                };
                 if prev_state.is_colon && matches!(relation, symbol::IDENTICAL_TO) {
                     Node::OperatorWithSpacing {
                         op: relation.into(),
@@ -233,19 +591,42 @@ where
                     Node::Operator(relation.into(), None)
                 }
             }
-            Token::BinaryOp(binary_op) => Node::Operator(
-                binary_op.into(),
-                if prev_state.is_relation {
-                    Some(OpAttr::FormPrefix)
-                } else {
-                    None
-                },
-            ),
+            Token::BinaryOp(binary_op) => Node::Operator(binary_op.into(), None),
             Token::OpGreaterThan => Node::OpGreaterThan,
             Token::OpLessThan => Node::OpLessThan,
             Token::OpAmpersand => Node::OpAmpersand,
             Token::Function(fun) => Node::MultiLetterIdent(fun),
             Token::Space(space) => Node::Space(space),
+            Token::Hspace => {
+                // The starred version of `\hspace` is not removed at line breaks,
+                // which makes no difference for us.
+                if matches!(
+                    self.peek.token(),
+                    Token::Relation(symbol::ASTERISK_OPERATOR)
+                ) {
+                    self.next_token();
+                }
+                Node::Kern(self.parse_length_arg()?)
+            }
+            Token::Kern => Node::Kern(self.parse_dimension()?),
+            Token::Skip => Node::Kern(self.parse_glue()?),
+            Token::Rule => {
+                let raise = if matches!(self.peek.token(), Token::SquareBracketOpen) {
+                    self.next_token(); // Discard the opening token.
+                    let raise = self.parse_dimension()?;
+                    self.expect_token(&Token::SquareBracketClose)?;
+                    Some(raise)
+                } else {
+                    None
+                };
+                let width = self.parse_length_arg()?;
+                let height = self.parse_length_arg()?;
+                Node::Rule {
+                    width,
+                    height,
+                    raise,
+                }
+            }
             Token::NonBreakingSpace | Token::Whitespace => Node::Text("\u{A0}"),
             Token::Sqrt => {
                 let next = self.next_token();
@@ -282,21 +663,23 @@ where
                 let open = match self.parse_next(true)? {
                     Node::StretchableOp(op, _) => *op,
                     Node::Row { nodes: [], .. } => symbol::NULL,
-                    _ => return Err(LatexError(0, LatexErrKind::UnexpectedEOF)),
+                    _ => return Err(LatexError(0..0, LatexErrKind::UnexpectedEOF)),
                 };
                 let close = match self.parse_next(true)? {
                     Node::StretchableOp(op, _) => *op,
                     Node::Row { nodes: [], .. } => symbol::NULL,
-                    _ => return Err(LatexError(0, LatexErrKind::UnexpectedEOF)),
+                    _ => return Err(LatexError(0..0, LatexErrKind::UnexpectedEOF)),
                 };
                 self.check_lbrace()?;
                 let (loc, length) = self.parse_text_group()?;
                 let lt = match length.trim() {
                     "" => None,
-                    decimal => Some(
-                        parse_length_specification(decimal)
-                            .map_err(|_| LatexError(loc, LatexErrKind::ExpectedLength(decimal)))?,
-                    ),
+                    decimal => Some(parse_length_specification(decimal).map_err(|_| {
+                        LatexError(
+                            self.l.token_span(loc),
+                            LatexErrKind::ExpectedLength(decimal),
+                        )
+                    })?),
                 };
                 let style = match self.parse_next(true)? {
                     Node::Number(num) => match num.as_bytes() {
@@ -304,10 +687,10 @@ where
                         b"1" => Some(Style::TextStyle),
                         b"2" => Some(Style::ScriptStyle),
                         b"3" => Some(Style::ScriptScriptStyle),
-                        _ => return Err(LatexError(0, LatexErrKind::UnexpectedEOF)),
+                        _ => return Err(LatexError(0..0, LatexErrKind::UnexpectedEOF)),
                     },
                     Node::Row { nodes: [], .. } => None,
-                    _ => return Err(LatexError(0, LatexErrKind::UnexpectedEOF)),
+                    _ => return Err(LatexError(0..0, LatexErrKind::UnexpectedEOF)),
                 };
                 let num = self.parse_next(true)?;
                 let den = self.parse_next(true)?;
@@ -327,6 +710,40 @@ where
                     Node::UnderOp(op, target)
                 }
             }
+            Token::ExtensibleArrow(op) => {
+                // The label below the arrow is given as an optional argument.
+                let below = if matches!(self.peek.token(), Token::SquareBracketOpen) {
+                    self.next_token(); // Discard the opening token.
+                    let below = self.parse_sequence(Token::SquareBracketClose, false)?;
+                    self.next_token(); // Discard the closing token.
+                    Some(self.node_vec_to_node(below))
+                } else {
+                    None
+                };
+                let above = self.parse_next(true)?;
+                let target = self.commit(Node::Operator(op, Some(OpAttr::Extensible)));
+                let over = self.extensible_arrow_label(above);
+                let arrow = match below {
+                    Some(below) => Node::UnderOver {
+                        target,
+                        under: self.extensible_arrow_label(below),
+                        over,
+                    },
+                    None => Node::Overset {
+                        symbol: over,
+                        target,
+                    },
+                };
+                let nodes = [
+                    &Node::Space("0.2778"),
+                    self.commit(arrow),
+                    &Node::Space("0.2778"),
+                ];
+                Node::Row {
+                    nodes: self.arena.push_slice(&nodes),
+                    attr: RowAttr::None,
+                }
+            }
             Token::Overset | Token::Underset => {
                 let symbol = self.parse_next(true)?;
                 let target = self.parse_next(true)?;
@@ -366,11 +783,17 @@ where
                 }
             }
             Token::BigOp(op) => {
-                let target = if matches!(self.peek.token(), Token::Limits) {
-                    self.next_token(); // Discard the limits token.
-                    self.commit(Node::Operator(op.into(), Some(OpAttr::NoMovableLimits)))
-                } else {
-                    self.commit(Node::Operator(op.into(), None))
+                let target = match self.peek.token() {
+                    Token::Limits => {
+                        self.next_token(); // Discard the limits token.
+                        self.commit(Node::Operator(op.into(), Some(OpAttr::NoMovableLimits)))
+                    }
+                    Token::NoLimits => {
+                        self.next_token(); // Discard the nolimits token.
+                        let target = self.commit(Node::Operator(op.into(), None));
+                        return self.scripts(target);
+                    }
+                    _ => self.commit(Node::Operator(op.into(), None)),
                 };
                 match self.get_bounds()? {
                     Bounds(Some(under), Some(over)) => Node::UnderOver {
@@ -396,10 +819,83 @@ where
                     Node::MultiLetterIdent(lim)
                 }
             }
+            Token::Substack => {
+                self.check_lbrace()?;
+                self.next_token(); // Discard the opening brace.
+                let content = self.parse_sequence(Token::GroupEnd, false)?;
+                self.next_token(); // Discard the closing brace.
+                Node::Table {
+                    content: self.arena.push_slice(&content),
+                    align: Align::Center,
+                    attr: Some(FracAttr::SubstackStyle),
+                }
+            }
             Token::Slashed => {
                 let node = self.parse_next(true)?;
                 Node::Slashed(node)
             }
+            Token::Phantom(attr) => {
+                let content = self.parse_next(true)?;
+                let phantom = Node::Phantom(content);
+                match attr {
+                    Some(attr) => Node::Padded {
+                        attr,
+                        content: self.commit(phantom),
+                    },
+                    None => phantom,
+                }
+            }
+            Token::Smash => {
+                // `\smash[t]` only hides the height and `\smash[b]` only the depth.
+                let attr = if matches!(self.peek.token(), Token::SquareBracketOpen) {
+                    self.next_token(); // Discard the opening token.
+                    let TokLoc(loc, token) = self.next_token();
+                    let attr = match token {
+                        Token::Letter('t') => PaddedAttr::ZeroHeight,
+                        Token::Letter('b') => PaddedAttr::ZeroDepth,
+                        _ => {
+                            return Err(LatexError(
+                                self.l.token_span(loc),
+                                LatexErrKind::UnexpectedToken {
+                                    expected: &Token::Letter('t'),
+                                    got: token,
+                                },
+                            ));
+                        }
+                    };
+                    let TokLoc(loc, token) = self.next_token();
+                    if !matches!(token, Token::SquareBracketClose) {
+                        return Err(LatexError(
+                            self.l.token_span(loc),
+                            LatexErrKind::UnexpectedToken {
+                                expected: &Token::SquareBracketClose,
+                                got: token,
+                            },
+                        ));
+                    }
+                    attr
+                } else {
+                    PaddedAttr::ZeroHeightDepth
+                };
+                let content = self.parse_next(true)?;
+                Node::Padded { attr, content }
+            }
+            Token::Lap(attr) => {
+                let cmd = match attr {
+                    PaddedAttr::LeftOverlap => "\\llap",
+                    PaddedAttr::CenterOverlap => "\\clap",
+                    _ => "\\rlap",
+                };
+                let text = self.parse_text_arg(loc, cmd)?;
+                Node::Padded {
+                    attr,
+                    content: self.commit(Node::Text(text)),
+                }
+            }
+            Token::MathLap(attr) => {
+                let content = self.parse_next(true)?;
+                Node::Padded { attr, content }
+            }
             Token::Not => {
                 // `\not` has to be followed by something:
                 match self.next_token().into_token() {
@@ -420,7 +916,7 @@ where
                     }
                     _ => {
                         return Err(LatexError(
-                            loc,
+                            self.l.token_span(loc),
                             LatexErrKind::CannotBeUsedHere {
                                 got: cur_token,
                                 correct_place: Place::BeforeSomeOps,
@@ -455,13 +951,11 @@ where
                         Bounds(None, None) => return Ok(target),
                     }
                 } else {
-                    let target = self.commit(Node::Operator(int.into(), None));
-                    match self.get_bounds()? {
-                        Bounds(Some(sub), Some(sup)) => Node::SubSup { target, sub, sup },
-                        Bounds(Some(symbol), None) => Node::Subscript { target, symbol },
-                        Bounds(None, Some(symbol)) => Node::Superscript { target, symbol },
-                        Bounds(None, None) => return Ok(target),
+                    if matches!(self.peek.token(), Token::NoLimits) {
+                        self.next_token(); // Discard the nolimits token.
                     }
+                    let target = self.commit(Node::Operator(int.into(), None));
+                    return self.scripts(target);
                 }
             }
             Token::Colon => match &self.peek.token() {
@@ -486,7 +980,11 @@ where
                 },
             },
             Token::GroupBegin => {
-                let content = self.parse_sequence(Token::GroupEnd, false)?;
+                let mut content = self.parse_sequence(Token::GroupEnd, false)?;
+                if matches!(self.peek.token(), Token::EOF) {
+                    // We only get here if we recovered from an unclosed group.
+                    content.insert(0, self.commit(Node::Error("{")));
+                }
                 self.next_token(); // Discard the closing token.
                 return Ok(self.node_vec_to_node(content));
             }
@@ -506,7 +1004,7 @@ where
                     Token::Letter(symbol::FULL_STOP) => symbol::NULL,
                     _ => {
                         return Err(LatexError(
-                            loc,
+                            self.l.token_span(loc),
                             LatexErrKind::MissingParenthesis {
                                 location: &Token::Left,
                                 got: next_token,
@@ -524,7 +1022,7 @@ where
                     Token::Letter(symbol::FULL_STOP) => symbol::NULL,
                     _ => {
                         return Err(LatexError(
-                            loc,
+                            self.l.token_span(loc),
                             LatexErrKind::MissingParenthesis {
                                 location: &Token::Right,
                                 got: next_token,
@@ -547,7 +1045,7 @@ where
                     Token::SquareBracketClose => symbol::RIGHT_SQUARE_BRACKET,
                     _ => {
                         return Err(LatexError(
-                            loc,
+                            self.l.token_span(loc),
                             LatexErrKind::UnexpectedToken {
                                 expected: &Token::Delimiter(symbol::NULL),
                                 got: next_token,
@@ -565,7 +1063,7 @@ where
                     Token::SquareBracketClose => symbol::RIGHT_SQUARE_BRACKET,
                     _ => {
                         return Err(LatexError(
-                            loc,
+                            self.l.token_span(loc),
                             LatexErrKind::UnexpectedToken {
                                 expected: &Token::Delimiter(symbol::NULL),
                                 got: next_token,
@@ -578,26 +1076,137 @@ where
             Token::Begin => {
                 self.check_lbrace()?;
                 // Read the environment name.
-                let env_name = self.parse_text_group()?.1;
-                let content = self.parse_sequence(Token::End, false)?;
+                let env_name = self.l.read_length_or_env_name();
+                // Arrows like `@>>>` are only recognized in a `CD` environment, so the
+                // lexer has to know about it before it reads the first token of the content.
+                let old_cd_mode = mem::replace(&mut self.l.cd_mode, env_name == Some("CD"));
+                // Discard the opening token (which is still stored as `peek`).
+                let opening_loc = self.next_token().location();
+                let Some(env_name) = env_name else {
+                    return Err(LatexError(
+                        self.l.token_span(opening_loc),
+                        LatexErrKind::UnparsableEnvName,
+                    ));
+                };
+                // The column specifications are not subject to letter collection.
+                let old_collector = mem::replace(&mut self.collector, LetterCollector::Inactive);
+                let array_spec = match env_name {
+                    "array" => Some(self.parse_array_spec()?),
+                    _ => None,
+                };
+                let column_align = match env_name {
+                    "matrix*" | "pmatrix*" | "bmatrix*" | "Bmatrix*" | "vmatrix*" | "Vmatrix*" => {
+                        Some(self.parse_column_align()?)
+                    }
+                    "subarray" => Some(self.parse_subarray_spec()?),
+                    _ => None,
+                };
+                if matches!(env_name, "alignat" | "alignat*" | "alignedat") {
+                    // The number of column pairs is implied by the content, so we skip it.
+                    self.check_lbrace()?;
+                    self.parse_text_group()?;
+                }
+                self.collector = old_collector;
+                let layout = match env_name {
+                    "array" => Some(ArrayLayout {
+                        num_columns: array_spec.map(|spec| spec.columns.len()),
+                        column: 0,
+                    }),
+                    "matrix*" | "pmatrix*" | "bmatrix*" | "Bmatrix*" | "vmatrix*" | "Vmatrix*" => {
+                        Some(ArrayLayout {
+                            num_columns: None,
+                            column: 0,
+                        })
+                    }
+                    _ => None,
+                };
+                let old_layout = mem::replace(&mut self.array_layout, layout);
+                let content = if env_name == "CD" {
+                    self.parse_cd_content()
+                } else if matches!(env_name, "cases*" | "dcases*" | "rcases*" | "drcases*") {
+                    self.parse_text_cases_content()
+                } else {
+                    self.parse_sequence(Token::End, false)
+                };
+                self.l.cd_mode = old_cd_mode;
+                self.array_layout = old_layout;
+                let mut content = content?;
+                match env_name {
+                    "align" | "alignat" | "flalign" | "gather" => self.number_rows(&mut content),
+                    "equation" | "multline" => self.number_once(&mut content),
+                    _ => {}
+                }
+                let is_tagged = content.iter().any(|node| is_tag(node));
+                if is_tagged {
+                    self.register_labels(&content)?;
+                }
                 let content = self.arena.push_slice(&content);
                 let end_token_loc = self.next_token().location();
                 let node = match env_name {
-                    "align" | "align*" | "aligned" => Node::Table {
+                    "array" => Node::Array {
+                        content,
+                        array_spec: array_spec.expect("the column specification is parsed above"),
+                    },
+                    "align" | "align*" | "aligned" | "split" => Node::Table {
                         content,
                         align: Align::Alternating,
                         attr: Some(FracAttr::DisplayStyleTrue),
                     },
-                    "cases" => {
-                        let align = Align::Left;
+                    "alignat" | "alignat*" | "alignedat" => Node::Table {
+                        content,
+                        align: Align::AlternatingTight,
+                        attr: Some(FracAttr::DisplayStyleTrue),
+                    },
+                    "flalign" | "flalign*" => Node::Table {
+                        content,
+                        align: Align::AlternatingFullWidth,
+                        attr: Some(FracAttr::DisplayStyleTrue),
+                    },
+                    "gather" | "gather*" | "gathered" => Node::Table {
+                        content,
+                        align: Align::Center,
+                        attr: Some(FracAttr::DisplayStyleTrue),
+                    },
+                    "multline" | "multline*" => Node::Table {
+                        content,
+                        align: Align::Multline,
+                        attr: Some(FracAttr::DisplayStyleTrue),
+                    },
+                    "equation" | "equation*" if is_tagged => Node::Table {
+                        content,
+                        align: Align::Center,
+                        attr: Some(FracAttr::DisplayStyleTrue),
+                    },
+                    "CD" => Node::Table {
+                        content,
+                        align: Align::Center,
+                        attr: Some(FracAttr::DisplayStyleTrue),
+                    },
+                    "equation" | "equation*" => Node::Row {
+                        nodes: content,
+                        attr: RowAttr::Style(Style::DisplayStyle),
+                    },
+                    cases_variant @ ("cases" | "dcases" | "rcases" | "drcases" | "cases*"
+                    | "dcases*" | "rcases*" | "drcases*") => {
+                        let cases_variant = cases_variant.trim_end_matches('*');
+                        // The `d` variants typeset their rows in display style.
+                        let attr = cases_variant
+                            .starts_with('d')
+                            .then_some(FracAttr::DisplayStyleTrue);
                         let content = self.commit(Node::Table {
                             content,
-                            align,
-                            attr: None,
+                            align: Align::Left,
+                            attr,
                         });
+                        // The `r` variants have the brace on the right.
+                        let (open, close) = if cases_variant.ends_with("rcases") {
+                            (symbol::NULL, symbol::RIGHT_CURLY_BRACKET)
+                        } else {
+                            (symbol::LEFT_CURLY_BRACKET, symbol::NULL)
+                        };
                         Node::Fenced {
-                            open: symbol::LEFT_CURLY_BRACKET,
-                            close: symbol::NULL,
+                            open,
+                            close,
                             content,
                             style: None,
                         }
@@ -607,43 +1216,90 @@ where
                         align: Align::Center,
                         attr: None,
                     },
-                    matrix_variant
-                    @ ("pmatrix" | "bmatrix" | "Bmatrix" | "vmatrix" | "Vmatrix") => {
-                        let align = Align::Center;
-                        let (open, close) = match matrix_variant {
-                            "pmatrix" => (symbol::LEFT_PARENTHESIS, symbol::RIGHT_PARENTHESIS),
-                            "bmatrix" => {
-                                (symbol::LEFT_SQUARE_BRACKET, symbol::RIGHT_SQUARE_BRACKET)
-                            }
-                            "Bmatrix" => (symbol::LEFT_CURLY_BRACKET, symbol::RIGHT_CURLY_BRACKET),
-                            "vmatrix" => (symbol::VERTICAL_LINE, symbol::VERTICAL_LINE),
-                            "Vmatrix" => {
-                                (symbol::DOUBLE_VERTICAL_LINE, symbol::DOUBLE_VERTICAL_LINE)
-                            }
+                    "subarray" => Node::Table {
+                        content,
+                        align: match column_align {
+                            Some(ColumnAlign::Left) => Align::Left,
+                            _ => Align::Center,
+                        },
+                        attr: Some(FracAttr::SubstackStyle),
+                    },
+                    "smallmatrix" => Node::Table {
+                        content,
+                        align: Align::Center,
+                        attr: Some(FracAttr::SmallMatrixStyle),
+                    },
+                    "matrix*" => self.aligned_matrix(content, column_align),
+                    matrix_variant @ ("pmatrix" | "bmatrix" | "Bmatrix" | "vmatrix" | "Vmatrix"
+                    | "pmatrix*" | "bmatrix*" | "Bmatrix*" | "vmatrix*"
+                    | "Vmatrix*" | "psmallmatrix" | "bsmallmatrix"
+                    | "Bsmallmatrix" | "vsmallmatrix" | "Vsmallmatrix") => {
+                        let (open, close) = match matrix_variant
+                            .trim_end_matches('*')
+                            .trim_end_matches("smallmatrix")
+                            .trim_end_matches("matrix")
+                        {
+                            "p" => (symbol::LEFT_PARENTHESIS, symbol::RIGHT_PARENTHESIS),
+                            "b" => (symbol::LEFT_SQUARE_BRACKET, symbol::RIGHT_SQUARE_BRACKET),
+                            "B" => (symbol::LEFT_CURLY_BRACKET, symbol::RIGHT_CURLY_BRACKET),
+                            "v" => (symbol::VERTICAL_LINE, symbol::VERTICAL_LINE),
+                            "V" => (symbol::DOUBLE_VERTICAL_LINE, symbol::DOUBLE_VERTICAL_LINE),
                             // SAFETY: `matrix_variant` is one of the strings above.
                             _ => unsafe { std::hint::unreachable_unchecked() },
                         };
-                        let attr = None;
+                        let content = if matrix_variant.ends_with('*') {
+                            self.aligned_matrix(content, column_align)
+                        } else if matrix_variant.ends_with("smallmatrix") {
+                            Node::Table {
+                                content,
+                                align: Align::Center,
+                                attr: Some(FracAttr::SmallMatrixStyle),
+                            }
+                        } else {
+                            Node::Table {
+                                content,
+                                align: Align::Center,
+                                attr: None,
+                            }
+                        };
                         Node::Fenced {
                             open,
                             close,
-                            content: self.commit(Node::Table {
-                                content,
-                                align,
-                                attr,
-                            }),
+                            content: self.commit(content),
                             style: None,
                         }
                     }
                     _ => {
-                        return Err(LatexError(loc, LatexErrKind::UnknownEnvironment(env_name)));
+                        let error = LatexError(
+                            self.l.token_span(loc),
+                            LatexErrKind::UnknownEnvironment(env_name),
+                        );
+                        let mut builder = self.buffer.get_builder();
+                        builder.push_str("\\begin{");
+                        builder.push_str(env_name);
+                        builder.push_char('}');
+                        let text = builder.finish(self.arena);
+                        let error_node = self.recover(error, text)?;
+                        // Render the content like a matrix after the error.
+                        let nodes = [
+                            self.commit(error_node),
+                            self.commit(Node::Table {
+                                content,
+                                align: Align::Center,
+                                attr: None,
+                            }),
+                        ];
+                        Node::Row {
+                            nodes: self.arena.push_slice(&nodes),
+                            attr: RowAttr::None,
+                        }
                     }
                 };
                 self.check_lbrace()?;
                 let end_name = self.parse_text_group()?.1;
                 if end_name != env_name {
                     return Err(LatexError(
-                        end_token_loc,
+                        self.l.token_span(end_token_loc),
                         LatexErrKind::MismatchedEnvironment {
                             expected: env_name,
                             got: end_name,
@@ -653,6 +1309,70 @@ where
 
                 node
             }
+            // The class only affects the spacing, which is applied to the whole sequence.
+            Token::MathClass(MathClass::Op) => {
+                // Like a big operator, `\mathop` takes its scripts as limits.
+                let content = self.parse_next(true)?;
+                let limits = match self.peek.token() {
+                    Token::Limits => true,
+                    Token::NoLimits => {
+                        self.next_token(); // Discard the nolimits token.
+                        return self.scripts(content);
+                    }
+                    _ => false,
+                };
+                if limits {
+                    self.next_token(); // Discard the limits token.
+                }
+                let bounds = self.get_bounds()?;
+                if matches!(bounds, Bounds(None, None)) {
+                    return Ok(content);
+                }
+                let target = match content {
+                    Node::Operator(op, None) if limits => {
+                        self.commit(Node::Operator(*op, Some(OpAttr::NoMovableLimits)))
+                    }
+                    // Without `\limits`, the limits only stay in place in display style,
+                    // which identifiers can't do in MathML.
+                    Node::SingleLetterIdent(ch, is_normal) if !limits => {
+                        let ch = if *is_normal {
+                            *ch
+                        } else {
+                            TextTransform::Italic.transform(*ch, false)
+                        };
+                        let mut builder = self.buffer.get_builder();
+                        builder.push_char(ch);
+                        let letters = builder.finish(self.arena);
+                        self.commit(Node::IdentOp(letters))
+                    }
+                    Node::MultiLetterIdent(letters)
+                    | Node::TextTransform {
+                        tf: MathVariant::Normal,
+                        content: Node::MultiLetterIdent(letters) | Node::CollectedLetters(letters),
+                    } if !limits => self.commit(Node::IdentOp(letters)),
+                    Node::TextTransform {
+                        tf: MathVariant::Normal,
+                        content: Node::SingleLetterIdent(ch, _),
+                    } if !limits => {
+                        let mut builder = self.buffer.get_builder();
+                        builder.push_char(*ch);
+                        let letters = builder.finish(self.arena);
+                        self.commit(Node::IdentOp(letters))
+                    }
+                    _ => content,
+                };
+                match bounds {
+                    Bounds(Some(under), Some(over)) => Node::UnderOver {
+                        target,
+                        under,
+                        over,
+                    },
+                    Bounds(Some(symbol), None) => Node::Underset { target, symbol },
+                    Bounds(None, Some(symbol)) => Node::Overset { target, symbol },
+                    Bounds(None, None) => return Ok(target),
+                }
+            }
+            Token::MathClass(_) => return self.parse_next(true),
             Token::OperatorName => {
                 // TODO: Don't parse a node just to immediately destructure it.
 
@@ -660,53 +1380,211 @@ where
                 let old_collector = mem::replace(&mut self.collector, LetterCollector::Inactive);
                 let node = self.parse_next(true)?;
                 self.collector = old_collector;
-                let mut builder = self.buffer.get_builder();
-                if !extract_letters(&mut builder, node) {
-                    return Err(LatexError(
-                        loc,
-                        LatexErrKind::ExpectedText("\\operatorname"),
-                    ));
-                }
-                let letters = builder.finish(self.arena);
-                if let Some(ch) = get_single_char(letters) {
-                    Node::SingleLetterIdent(ch, true)
-                } else {
-                    Node::MultiLetterIdent(letters)
-                }
+                self.collect_text(node, ArgKind::Letters, loc, "\\operatorname")?
             }
             Token::Text(transform) => {
-                self.l.text_mode = true;
-                let node = self.parse_next(true)?;
-                let mut builder = self.buffer.get_builder();
-                if !extract_letters(&mut builder, node) {
-                    return Err(LatexError(loc, LatexErrKind::ExpectedText("\\text")));
-                }
-                let text = builder.finish(self.arena);
-                self.l.text_mode = false;
-                // Discard any whitespace tokens that are still stored in self.peek_token.
-                if matches!(self.peek.token(), Token::Whitespace) {
-                    self.next_token();
-                }
+                let text = self.parse_text_with_spaces(loc, "\\text")?;
                 if let Some(transform) = transform {
                     Node::TextTransform {
-                        content: self.commit(Node::Text(text)),
+                        content: self.commit(text),
                         tf: MathVariant::Transform(transform),
                     }
                 } else {
-                    Node::Text(text)
+                    text
                 }
             }
-            Token::Ampersand => Node::ColumnSeparator,
-            Token::NewLine => Node::RowSeparator,
-            Token::Color => {
-                let (loc, color_name) = self.parse_text_group()?;
-                let Some(color) = get_color(color_name) else {
-                    return Err(LatexError(loc, LatexErrKind::UnknownColor(color_name)));
+            Token::Tag => {
+                // `\tag*` omits the parentheses.
+                let is_starred = matches!(
+                    self.peek.token(),
+                    Token::Relation(symbol::ASTERISK_OPERATOR)
+                );
+                if is_starred {
+                    self.next_token();
+                }
+                let text = self.parse_text_arg(loc, "\\tag")?;
+                if is_starred {
+                    Node::Tag(text)
+                } else {
+                    let mut builder = self.buffer.get_builder();
+                    builder.push_char('(');
+                    builder.push_str(text);
+                    builder.push_char(')');
+                    Node::Tag(builder.finish(self.arena))
+                }
+            }
+            Token::NoTag => Node::Tag(""),
+            Token::Label => {
+                let label = self.parse_label_arg(loc, "\\label")?;
+                if self.label_locs.iter().any(|(other, _)| *other == label) {
+                    let error =
+                        LatexError(self.l.token_span(loc), LatexErrKind::DuplicateLabel(label));
+                    if !self.recover {
+                        return Err(error);
+                    }
+                    self.errors.push(error);
+                } else {
+                    self.label_locs.push((label, loc));
+                }
+                Node::Label(self.arena.alloc_str(label))
+            }
+            Token::EqRef => {
+                let label = self.parse_label_arg(loc, "\\eqref")?;
+                let number = match self.labels.get(label) {
+                    Some(number) => self.arena.alloc_str(number),
+                    None => {
+                        let error =
+                            LatexError(self.l.token_span(loc), LatexErrKind::UndefinedLabel(label));
+                        if !self.recover {
+                            return Err(error);
+                        }
+                        // Like LaTeX, we show question marks in place of the number.
+                        self.errors.push(error);
+                        "(??)"
+                    }
                 };
+                Node::Reference {
+                    label: self.arena.alloc_str(label),
+                    number: self.commit(Node::Text(number)),
+                }
+            }
+            Token::Intertext => {
+                let text = self.parse_token(TokLoc(loc, Token::Text(None)), true, None)?;
+                Node::Intertext(text)
+            }
+            Token::Ampersand => {
+                let too_many_columns = self.array_layout.as_mut().and_then(|layout| {
+                    layout.column += 1;
+                    layout.num_columns.filter(|&n| layout.column >= n)
+                });
+                if let Some(num_columns) = too_many_columns {
+                    let error = LatexError(
+                        self.l.token_span(loc),
+                        LatexErrKind::TooManyColumns(num_columns),
+                    );
+                    self.recover(error, "&")?
+                } else {
+                    Node::ColumnSeparator
+                }
+            }
+            Token::NewLine => {
+                if let Some(layout) = &mut self.array_layout {
+                    layout.column = 0;
+                }
+                Node::RowSeparator
+            }
+            Token::HLine(_) | Token::CLine if self.array_layout.is_none() => {
+                let error = LatexError(
+                    self.l.token_span(loc),
+                    LatexErrKind::CannotBeUsedHere {
+                        got: cur_token,
+                        correct_place: Place::InArray,
+                    },
+                );
+                self.recover(error, self.l.span_text(self.l.token_span(loc)))?
+            }
+            Token::HLine(line) => Node::HLine {
+                line,
+                columns: None,
+            },
+            Token::CLine => {
+                self.check_lbrace()?;
+                self.next_token(); // Discard the opening brace.
+                let first = self.parse_column_number()?;
+                let TokLoc(minus_loc, token) = self.next_token();
+                if !matches!(token, Token::BinaryOp(symbol::MINUS_SIGN)) {
+                    return Err(LatexError(
+                        self.l.token_span(minus_loc),
+                        LatexErrKind::UnexpectedToken {
+                            expected: &Token::BinaryOp(symbol::MINUS_SIGN),
+                            got: token,
+                        },
+                    ));
+                }
+                let last = self.parse_column_number()?;
+                let TokLoc(end_loc, token) = self.next_token();
+                if !matches!(token, Token::GroupEnd) {
+                    return Err(LatexError(
+                        self.l.token_span(end_loc),
+                        LatexErrKind::UnexpectedToken {
+                            expected: &Token::GroupEnd,
+                            got: token,
+                        },
+                    ));
+                }
+                let num_columns = self
+                    .array_layout
+                    .and_then(|layout| layout.num_columns)
+                    .unwrap_or(usize::MAX);
+                if first == 0 || first > last || usize::from(last) > num_columns {
+                    let error = LatexError(
+                        loc..end_loc + 1,
+                        LatexErrKind::InvalidColumnRange(first, last),
+                    );
+                    self.recover(error, self.l.span_text(loc..end_loc + 1))?
+                } else {
+                    Node::HLine {
+                        line: LineType::Solid,
+                        columns: Some((first, last)),
+                    }
+                }
+            }
+            Token::Color => {
+                let (r, g, b) = self.parse_color()?;
                 let content = self.parse_sequence(Token::GroupEnd, true)?;
                 Node::Row {
                     nodes: self.arena.push_slice(&content),
-                    attr: color,
+                    attr: RowAttr::Color(r, g, b),
+                }
+            }
+            Token::TextColor => {
+                let (r, g, b) = self.parse_color()?;
+                let content = self.parse_next(true)?;
+                Node::Row {
+                    nodes: self.arena.push_slice(&[content]),
+                    attr: RowAttr::Color(r, g, b),
+                }
+            }
+            Token::Enclose(notation) => {
+                let content = self.parse_next(true)?;
+                Node::Enclose { content, notation }
+            }
+            Token::FBox => {
+                // The width and position of `\framebox` are not supported, so they are skipped.
+                while matches!(self.peek.token(), Token::SquareBracketOpen) {
+                    self.next_token(); // Discard the opening bracket.
+                    self.parse_sequence(Token::SquareBracketClose, false)?;
+                    self.next_token(); // Discard the closing bracket.
+                }
+                Node::Enclose {
+                    content: self.parse_text_box()?,
+                    notation: Notation::Box,
+                }
+            }
+            Token::CancelTo => {
+                // The value which the content is cancelled to is shown as a superscript
+                // after the struck-out content.
+                let symbol = self.parse_next(true)?;
+                let content = self.parse_next(true)?;
+                Node::Superscript {
+                    target: self.commit(Node::Enclose {
+                        content,
+                        notation: Notation::UpDiagonalStrike,
+                    }),
+                    symbol,
+                }
+            }
+            Token::ColorBox | Token::FColorBox => {
+                let frame = if matches!(cur_token, Token::FColorBox) {
+                    Some(self.parse_color()?)
+                } else {
+                    None
+                };
+                let background = self.parse_color()?;
+                Node::ColorBox {
+                    content: self.parse_text_box()?,
+                    background,
+                    frame,
                 }
             }
             Token::Style(style) => {
@@ -717,12 +1595,17 @@ where
                 }
             }
             Token::UnknownCommand(name) => {
-                return Err(LatexError(loc, LatexErrKind::UnknownCommand(name)));
+                let error = LatexError(self.l.token_span(loc), LatexErrKind::UnknownCommand(name));
+                let mut builder = self.buffer.get_builder();
+                builder.push_char('\\');
+                builder.push_str(name);
+                let text = builder.finish(self.arena);
+                self.recover(error, text)?
             }
             // Token::Underscore | Token::Circumflex => {
             Token::Circumflex => {
                 return Err(LatexError(
-                    loc,
+                    self.l.token_span(loc),
                     LatexErrKind::CannotBeUsedHere {
                         got: cur_token,
                         correct_place: Place::AfterOpOrIdent,
@@ -740,20 +1623,123 @@ where
             Token::Underscore => {
                 let sub = self.parse_next(true)?;
                 let base = self.parse_next(false)?;
-                Node::Multiscript { base, sub }
+                let pre = [ScriptPair {
+                    sub: Some(sub),
+                    sup: None,
+                }];
+                self.multiscript(base, &[], &pre)
+            }
+            Token::Sideset => {
+                self.check_lbrace()?;
+                self.next_token(); // Discard the opening brace.
+                let pre = self.parse_script_pairs(Token::GroupEnd, false)?;
+                self.check_lbrace()?;
+                self.next_token(); // Discard the opening brace.
+                let post = self.parse_script_pairs(Token::GroupEnd, false)?;
+                // The limits of a big operator stay above and below it, so the scripts are
+                // attached to the operator itself.
+                let base = self.parse_next(false)?;
+                match *base {
+                    Node::UnderOver {
+                        target,
+                        under,
+                        over,
+                    } => Node::UnderOver {
+                        target: self.commit(self.multiscript(target, &post, &pre)),
+                        under,
+                        over,
+                    },
+                    Node::Underset { target, symbol } => Node::Underset {
+                        target: self.commit(self.multiscript(target, &post, &pre)),
+                        symbol,
+                    },
+                    Node::Overset { target, symbol } => Node::Overset {
+                        target: self.commit(self.multiscript(target, &post, &pre)),
+                        symbol,
+                    },
+                    _ => self.multiscript(base, &post, &pre),
+                }
+            }
+            Token::Prescript => {
+                let sup = self.parse_next(true)?;
+                let sub = self.parse_next(true)?;
+                let base = self.parse_next(true)?;
+                let pre = [ScriptPair {
+                    sub: non_empty(sub),
+                    sup: non_empty(sup),
+                }];
+                self.multiscript(base, &[], &pre)
+            }
+            Token::Tensor => {
+                let pre = if matches!(self.peek.token(), Token::SquareBracketOpen) {
+                    self.next_token(); // Discard the opening bracket.
+                    self.parse_script_pairs(Token::SquareBracketClose, true)?
+                } else {
+                    Vec::new()
+                };
+                let base = self.parse_next(true)?;
+                let post = self.parse_tensor_indices()?;
+                self.multiscript(base, &post, &pre)
+            }
+            Token::Indices => {
+                // Usually, `\indices` is attached to the node before it while parsing the
+                // sequence. Otherwise, there is nothing to attach the indices to.
+                let post = self.parse_tensor_indices()?;
+                let base = self.commit(Node::Row {
+                    nodes: &[],
+                    attr: RowAttr::None,
+                });
+                self.multiscript(base, &post, &[])
             }
-            Token::Limits => {
+            Token::Limits | Token::NoLimits => {
                 return Err(LatexError(
-                    loc,
+                    self.l.token_span(loc),
                     LatexErrKind::CannotBeUsedHere {
                         got: cur_token,
                         correct_place: Place::AfterBigOp,
                     },
                 ));
             }
-            Token::EOF => return Err(LatexError(loc, LatexErrKind::UnexpectedEOF)),
-            Token::End | Token::Right | Token::GroupEnd => {
-                return Err(LatexError(loc, LatexErrKind::UnexpectedClose(cur_token)));
+            Token::EOF => {
+                // If we recovered from a group which was still open at the end of the
+                // input, the missing end has already been reported.
+                let reported = self.recover
+                    && self
+                        .errors
+                        .iter()
+                        .any(|e| matches!(e.1, LatexErrKind::UnclosedGroup(_)));
+                if !reported {
+                    return Err(LatexError(
+                        self.l.token_span(loc),
+                        LatexErrKind::UnexpectedEOF,
+                    ));
+                }
+                Node::Row {
+                    nodes: &[],
+                    attr: RowAttr::None,
+                }
+            }
+            Token::GroupEnd => {
+                let error = LatexError(
+                    self.l.token_span(loc),
+                    LatexErrKind::UnexpectedClose(cur_token),
+                );
+                self.recover(error, "}")?
+            }
+            Token::CDArrow(_) => {
+                return Err(LatexError(
+                    self.l.token_span(loc),
+                    LatexErrKind::CannotBeUsedHere {
+                        got: cur_token,
+                        correct_place: Place::InCDEnvironment,
+                    },
+                ));
+            }
+            Token::End | Token::Right | Token::CDLabelEnd => {
+                return Err(LatexError(
+                    self.l.token_span(loc),
+                    LatexErrKind::UnexpectedClose(cur_token),
+                ));
             }
             Token::CustomCmd(num_args, predefined) => {
                 let mut nodes = Vec::with_capacity(num_args);
@@ -776,7 +1762,7 @@ where
                 }
                 _ => {
                     return Err(LatexError(
-                        loc,
+                        self.l.token_span(loc),
                         LatexErrKind::CannotBeUsedHere {
                             got: cur_token,
                             correct_place: Place::AfterOpOrIdent,
@@ -785,10 +1771,232 @@ where
                 }
             },
             Token::HardcodedMathML(mathml) => Node::HardcodedMathML(mathml),
+            Token::UserCmd(name) => {
+                let Some(cmd) = self.user_cmd(name) else {
+                    return Err(LatexError(
+                        self.l.token_span(loc),
+                        LatexErrKind::UnknownCommand(name),
+                    ));
+                };
+                let mut nodes = Vec::with_capacity(cmd.num_args as usize);
+                if let Some(default) = cmd.default {
+                    let arg = if matches!(self.peek.token(), Token::SquareBracketOpen) {
+                        self.next_token(); // Discard the opening token.
+                        let arg = self.parse_sequence(Token::SquareBracketClose, false)?;
+                        self.next_token(); // Discard the closing token.
+                        self.node_vec_to_node(arg)
+                    } else {
+                        default
+                    };
+                    let arg = self.convert_arg(arg, cmd.arg_kinds[0], loc)?;
+                    nodes.push(arg);
+                }
+                while nodes.len() < cmd.num_args as usize {
+                    let kind = cmd.arg_kinds[nodes.len()];
+                    let arg = match kind {
+                        ArgKind::Math => self.parse_next(true)?,
+                        ArgKind::Text => self.parse_text_node()?,
+                        ArgKind::Letters => {
+                            // Turn off collection mode, like for `\operatorname`.
+                            let old_collector =
+                                mem::replace(&mut self.collector, LetterCollector::Inactive);
+                            let arg = self.parse_next(true);
+                            self.collector = old_collector;
+                            arg?
+                        }
+                    };
+                    let arg = self.convert_arg(arg, kind, loc)?;
+                    nodes.push(arg);
+                }
+                let args = self.arena.push_slice(&nodes);
+                Node::CustomCmd {
+                    predefined: cmd.body,
+                    args,
+                }
+            }
+            Token::CustomCmdArg(num) => {
+                if num > self.num_params {
+                    return Err(LatexError(
+                        self.l.token_span(loc),
+                        LatexErrKind::CannotBeUsedHere {
+                            got: cur_token,
+                            correct_place: Place::InCmdDefinition,
+                        },
+                    ));
+                }
+                Node::CustomCmdArg(usize::from(num - 1))
+            }
+            Token::NewCommand
+            | Token::RenewCommand
+            | Token::Def
+            | Token::DefineColor
+            | Token::Infix(_) => {
+                return Err(LatexError(
+                    self.l.token_span(loc),
+                    LatexErrKind::CannotBeUsedHere {
+                        got: cur_token,
+                        correct_place: Place::OutsideOfArgument,
+                    },
+                ));
+            }
         };
         Ok(self.commit(node))
     }
 
+    /// If recovery is enabled, record the error and return an error node showing `text`;
+    /// otherwise return the error.
+    fn recover(
+        &mut self,
+        error: LatexError<'source>,
+        text: &'arena str,
+    ) -> Result<Node<'arena>, LatexError<'source>> {
+        if !self.recover {
+            return Err(error);
+        }
+        self.errors.push(error);
+        Ok(Node::Error(text))
+    }
+
+    /// Parse the definition of a command via `\newcommand`, `\renewcommand` or `\def`.
+    ///
+    /// The definition is registered with the parser and the lexer, so that the
+    /// command can be used in the rest of the input.
+    fn parse_cmd_definition(
+        &mut self,
+        cur_tokloc: TokLoc<'source>,
+    ) -> Result<(), LatexError<'source>> {
+        let TokLoc(loc, cur_token) = cur_tokloc;
+        let is_def = matches!(cur_token, Token::Def);
+        // The starred version only differs in whether paragraphs are allowed in arguments.
+        if !is_def
+            && matches!(
+                self.peek.token(),
+                Token::Relation(symbol::ASTERISK_OPERATOR)
+            )
+        {
+            self.next_token(); // Discard the asterisk.
+        }
+        let name = self.parse_cmd_name(!is_def)?;
+        // `\newcommand` can't redefine a command and `\renewcommand` can't define a new one,
+        // whereas `\def` can do both. This is only reported once the definition itself has been
+        // parsed, so that errors within the definition take precedence.
+        let is_defined =
+            self.user_cmd(name).is_some() || !matches!(get_command(name), Token::UnknownCommand(_));
+
+        let mut num_args = 0u8;
+        let mut default = None;
+        if is_def {
+            // The parameters of `\def` are given as `#1#2...`.
+            while let Token::CustomCmdArg(num) = *self.peek.token() {
+                let TokLoc(loc, token) = self.next_token();
+                if num != num_args + 1 {
+                    return Err(LatexError(
+                        self.l.token_span(loc),
+                        LatexErrKind::UnexpectedToken {
+                            expected: &Token::CustomCmdArg(0),
+                            got: token,
+                        },
+                    ));
+                }
+                num_args = num;
+            }
+        } else if matches!(self.peek.token(), Token::SquareBracketOpen) {
+            self.next_token(); // Discard the opening token.
+            let TokLoc(loc, token) = self.next_token();
+            num_args = match token {
+                Token::Number(digit) => digit as u8 - b'0',
+                _ => {
+                    return Err(LatexError(
+                        self.l.token_span(loc),
+                        LatexErrKind::UnexpectedToken {
+                            expected: &Token::Number(Digit::One),
+                            got: token,
+                        },
+                    ));
+                }
+            };
+            let TokLoc(loc, token) = self.next_token();
+            if !matches!(token, Token::SquareBracketClose) {
+                return Err(LatexError(
+                    self.l.token_span(loc),
+                    LatexErrKind::UnexpectedToken {
+                        expected: &Token::SquareBracketClose,
+                        got: token,
+                    },
+                ));
+            }
+            if num_args > 0 && matches!(self.peek.token(), Token::SquareBracketOpen) {
+                self.next_token(); // Discard the opening token.
+                let content = self.parse_sequence(Token::SquareBracketClose, false)?;
+                self.next_token(); // Discard the closing token.
+                default = Some(self.node_vec_to_node(content));
+            }
+        }
+
+        self.check_lbrace()?;
+        self.next_token(); // Discard the opening token.
+        let old_num_params = mem::replace(&mut self.num_params, num_args);
+        let old_param_kinds = mem::replace(&mut self.param_kinds, [ArgKind::Math; 9]);
+        let atom = self.atom_of(self.peek.token());
+        let content = self.parse_sequence(Token::GroupEnd, false)?;
+        self.num_params = old_num_params;
+        let arg_kinds = mem::replace(&mut self.param_kinds, old_param_kinds);
+        let body = self.node_vec_to_node(content);
+
+        match cur_token {
+            Token::NewCommand if is_defined => {
+                return Err(LatexError(
+                    self.l.token_span(loc),
+                    LatexErrKind::CommandAlreadyDefined(name),
+                ));
+            }
+            Token::RenewCommand if !is_defined => {
+                return Err(LatexError(
+                    self.l.token_span(loc),
+                    LatexErrKind::UnknownCommand(name),
+                ));
+            }
+            _ => {}
+        }
+
+        // Register the command before discarding the closing token, such that the
+        // token after the definition is already lexed with the new command in place.
+        if !self.l.user_cmds.contains(&name) {
+            self.l.user_cmds.push(name);
+        }
+        self.user_cmds.push((
+            name,
+            UserCmd {
+                num_args,
+                default,
+                body,
+                atom,
+                arg_kinds,
+            },
+        ));
+        self.next_token(); // Discard the closing token.
+        Ok(())
+    }
+
+    /// Parse the name of a command that is being defined, e.g. `\foo` or `{\foo}`.
+    fn parse_cmd_name(&mut self, allow_braces: bool) -> Result<&'source str, LatexError<'source>> {
+        if allow_braces && matches!(self.peek.token(), Token::GroupBegin) {
+            let name = self.l.read_cmd_name_group();
+            // Discard the opening token (which is still stored as `peek`).
+            let opening_loc = self.next_token().location();
+            return name.ok_or(LatexError(
+                self.l.token_span(opening_loc),
+                LatexErrKind::ExpectedCmdName,
+            ));
+        }
+        let name = self.l.cmd_name_at(self.peek.location());
+        let loc = self.next_token().location();
+        name.ok_or(LatexError(
+            self.l.token_span(loc),
+            LatexErrKind::ExpectedCmdName,
+        ))
+    }
+
     /// Same as `parse_token`, but also gets the next token.
     #[inline]
     fn parse_next(&mut self, wants_arg: bool) -> Result<&'arena Node<'arena>, LatexError<'source>> {
@@ -796,21 +2004,909 @@ where
         self.parse_token(token, wants_arg, None)
     }
 
+    /// Parse scripts like `_a^b` until the given end token, which is consumed, as in the
+    /// arguments of `\sideset` and `\tensor`.
+    ///
+    /// If `staggered` is `true`, every script gets a column of its own. Otherwise,
+    /// a subscript and a superscript which directly follow each other share a column.
+    fn parse_script_pairs(
+        &mut self,
+        end_token: Token<'static>,
+        staggered: bool,
+    ) -> Result<Vec<ScriptPair<'arena>>, LatexError<'source>> {
+        let mut pairs: Vec<ScriptPair<'arena>> = Vec::new();
+        loop {
+            let TokLoc(loc, token) = self.next_token();
+            let (is_sub, script) = match token {
+                token if token.is_same_kind_as(&end_token) => break,
+                Token::Underscore => (true, self.parse_next(true)?),
+                Token::Circumflex => (false, self.parse_next(true)?),
+                Token::Prime => (
+                    false,
+                    self.commit(Node::Operator(symbol::PRIME.into(), None)),
+                ),
+                Token::EOF => {
+                    return Err(LatexError(
+                        self.l.token_span(loc),
+                        LatexErrKind::UnclosedGroup(end_token),
+                    ));
+                }
+                _ => {
+                    return Err(LatexError(
+                        self.l.token_span(loc),
+                        LatexErrKind::UnexpectedToken {
+                            expected: &Token::Underscore,
+                            got: token,
+                        },
+                    ));
+                }
+            };
+            match pairs.last_mut() {
+                Some(pair) if !staggered && is_sub && pair.sub.is_none() => {
+                    pair.sub = Some(script);
+                }
+                Some(pair) if !staggered && !is_sub && pair.sup.is_none() => {
+                    pair.sup = Some(script);
+                }
+                _ => pairs.push(if is_sub {
+                    ScriptPair {
+                        sub: Some(script),
+                        sup: None,
+                    }
+                } else {
+                    ScriptPair {
+                        sub: None,
+                        sup: Some(script),
+                    }
+                }),
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// Parse the indices of a tensor in braces, like `{^a_b}`, each in a column of its own.
+    fn parse_tensor_indices(&mut self) -> Result<Vec<ScriptPair<'arena>>, LatexError<'source>> {
+        self.check_lbrace()?;
+        self.next_token(); // Discard the opening brace.
+        self.parse_script_pairs(Token::GroupEnd, true)
+    }
+
+    /// Attach the given scripts after (`post`) and before (`pre`) the base.
+    fn multiscript(
+        &self,
+        base: &'arena Node<'arena>,
+        post: &[ScriptPair<'arena>],
+        pre: &[ScriptPair<'arena>],
+    ) -> Node<'arena> {
+        let scripts = [post, pre].concat();
+        Node::Multiscript {
+            base,
+            scripts: self.arena.push_script_pairs(&scripts),
+            num_postscripts: post.len() as u32,
+        }
+    }
+
+    /// Pad the label of an extensible arrow, and make sure that the arrow has a minimum
+    /// width, even if the label is short.
+    fn extensible_arrow_label(&self, label: &'arena Node<'arena>) -> &'arena Node<'arena> {
+        let nodes = [&Node::Space("0.4286"), label, &Node::Space("0.4286")];
+        let target = self.commit(Node::Row {
+            nodes: self.arena.push_slice(&nodes),
+            attr: RowAttr::None,
+        });
+        self.commit(Node::Overset {
+            symbol: &Node::Space("3.5"),
+            target,
+        })
+    }
+
+    /// Parse the argument of a box like `\fbox` or `\colorbox`, which is text that may
+    /// contain math in dollar signs.
+    fn parse_text_box(&mut self) -> Result<&'arena Node<'arena>, LatexError<'source>> {
+        self.check_lbrace()?;
+        // The content of the box is text, so the next token has to be read in text mode.
+        self.l.text_mode = true;
+        self.next_token(); // Discard the opening brace.
+        let mut content = Vec::new();
+        self.parse_text_content(&mut content, |token| {
+            matches!(token, Token::GroupEnd | Token::EOF)
+        })?;
+        self.expect_token(&Token::GroupEnd)?;
+        Ok(self.node_vec_to_node(content))
+    }
+
+    /// Parse a color argument like `{red!30!blue}`, which may be preceded by a color
+    /// model, like in `[rgb]{0.1, 0.2, 0.3}`.
+    fn parse_color(&mut self) -> Result<Rgb, LatexError<'source>> {
+        let model = if matches!(self.peek.token(), Token::SquareBracketOpen) {
+            Some(self.parse_color_group(']')?)
+        } else {
+            None
+        };
+        self.check_lbrace()?;
+        let (span, spec) = self.parse_color_group('}')?;
+        match model {
+            Some(model) => color_in_model(model, (span, spec)),
+            None => parse_color_expression(spec, |name| self.lookup_color(name))
+                .ok_or(LatexError(span, LatexErrKind::UnknownColor(spec))),
+        }
+    }
+
+    /// Parse the definition of a color via `\definecolor{name}{model}{spec}`.
+    ///
+    /// The color can be used by its name in the rest of the input.
+    fn parse_color_definition(&mut self) -> Result<(), LatexError<'source>> {
+        self.check_lbrace()?;
+        let (name_span, name) = self.parse_color_group('}')?;
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(LatexError(name_span, LatexErrKind::InvalidColorSpec(name)));
+        }
+        self.check_lbrace()?;
+        let model = self.parse_color_group('}')?;
+        self.check_lbrace()?;
+        let spec = self.parse_color_group('}')?;
+        let color = color_in_model(model, spec)?;
+        self.colors.push((name, color));
+        Ok(())
+    }
+
+    /// Look up a color by its name, among the colors defined by the user and the
+    /// predefined ones.
+    fn lookup_color(&self, name: &str) -> Option<Rgb> {
+        self.colors
+            .iter()
+            .rev()
+            .find(|(defined, _)| *defined == name)
+            .map(|(_, color)| *color)
+            .or_else(|| get_color(name))
+    }
+
+    /// Read the color or color model in the group which is opened by the peek token,
+    /// and return it together with the span of the group.
+    fn parse_color_group(
+        &mut self,
+        closing: char,
+    ) -> Result<(Range<usize>, &'source str), LatexError<'source>> {
+        let result = self.l.read_color_spec(closing);
+        // Discard the opening token (which is still stored as `peek`).
+        let opening_loc = self.next_token().location();
+        // The opening and closing characters are one byte long.
+        match result {
+            Ok(spec) => Ok((opening_loc..opening_loc + spec.len() + 2, spec.trim())),
+            Err(text) => Err(LatexError(
+                opening_loc..opening_loc + text.len() + 1,
+                LatexErrKind::InvalidColorSpec(text),
+            )),
+        }
+    }
+
     /// Parse the contents of a group which can only contain text.
     fn parse_text_group(&mut self) -> Result<(usize, &'source str), LatexError<'source>> {
         let result = self.l.read_length_or_env_name();
         // Discard the opening token (which is still stored as `peek`).
         let opening_loc = self.next_token().location();
-        result
-            .map(|r| (opening_loc, r))
-            .ok_or(LatexError(opening_loc, LatexErrKind::UnparsableEnvName))
+        result.map(|r| (opening_loc, r)).ok_or(LatexError(
+            self.l.token_span(opening_loc),
+            LatexErrKind::UnparsableEnvName,
+        ))
+    }
+
+    /// Parse the argument of a command like `\text`, which is read in text mode.
+    fn parse_text_arg(
+        &mut self,
+        loc: usize,
+        cmd: &'static str,
+    ) -> Result<&'arena str, LatexError<'source>> {
+        let node = self.parse_text_node()?;
+        self.extract_text(&[node], loc, cmd)
+    }
+
+    /// Parse the argument of `\text`, which, unlike other text arguments, may also contain
+    /// explicit spaces like `\hspace{1em}`.
+    ///
+    /// If there are such spaces, the result is a row of text nodes and spaces.
+    fn parse_text_with_spaces(
+        &mut self,
+        loc: usize,
+        cmd: &'static str,
+    ) -> Result<Node<'arena>, LatexError<'source>> {
+        let node = self.parse_text_node()?;
+        self.collect_text(node, ArgKind::Text, loc, cmd)
+    }
+
+    /// Turn the given node, which has to consist only of text, into a text node if `kind`
+    /// is `ArgKind::Text`, or into an identifier if it is `ArgKind::Letters`.
+    ///
+    /// Explicit spaces in text and the arguments of a command whose body is being parsed
+    /// are kept as they are, so the result is then a row. The arguments are recorded as
+    /// being used in this way, so that they are parsed accordingly when the command is used.
+    fn collect_text(
+        &mut self,
+        node: &'arena Node<'arena>,
+        kind: ArgKind,
+        loc: usize,
+        cmd: &'static str,
+    ) -> Result<Node<'arena>, LatexError<'source>> {
+        let is_kept = |node: &Node| match node {
+            Node::Kern(_) => matches!(kind, ArgKind::Text),
+            Node::CustomCmdArg(_) => true,
+            _ => false,
+        };
+        let mut leaves = Vec::new();
+        flatten_rows(node, &mut leaves);
+        if !leaves.iter().any(|node| is_kept(node)) {
+            let text = self.extract_text(&[node], loc, cmd)?;
+            return Ok(self.text_node(text, kind));
+        }
+        let mut kept = leaves.iter().filter(|node| is_kept(node)).copied();
+        let mut nodes = Vec::new();
+        for text in leaves.split(|node| is_kept(node)) {
+            if !text.is_empty() {
+                let text = self.extract_text(text, loc, cmd)?;
+                nodes.push(self.commit(self.text_node(text, kind)));
+            }
+            if let Some(node) = kept.next() {
+                if let Node::CustomCmdArg(index) = node {
+                    self.param_kinds[*index] = kind;
+                }
+                nodes.push(node);
+            }
+        }
+        Ok(Node::Row {
+            nodes: self.arena.push_slice(&nodes),
+            attr: RowAttr::None,
+        })
+    }
+
+    /// A text node or an upright identifier with the given text, depending on `kind`.
+    fn text_node(&self, text: &'arena str, kind: ArgKind) -> Node<'arena> {
+        match kind {
+            ArgKind::Text => Node::Text(text),
+            _ => match get_single_char(text) {
+                Some(ch) => Node::SingleLetterIdent(ch, true),
+                None => Node::MultiLetterIdent(text),
+            },
+        }
+    }
+
+    /// Bring an argument of a user-defined command into the form in which the body of
+    /// the command uses it.
+    fn convert_arg(
+        &mut self,
+        arg: &'arena Node<'arena>,
+        kind: ArgKind,
+        loc: usize,
+    ) -> Result<&'arena Node<'arena>, LatexError<'source>> {
+        let cmd = match kind {
+            ArgKind::Math => return Ok(arg),
+            ArgKind::Text => "\\text",
+            ArgKind::Letters => "\\operatorname",
+        };
+        let node = self.collect_text(arg, kind, loc, cmd)?;
+        Ok(self.commit(node))
+    }
+
+    /// Parse the next node in text mode.
+    fn parse_text_node(&mut self) -> Result<&'arena Node<'arena>, LatexError<'source>> {
+        self.l.text_mode = true;
+        let node = self.parse_next(true)?;
+        self.l.text_mode = false;
+        // Discard any whitespace tokens that are still stored in self.peek_token.
+        if matches!(self.peek.token(), Token::Whitespace) {
+            self.next_token();
+        }
+        Ok(node)
+    }
+
+    /// Concatenate the letters in the given nodes, which have to consist only of text.
+    fn extract_text(
+        &mut self,
+        nodes: &[&'arena Node<'arena>],
+        loc: usize,
+        cmd: &'static str,
+    ) -> Result<&'arena str, LatexError<'source>> {
+        let mut builder = self.buffer.get_builder();
+        if !nodes.iter().all(|node| extract_letters(&mut builder, node)) {
+            return Err(LatexError(
+                self.l.token_span(loc),
+                LatexErrKind::ExpectedText(cmd),
+            ));
+        }
+        Ok(builder.finish(self.arena))
+    }
+
+    /// Parse the content of a `CD` environment into the cells of a table.
+    ///
+    /// In a row with objects, the objects and the horizontal arrows between them each
+    /// get a cell of their own. In a row with vertical arrows, the arrows are placed
+    /// below the objects, so there is an empty cell between each two of them.
+    fn parse_cd_content(&mut self) -> Result<Vec<&'arena Node<'arena>>, LatexError<'source>> {
+        let mut content = Vec::new();
+        // The objects and arrows of the current row.
+        let mut row: Vec<(Option<CDArrow>, &'arena Node<'arena>)> = Vec::new();
+        loop {
+            let objects = self.parse_sequence_until(
+                |token| matches!(token, Token::CDArrow(_) | Token::NewLine | Token::End),
+                Token::End,
+                false,
+            )?;
+            if !objects.is_empty() {
+                let objects = match objects.as_slice() {
+                    [object] => *object,
+                    _ => self.commit(Node::Row {
+                        nodes: self.arena.push_slice(&objects),
+                        attr: RowAttr::None,
+                    }),
+                };
+                row.push((None, objects));
+            }
+            let arrow = match self.peek.token() {
+                Token::CDArrow(arrow) => *arrow,
+                Token::NewLine => {
+                    self.next_token();
+                    self.push_cd_row(&mut content, mem::take(&mut row));
+                    content.push(self.commit(Node::RowSeparator));
+                    continue;
+                }
+                _ => {
+                    // We have reached `\end`, which is consumed by the caller.
+                    self.push_cd_row(&mut content, row);
+                    return Ok(content);
+                }
+            };
+            self.next_token();
+            let node = match arrow {
+                CDArrow::Right | CDArrow::Left | CDArrow::Down | CDArrow::Up => {
+                    let first = self.parse_sequence(Token::CDLabelEnd, false)?;
+                    self.next_token(); // Discard the end of the label.
+                    let second = self.parse_sequence(Token::CDLabelEnd, false)?;
+                    self.next_token(); // Discard the end of the label.
+                    let args = [first, second].map(|label| match label.as_slice() {
+                        [node] => *node,
+                        _ => self.commit(Node::Row {
+                            nodes: self.arena.push_slice(&label),
+                            attr: RowAttr::None,
+                        }),
+                    });
+                    let predefined = match arrow {
+                        CDArrow::Right => &predefined::CD_RIGHTARROW,
+                        CDArrow::Left => &predefined::CD_LEFTARROW,
+                        CDArrow::Down => &predefined::CD_DOWNARROW,
+                        _ => &predefined::CD_UPARROW,
+                    };
+                    self.commit(Node::CustomCmd {
+                        predefined,
+                        args: self.arena.push_slice(&args),
+                    })
+                }
+                CDArrow::Equals => &predefined::CD_EQUALS,
+                CDArrow::Parallel => &predefined::CD_PARALLEL,
+                CDArrow::Empty => self.commit(Node::Row {
+                    nodes: &[],
+                    attr: RowAttr::None,
+                }),
+            };
+            row.push((Some(arrow), node));
+        }
+    }
+
+    /// Parse the content of a starred `cases` environment, like `cases*`.
+    ///
+    /// The first column is math, as usual, but all further columns are text, in which
+    /// math can be written between dollar signs.
+    fn parse_text_cases_content(
+        &mut self,
+    ) -> Result<Vec<&'arena Node<'arena>>, LatexError<'source>> {
+        let mut content = self.parse_sequence_until(
+            |token| matches!(token, Token::Ampersand | Token::NewLine | Token::End),
+            Token::End,
+            false,
+        )?;
+        loop {
+            match self.peek.token() {
+                Token::Ampersand => {
+                    self.next_token();
+                    content.push(self.commit(Node::ColumnSeparator));
+                    self.parse_text_content(&mut content, |token| {
+                        matches!(
+                            token,
+                            Token::Ampersand | Token::NewLine | Token::End | Token::EOF
+                        )
+                    })?;
+                }
+                Token::NewLine => {
+                    self.next_token();
+                    content.push(self.commit(Node::RowSeparator));
+                    content.extend(self.parse_sequence_until(
+                        |token| matches!(token, Token::Ampersand | Token::NewLine | Token::End),
+                        Token::End,
+                        false,
+                    )?);
+                }
+                // We have reached `\end` (or the end of the input, which the caller
+                // reports), which is consumed by the caller.
+                _ => return Ok(content),
+            }
+        }
+    }
+
+    /// Parse text until a token for which `is_end` returns `true`, and append the
+    /// resulting nodes to `content`. The end token is not consumed.
+    ///
+    /// The text is collected into `Node::Text`, interrupted by any math in dollar signs
+    /// and by commands which don't produce text, like spacing commands.
+    fn parse_text_content(
+        &mut self,
+        content: &mut Vec<&'arena Node<'arena>>,
+        is_end: impl Fn(&Token) -> bool,
+    ) -> Result<(), LatexError<'source>> {
+        let old_collector = mem::replace(&mut self.collector, LetterCollector::Inactive);
+        self.l.text_mode = true;
+        let mut text = String::new();
+        loop {
+            match self.peek.token() {
+                token if is_end(token) => break,
+                Token::Letter('$') => {
+                    self.push_text(content, &mut text);
+                    self.l.text_mode = false;
+                    self.next_token(); // Discard the opening dollar sign.
+                    let math = self.parse_sequence_until(
+                        |token| matches!(token, Token::Letter('$')),
+                        Token::Letter('$'),
+                        false,
+                    )?;
+                    content.extend(math);
+                    self.l.text_mode = true;
+                    self.next_token(); // Discard the closing dollar sign.
+                }
+                _ => {
+                    let node = self.parse_next(true)?;
+                    let mut builder = self.buffer.get_builder();
+                    if extract_letters(&mut builder, node) {
+                        text.push_str(builder.finish(self.arena));
+                    } else {
+                        self.push_text(content, &mut text);
+                        content.push(node);
+                    }
+                }
+            }
+        }
+        // Trailing whitespace is not part of the text.
+        text.truncate(text.trim_end_matches('\u{A0}').len());
+        self.push_text(content, &mut text);
+        self.l.text_mode = false;
+        self.collector = old_collector;
+        Ok(())
+    }
+
+    /// Turn the text collected so far into a node, if there is any.
+    fn push_text(&self, content: &mut Vec<&'arena Node<'arena>>, text: &mut String) {
+        if !text.is_empty() {
+            content.push(self.commit(Node::Text(self.arena.alloc_str(text))));
+            text.clear();
+        }
+    }
+
+    /// Lay out one row of a `CD` environment, as described in `parse_cd_content`.
+    fn push_cd_row(
+        &self,
+        content: &mut Vec<&'arena Node<'arena>>,
+        row: Vec<(Option<CDArrow>, &'arena Node<'arena>)>,
+    ) {
+        let has_vertical_arrows = row
+            .iter()
+            .any(|(arrow, _)| arrow.is_some_and(CDArrow::is_vertical));
+        let separator = self.commit(Node::ColumnSeparator);
+        // The objects are in the even columns and the horizontal arrows in the odd ones.
+        // Vertical arrows are below the objects.
+        let mut current_col = None;
+        for (arrow, node) in row {
+            let parity = match arrow {
+                None if has_vertical_arrows => continue,
+                None => 0,
+                Some(_) if has_vertical_arrows => 0,
+                Some(_) => 1,
+            };
+            let min_col = current_col.map_or(0, |col| col + 1);
+            let col = if min_col % 2 == parity {
+                min_col
+            } else {
+                min_col + 1
+            };
+            let num_separators = col - current_col.unwrap_or(0);
+            content.extend(std::iter::repeat_n(separator, num_separators));
+            content.push(node);
+            current_col = Some(col);
+        }
+    }
+
+    /// Parse the name of a label, as in `\label{eq:energy}`.
+    fn parse_label_arg(
+        &mut self,
+        loc: usize,
+        cmd: &'static str,
+    ) -> Result<&'source str, LatexError<'source>> {
+        self.check_lbrace()?;
+        let label = self.l.read_label_name();
+        self.next_token(); // Discard the opening brace.
+        label.ok_or(LatexError(
+            self.l.token_span(loc),
+            LatexErrKind::ExpectedText(cmd),
+        ))
+    }
+
+    /// Record the labels in each row of `content` under the tag of the row, so that
+    /// `\eqref` can refer to them.
+    ///
+    /// A label which already has a different number is an error. If it has the same
+    /// number, it comes from an earlier pass over the same document.
+    fn register_labels(
+        &mut self,
+        content: &[&'arena Node<'arena>],
+    ) -> Result<(), LatexError<'source>> {
+        for row in content.split(|node| matches!(node, Node::RowSeparator | Node::Intertext(_))) {
+            let Some(tag) = row.iter().find_map(|node| match node {
+                Node::Tag(tag) if !tag.is_empty() => Some(*tag),
+                _ => None,
+            }) else {
+                continue;
+            };
+            let mut collector = LabelCollector(Vec::new());
+            visit::walk(&mut collector, row);
+            for label in collector.0 {
+                match self.labels.get(label) {
+                    None => {
+                        self.labels.insert(label.to_string(), tag.to_string());
+                    }
+                    Some(number) if number == tag => {}
+                    Some(_) => {
+                        // A label from the body of a command which was defined elsewhere
+                        // has no location in this input; for those, the first one wins.
+                        let Some(&(label, loc)) =
+                            self.label_locs.iter().find(|(other, _)| *other == label)
+                        else {
+                            continue;
+                        };
+                        let error =
+                            LatexError(self.l.token_span(loc), LatexErrKind::DuplicateLabel(label));
+                        if !self.recover {
+                            return Err(error);
+                        }
+                        self.errors.push(error);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Give every row of `content` an equation number, unless it is empty or already
+    /// has a `\tag` or `\notag`.
+    fn number_rows(&mut self, content: &mut Vec<&'arena Node<'arena>>) {
+        let mut numbered = Vec::with_capacity(content.len());
+        let mut row_is_empty = true;
+        let mut row_has_tag = false;
+        for node in mem::take(content) {
+            if matches!(node, Node::RowSeparator | Node::Intertext(_)) {
+                if !row_is_empty && !row_has_tag {
+                    numbered.push(self.next_equation_number());
+                }
+                row_is_empty = true;
+                row_has_tag = false;
+            } else {
+                row_is_empty = false;
+                row_has_tag |= matches!(node, Node::Tag(_));
+            }
+            numbered.push(node);
+        }
+        if !row_is_empty && !row_has_tag {
+            numbered.push(self.next_equation_number());
+        }
+        *content = numbered;
+    }
+
+    /// Give `content` a single equation number, unless it already has a `\tag` or
+    /// `\notag`.
+    fn number_once(&mut self, content: &mut Vec<&'arena Node<'arena>>) {
+        if !content.iter().any(|node| matches!(node, Node::Tag(_))) {
+            let number = self.next_equation_number();
+            content.push(number);
+        }
+    }
+
+    fn next_equation_number(&mut self) -> &'arena Node<'arena> {
+        self.equation_counter += 1;
+        let mut builder = self.buffer.get_builder();
+        builder.push_char('(');
+        builder.push_str(&self.equation_counter.to_string());
+        builder.push_char(')');
+        let number = builder.finish(self.arena);
+        self.commit(Node::Tag(number))
+    }
+
+    /// Parse the column specification of an `array`, like `{l|c@{.}r}`.
+    fn parse_array_spec(&mut self) -> Result<&'arena ArraySpec<'arena>, LatexError<'source>> {
+        self.check_lbrace()?;
+        self.next_token(); // Discard the opening brace.
+        let mut beginning_line = None;
+        let mut beginning_separator = None;
+        let mut columns: Vec<ColumnSpec> = Vec::new();
+        loop {
+            let TokLoc(loc, token) = self.next_token();
+            let align = match token {
+                Token::Letter('l') => ColumnAlign::Left,
+                Token::Letter('c') => ColumnAlign::Center,
+                Token::Letter('r') => ColumnAlign::Right,
+                Token::Delimiter(symbol::VERTICAL_LINE) | Token::Colon => {
+                    let line = if matches!(token, Token::Colon) {
+                        LineType::Dashed
+                    } else {
+                        LineType::Solid
+                    };
+                    match columns.last_mut() {
+                        Some(column) => column.line_after = Some(line),
+                        None => beginning_line = Some(line),
+                    }
+                    continue;
+                }
+                Token::Letter('@') => {
+                    self.check_lbrace()?;
+                    let separator = Some(self.parse_next(true)?);
+                    match columns.last_mut() {
+                        Some(column) => column.separator_after = separator,
+                        None => beginning_separator = separator,
+                    }
+                    continue;
+                }
+                Token::GroupEnd => break,
+                Token::EOF => {
+                    return Err(LatexError(
+                        self.l.token_span(loc),
+                        LatexErrKind::UnclosedGroup(Token::GroupEnd),
+                    ));
+                }
+                _ => {
+                    return Err(LatexError(
+                        self.l.token_span(loc),
+                        LatexErrKind::UnknownColumnSpec(self.l.token_text(loc)),
+                    ));
+                }
+            };
+            columns.push(ColumnSpec {
+                align,
+                line_after: None,
+                separator_after: None,
+            });
+        }
+        Ok(self.arena.alloc_array_spec(ArraySpec {
+            beginning_line,
+            beginning_separator,
+            columns: self.arena.push_column_specs(&columns),
+        }))
+    }
+
+    /// Parse the optional column alignment of the starred matrix environments, like `[r]`.
+    fn parse_column_align(&mut self) -> Result<ColumnAlign, LatexError<'source>> {
+        if !matches!(self.peek.token(), Token::SquareBracketOpen) {
+            return Ok(ColumnAlign::Center);
+        }
+        self.next_token(); // Discard the opening bracket.
+        let TokLoc(loc, token) = self.next_token();
+        let align = match token {
+            Token::Letter('l') => ColumnAlign::Left,
+            Token::Letter('c') => ColumnAlign::Center,
+            Token::Letter('r') => ColumnAlign::Right,
+            _ => {
+                return Err(LatexError(
+                    self.l.token_span(loc),
+                    LatexErrKind::UnknownColumnSpec(self.l.token_text(loc)),
+                ));
+            }
+        };
+        let TokLoc(loc, token) = self.next_token();
+        if !matches!(token, Token::SquareBracketClose) {
+            return Err(LatexError(
+                self.l.token_span(loc),
+                LatexErrKind::UnexpectedToken {
+                    expected: &Token::SquareBracketClose,
+                    got: token,
+                },
+            ));
+        }
+        Ok(align)
+    }
+
+    /// Parse the column specification of a `subarray`, which is either `{l}` or `{c}`.
+    fn parse_subarray_spec(&mut self) -> Result<ColumnAlign, LatexError<'source>> {
+        self.check_lbrace()?;
+        let (loc, spec) = self.parse_text_group()?;
+        match spec.trim() {
+            "l" => Ok(ColumnAlign::Left),
+            "c" => Ok(ColumnAlign::Center),
+            spec => Err(LatexError(
+                self.l.token_span(loc),
+                LatexErrKind::UnknownColumnSpec(spec),
+            )),
+        }
+    }
+
+    /// Build the array for a starred matrix environment, where all columns have the same
+    /// alignment.
+    fn aligned_matrix(
+        &self,
+        content: &'arena [&'arena Node<'arena>],
+        align: Option<ColumnAlign>,
+    ) -> Node<'arena> {
+        let num_columns = content
+            .split(|node| matches!(node, Node::RowSeparator))
+            .map(|row| {
+                row.iter()
+                    .filter(|node| matches!(node, Node::ColumnSeparator))
+                    .count()
+                    + 1
+            })
+            .max()
+            .unwrap_or(1);
+        let column = ColumnSpec {
+            align: align.unwrap_or(ColumnAlign::Center),
+            line_after: None,
+            separator_after: None,
+        };
+        let columns: Vec<ColumnSpec> = std::iter::repeat_n(column, num_columns).collect();
+        Node::Array {
+            content,
+            array_spec: self.arena.alloc_array_spec(ArraySpec {
+                beginning_line: None,
+                beginning_separator: None,
+                columns: self.arena.push_column_specs(&columns),
+            }),
+        }
+    }
+
+    /// Parse a column number (counting from 1), as in `\cline{2-3}`.
+    fn parse_column_number(&mut self) -> Result<u8, LatexError<'source>> {
+        let TokLoc(loc, token) = self.next_token();
+        let Token::Number(digit) = token else {
+            return Err(LatexError(
+                self.l.token_span(loc),
+                LatexErrKind::UnexpectedToken {
+                    expected: &Token::Number(Digit::One),
+                    got: token,
+                },
+            ));
+        };
+        let mut number = digit as u8 - b'0';
+        while let Token::Number(digit) = self.peek.token() {
+            number = number
+                .saturating_mul(10)
+                .saturating_add(*digit as u8 - b'0');
+            self.next_token();
+        }
+        Ok(number)
+    }
+
+    /// Parse a length in braces, like the argument of `\hspace`.
+    fn parse_length_arg(&mut self) -> Result<SpecifiedLength, LatexError<'source>> {
+        self.check_lbrace()?;
+        self.next_token(); // Discard the opening brace.
+        let length = self.parse_dimension()?;
+        self.expect_token(&Token::GroupEnd)?;
+        Ok(length)
+    }
+
+    /// Parse a TeX dimension like `-1.5pt`, which consists of an optional sign,
+    /// a decimal number and a unit of two letters.
+    fn parse_dimension(&mut self) -> Result<SpecifiedLength, LatexError<'source>> {
+        // The letters of the unit are not subject to letter collection.
+        let old_collector = mem::replace(&mut self.collector, LetterCollector::Inactive);
+        let start = self.peek.location();
+        let mut end = start;
+        let mut has_sign_or_number = false;
+        let mut num_unit_letters = 0;
+        loop {
+            match self.peek.token() {
+                Token::BinaryOp(symbol::MINUS_SIGN | symbol::PLUS_SIGN) if end == start => {
+                    has_sign_or_number = true;
+                }
+                Token::Number(_) | Token::Letter('.') if num_unit_letters == 0 => {
+                    has_sign_or_number = true;
+                }
+                Token::Letter(_) if has_sign_or_number && num_unit_letters < 2 => {
+                    num_unit_letters += 1;
+                }
+                _ => break,
+            }
+            let loc = self.next_token().location();
+            end = self.l.token_span(loc).end;
+        }
+        self.collector = old_collector;
+        let span = if end > start {
+            start..end
+        } else {
+            self.l.token_span(start)
+        };
+        let text = self.l.span_text(span.clone());
+        parse_length_specification(text)
+            .map_err(|_| LatexError(span, LatexErrKind::ExpectedLength(text)))
+    }
+
+    /// Parse glue like `1em plus 2pt minus 1pt`.
+    ///
+    /// We can't stretch or shrink the space, so only the natural length is returned.
+    fn parse_glue(&mut self) -> Result<SpecifiedLength, LatexError<'source>> {
+        let length = self.parse_dimension()?;
+        for keyword in ["plus", "minus"] {
+            if self.skip_keyword(keyword) && !self.skip_infinite_dimension() {
+                self.parse_dimension()?;
+            }
+        }
+        Ok(length)
+    }
+
+    /// Consume the letters of the given keyword if they come next in the input.
+    fn skip_keyword(&mut self, keyword: &str) -> bool {
+        let start = self.peek.location();
+        if !matches!(self.peek.token(), Token::Letter(_))
+            || !self.l.text_from(start).starts_with(keyword)
+        {
+            return false;
+        }
+        self.skip_to(start + keyword.len());
+        true
+    }
+
+    /// Consume an infinite dimension like `-1.5fill`, which can only appear in glue.
+    fn skip_infinite_dimension(&mut self) -> bool {
+        let start = self.peek.location();
+        let text = self.l.text_from(start);
+        let number = text
+            .strip_prefix(['+', '-'])
+            .unwrap_or(text)
+            .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        let Some(unit) = number.trim_start().strip_prefix("fil") else {
+            return false;
+        };
+        if !text.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '+' | '-' | '.')) {
+            return false;
+        }
+        let rest = unit.trim_start_matches('l');
+        self.skip_to(start + text.len() - rest.len());
+        true
+    }
+
+    /// Consume all tokens which start before the given location.
+    fn skip_to(&mut self, end: usize) {
+        // The letters are not subject to letter collection.
+        let old_collector = mem::replace(&mut self.collector, LetterCollector::Inactive);
+        while self.peek.location() < end {
+            self.next_token();
+        }
+        self.collector = old_collector;
+    }
+
+    /// Consume the next token, which has to be of the same kind as `expected`.
+    fn expect_token(
+        &mut self,
+        expected: &'static Token<'static>,
+    ) -> Result<(), LatexError<'source>> {
+        let TokLoc(loc, token) = self.next_token();
+        if !token.is_same_kind_as(expected) {
+            return Err(LatexError(
+                self.l.token_span(loc),
+                LatexErrKind::UnexpectedToken {
+                    expected,
+                    got: token,
+                },
+            ));
+        }
+        Ok(())
     }
 
     fn check_lbrace(&mut self) -> Result<(), LatexError<'source>> {
         if !matches!(self.peek.token(), Token::GroupBegin) {
             let TokLoc(loc, token) = self.next_token();
             return Err(LatexError(
-                loc,
+                self.l.token_span(loc),
                 LatexErrKind::UnexpectedToken {
                     expected: &Token::GroupBegin,
                     got: token,
@@ -822,6 +2918,20 @@ where
 
     /// Parse the bounds of an integral, sum, or product.
     /// These bounds are preceeded by `_` or `^`.
+    /// Attach the scripts which follow `target`, if any, as subscript and superscript.
+    fn scripts(
+        &mut self,
+        target: &'arena Node<'arena>,
+    ) -> Result<&'arena Node<'arena>, LatexError<'source>> {
+        let node = match self.get_bounds()? {
+            Bounds(Some(sub), Some(sup)) => Node::SubSup { target, sub, sup },
+            Bounds(Some(symbol), None) => Node::Subscript { target, symbol },
+            Bounds(None, Some(symbol)) => Node::Superscript { target, symbol },
+            Bounds(None, None) => return Ok(target),
+        };
+        Ok(self.commit(node))
+    }
+
     fn get_bounds(&mut self) -> Result<Bounds<'arena>, LatexError<'source>> {
         let mut primes = self.prime_check();
         // Check whether the first bound is specified and is a lower bound.
@@ -844,7 +2954,7 @@ where
             if (first_circumflex && second_circumflex) || (first_underscore && second_underscore) {
                 let TokLoc(loc, token) = self.next_token();
                 return Err(LatexError(
-                    loc,
+                    self.l.token_span(loc),
                     LatexErrKind::CannotBeUsedHere {
                         got: token,
                         correct_place: Place::AfterOpOrIdent,
@@ -921,7 +3031,7 @@ where
             Token::Underscore | Token::Circumflex | Token::Prime
         ) {
             return Err(LatexError(
-                next.location(),
+                self.l.token_span(next.location()),
                 LatexErrKind::CannotBeUsedHere {
                     got: next.into_token(),
                     correct_place: Place::AfterOpOrIdent,
@@ -933,7 +3043,7 @@ where
         // If the bound was a superscript, it may *not* be followed by a prime.
         if is_sup && matches!(self.peek.token(), Token::Prime) {
             return Err(LatexError(
-                self.peek.location(),
+                self.l.token_span(self.peek.location()),
                 LatexErrKind::CannotBeUsedHere {
                     got: Token::Prime,
                     correct_place: Place::AfterOpOrIdent,
@@ -963,12 +3073,32 @@ where
 }
 
 #[inline]
-fn next_token<'source>(peek: &mut TokLoc<'source>, lexer: &mut Lexer<'source>) -> TokLoc<'source> {
+fn next_token<'source>(
+    peek: &mut TokLoc<'source>,
+    lexer: &mut Lexer<'_, 'source>,
+) -> TokLoc<'source> {
     let peek_token = lexer.next_token();
     // Return the previous peek token and store the new peek token.
     mem::replace(peek, peek_token)
 }
 
+/// Whether the node is a `\tag` or an equation number (but not a `\notag`).
+fn is_tag(node: &Node) -> bool {
+    matches!(node, Node::Tag(tag) if !tag.is_empty())
+}
+
+/// Collects the names of all `\label`s in a tree.
+struct LabelCollector<'arena>(Vec<&'arena str>);
+
+impl<'arena> Visit<'arena> for LabelCollector<'arena> {
+    fn enter(&mut self, node: &'arena Node<'arena>) -> bool {
+        if let Node::Label(label) = node {
+            self.0.push(label);
+        }
+        true
+    }
+}
+
 struct Bounds<'arena>(Option<&'arena Node<'arena>>, Option<&'arena Node<'arena>>);
 
 enum LetterCollector<'arena> {
@@ -1008,6 +3138,18 @@ fn extract_letters<'arena>(buffer: &mut StringBuilder, node: &'arena Node<'arena
     true
 }
 
+/// Collect the nodes in the given node, with all nested rows flattened.
+fn flatten_rows<'arena>(node: &'arena Node<'arena>, leaves: &mut Vec<&'arena Node<'arena>>) {
+    match node {
+        Node::Row { nodes, .. } => {
+            for node in nodes.iter() {
+                flatten_rows(node, leaves);
+            }
+        }
+        _ => leaves.push(node),
+    }
+}
+
 fn get_single_char(s: &str) -> Option<char> {
     let mut chars = s.chars();
     match (chars.next(), chars.next()) {
@@ -1016,6 +3158,25 @@ fn get_single_char(s: &str) -> Option<char> {
     }
 }
 
+/// Turn an empty group, like in `\prescript{}{6}{C}`, into a missing script.
+fn non_empty<'arena>(node: &'arena Node<'arena>) -> Option<&'arena Node<'arena>> {
+    match node {
+        Node::Row { nodes: [], .. } => None,
+        _ => Some(node),
+    }
+}
+
+/// Convert a color specification in the given color model, each given with its span.
+fn color_in_model<'source>(
+    (model_span, model): (Range<usize>, &'source str),
+    (span, spec): (Range<usize>, &'source str),
+) -> Result<Rgb, LatexError<'source>> {
+    parse_color_in_model(model, spec).map_err(|e| match e {
+        ColorError::UnknownModel => LatexError(model_span, LatexErrKind::UnknownColorModel(model)),
+        ColorError::InvalidSpec => LatexError(span, LatexErrKind::InvalidColorSpec(spec)),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use insta::assert_ron_snapshot;
@@ -1,6 +1,6 @@
 use mathml_renderer::{
     ast::Node::{self, *},
-    attribute::{MathSpacing, MathVariant, RowAttr, StretchMode},
+    attribute::{MathSpacing, MathVariant, PaddedAttr, RowAttr, StretchMode, Style},
     symbol,
 };
 
@@ -51,6 +51,15 @@ pub static ODV: Node = Frac {
     attr: None,
 };
 
+/// An invisible parenthesis without width, like `\vphantom{(}`.
+pub static MATHSTRUT: Node = Padded {
+    attr: PaddedAttr::ZeroWidth,
+    content: &Phantom(&StretchableOp(
+        symbol::LEFT_PARENTHESIS,
+        StretchMode::NoStretch,
+    )),
+};
+
 static XARROW_SPACING_HACK: Node = Overset {
     target: &Row {
         nodes: &[&Space("0.4286"), &CustomCmdArg(0), &Space("0.4286")],
@@ -59,34 +68,75 @@ static XARROW_SPACING_HACK: Node = Overset {
     symbol: &Space("3.5"),
 };
 
-pub static XRIGHTARROW: Node = Row {
+static CD_LABEL_BELOW_SPACING_HACK: Node = Overset {
+    target: &Row {
+        nodes: &[&Space("0.4286"), &CustomCmdArg(1), &Space("0.4286")],
+        attr: RowAttr::None,
+    },
+    symbol: &Space("3.5"),
+};
+
+/// The arrow `@>above>below>` of the `CD` environment.
+pub static CD_RIGHTARROW: Node = UnderOver {
+    target: &OperatorWithSpacing {
+        op: symbol::RIGHTWARDS_ARROW.as_op(),
+        left: Some(MathSpacing::Zero),
+        right: Some(MathSpacing::Zero),
+    },
+    under: &CD_LABEL_BELOW_SPACING_HACK,
+    over: &XARROW_SPACING_HACK,
+};
+
+/// The arrow `@<above<below<` of the `CD` environment.
+pub static CD_LEFTARROW: Node = UnderOver {
+    target: &OperatorWithSpacing {
+        op: symbol::LEFTWARDS_ARROW.as_op(),
+        left: Some(MathSpacing::Zero),
+        right: Some(MathSpacing::Zero),
+    },
+    under: &CD_LABEL_BELOW_SPACING_HACK,
+    over: &XARROW_SPACING_HACK,
+};
+
+/// The double line `@=` of the `CD` environment.
+pub static CD_EQUALS: Node = Overset {
+    target: &OperatorWithSpacing {
+        op: symbol::EQUALS_SIGN.as_op(),
+        left: Some(MathSpacing::Zero),
+        right: Some(MathSpacing::Zero),
+    },
+    symbol: &Space("3.5"),
+};
+
+static CD_LABEL_LEFT: Node = Row {
+    nodes: &[&CustomCmdArg(0)],
+    attr: RowAttr::Style(Style::ScriptStyle),
+};
+
+static CD_LABEL_RIGHT: Node = Row {
+    nodes: &[&CustomCmdArg(1)],
+    attr: RowAttr::Style(Style::ScriptStyle),
+};
+
+/// The arrow `@VleftVrightV` of the `CD` environment.
+pub static CD_DOWNARROW: Node = Row {
     nodes: &[
-        &Space("0.2778"),
-        &Overset {
-            target: &OperatorWithSpacing {
-                op: symbol::RIGHTWARDS_ARROW.as_op(),
-                left: Some(MathSpacing::Zero),
-                right: Some(MathSpacing::Zero),
-            },
-            symbol: &XARROW_SPACING_HACK,
-        },
-        &Space("0.2778"),
+        &CD_LABEL_LEFT,
+        &StretchableOp(symbol::DOWNWARDS_ARROW, StretchMode::Fence),
+        &CD_LABEL_RIGHT,
     ],
     attr: RowAttr::None,
 };
 
-pub static XLEFTARROW: Node = Row {
+/// The arrow `@AleftArightA` of the `CD` environment.
+pub static CD_UPARROW: Node = Row {
     nodes: &[
-        &Space("0.2778"),
-        &Overset {
-            target: &OperatorWithSpacing {
-                op: symbol::LEFTWARDS_ARROW.as_op(),
-                left: Some(MathSpacing::Zero),
-                right: Some(MathSpacing::Zero),
-            },
-            symbol: &XARROW_SPACING_HACK,
-        },
-        &Space("0.2778"),
+        &CD_LABEL_LEFT,
+        &StretchableOp(symbol::UPWARDS_ARROW, StretchMode::Fence),
+        &CD_LABEL_RIGHT,
     ],
     attr: RowAttr::None,
 };
+
+/// The double line `@|` of the `CD` environment.
+pub static CD_PARALLEL: Node = StretchableOp(symbol::DOUBLE_VERTICAL_LINE, StretchMode::Fence);
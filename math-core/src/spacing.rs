@@ -0,0 +1,451 @@
+//! The spacing between the atoms of a formula.
+//!
+//! Browsers space operators according to the operator dictionary, which only looks at the
+//! operator itself. TeX instead assigns a class to every atom and looks up the space between
+//! two neighbouring atoms in a table (see chapter 18 and appendix G of *The TeXbook*).
+//! Here, we determine the classes of the atoms in a sequence and correct the spacing wherever
+//! the two approaches disagree.
+
+use mathml_renderer::arena::Arena;
+use mathml_renderer::ast::Node;
+use mathml_renderer::attribute::MathSpacing;
+use mathml_renderer::symbol::{self, Op, ParenOp, Rel};
+
+use crate::token::{MathClass, Token};
+
+/// An atom in a sequence, as far as the spacing is concerned.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Atom {
+    class: MathClass,
+    /// Whether the class was given explicitly, like with `\mathrel`. In that case, we don't
+    /// know how the browser would space the content on its own.
+    explicit: bool,
+    /// Whether sub- or superscripts are attached to the atom.
+    scripted: bool,
+}
+
+impl Atom {
+    /// The atom which is produced by the given token, if we know its class.
+    pub(crate) fn from_token(token: &Token) -> Option<Atom> {
+        let class = match token {
+            Token::MathClass(class) => {
+                return Some(Atom {
+                    class: *class,
+                    explicit: true,
+                    scripted: false,
+                });
+            }
+            // A subformula in braces is an ordinary atom, whatever its content.
+            Token::GroupBegin => {
+                return Some(Atom {
+                    class: MathClass::Ord,
+                    explicit: true,
+                    scripted: false,
+                });
+            }
+            Token::BinaryOp(_) => MathClass::Bin,
+            Token::Relation(rel) => relation_class(*rel)?,
+            Token::Delimiter(paren) => delimiter_class(paren),
+            Token::SquareBracketOpen => MathClass::Open,
+            Token::SquareBracketClose => MathClass::Close,
+            Token::Left => MathClass::Inner,
+            Token::Function(_) | Token::Lim(_) | Token::OperatorName => MathClass::Op,
+            Token::Letter(_)
+            | Token::UprightLetter(_)
+            | Token::Number(_)
+            | Token::GetCollectedLetters
+            | Token::Transform(_)
+            | Token::Text(_)
+            | Token::Frac(_)
+            | Token::Binom(_)
+            | Token::Genfrac
+            | Token::Sqrt => MathClass::Ord,
+            _ => return None,
+        };
+        Some(Atom {
+            class,
+            explicit: false,
+            scripted: false,
+        })
+    }
+
+    /// The atom of a predefined node, like the body of a command, if we know its class.
+    pub(crate) fn from_node(node: &Node) -> Option<Atom> {
+        let class = match node {
+            // A row with a single node has the class of that node.
+            Node::Row { nodes: [node], .. } => return Atom::from_node(node),
+            Node::SingleLetterIdent(..)
+            | Node::MultiLetterIdent(_)
+            | Node::CollectedLetters(_)
+            | Node::Number(_)
+            | Node::Text(_)
+            | Node::TextTransform { .. }
+            | Node::Frac { .. } => MathClass::Ord,
+            _ => return None,
+        };
+        Some(Atom {
+            class,
+            explicit: false,
+            scripted: false,
+        })
+    }
+
+    /// The atom after sub- or superscripts have been attached to it.
+    pub(crate) fn with_scripts(self) -> Atom {
+        Atom {
+            scripted: true,
+            ..self
+        }
+    }
+
+    /// The spacing in mu which the browser gives an operator of this atom, if we know it.
+    ///
+    /// `op` is the operator, if it is available.
+    fn natural_spacing(self, op: Option<&Op>, is_first: bool) -> Option<(u8, u8)> {
+        if self.explicit {
+            return None;
+        }
+        match self.class {
+            // A sign at the start of a row is a prefix operator.
+            MathClass::Bin if is_first => op
+                .filter(|op| matches!(op.as_char(), '+' | '−' | '±' | '∓'))
+                .map(|_| (0, 0)),
+            MathClass::Bin => Some((4, 4)),
+            MathClass::Rel => Some((5, 5)),
+            MathClass::Punct => Some((0, 3)),
+            MathClass::Close => Some((0, 0)),
+            _ => None,
+        }
+    }
+}
+
+/// The class of a relation token.
+///
+/// The lexer emits many symbols as relations which belong to other classes in TeX.
+/// For ordinary symbols like primes and quantifiers, we return `None`, because the browser
+/// spaces them differently from other ordinary symbols.
+fn relation_class(rel: Rel) -> Option<MathClass> {
+    match rel {
+        symbol::COMMA | symbol::SEMICOLON => Some(MathClass::Punct),
+        symbol::EXCLAMATION_MARK => Some(MathClass::Close),
+        symbol::AMALGAMATION_OR_COPRODUCT
+        | symbol::ASTERISK_OPERATOR
+        | symbol::BULLET_OPERATOR
+        | symbol::CIRCLED_ASTERISK_OPERATOR
+        | symbol::CIRCLED_DASH
+        | symbol::CIRCLED_DIVISION_SLASH
+        | symbol::CIRCLED_DOT_OPERATOR
+        | symbol::CIRCLED_MINUS
+        | symbol::CIRCLED_PLUS
+        | symbol::CIRCLED_RING_OPERATOR
+        | symbol::CIRCLED_TIMES
+        | symbol::CURLY_LOGICAL_AND
+        | symbol::CURLY_LOGICAL_OR
+        | symbol::DIAMOND_OPERATOR
+        | symbol::DIVISION_TIMES
+        | symbol::DOUBLE_INTERSECTION
+        | symbol::DOUBLE_UNION
+        | symbol::INTERCALATE
+        | symbol::INTERSECTION
+        | symbol::LEFT_NORMAL_FACTOR_SEMIDIRECT_PRODUCT
+        | symbol::LEFT_SEMIDIRECT_PRODUCT
+        | symbol::LOGICAL_AND
+        | symbol::LOGICAL_AND_WITH_DOUBLE_OVERBAR
+        | symbol::LOGICAL_OR
+        | symbol::MULTISET_UNION
+        | symbol::NAND
+        | symbol::RIGHT_NORMAL_FACTOR_SEMIDIRECT_PRODUCT
+        | symbol::RIGHT_SEMIDIRECT_PRODUCT
+        | symbol::RING_OPERATOR
+        | symbol::SET_MINUS
+        | symbol::SMALL_REVERSE_SOLIDUS
+        | symbol::SQUARED_DOT_OPERATOR
+        | symbol::SQUARED_FALLING_DIAGONAL_SLASH
+        | symbol::SQUARED_MINUS
+        | symbol::SQUARED_PLUS
+        | symbol::SQUARED_RISING_DIAGONAL_SLASH
+        | symbol::SQUARED_SQUARE
+        | symbol::SQUARED_TIMES
+        | symbol::SQUARE_CAP
+        | symbol::SQUARE_CUP
+        | symbol::STAR_OPERATOR
+        | symbol::UNION
+        | symbol::WREATH_PRODUCT
+        | symbol::XOR
+        | symbol::Z_NOTATION_SCHEMA_COMPOSITION => Some(MathClass::Bin),
+        symbol::PRIME
+        | symbol::DOUBLE_PRIME
+        | symbol::TRIPLE_PRIME
+        | symbol::QUADRUPLE_PRIME
+        | symbol::REVERSED_PRIME
+        | symbol::REVERSED_DOUBLE_PRIME
+        | symbol::REVERSED_TRIPLE_PRIME
+        | symbol::HORIZONTAL_ELLIPSIS
+        | symbol::VERTICAL_ELLIPSIS
+        | symbol::DOWN_RIGHT_DIAGONAL_ELLIPSIS
+        | symbol::FOR_ALL
+        | symbol::THERE_EXISTS
+        | symbol::THERE_DOES_NOT_EXIST
+        | symbol::NOT_SIGN => None,
+        _ => Some(MathClass::Rel),
+    }
+}
+
+/// The class of a delimiter which is used without `\left` or `\right`.
+fn delimiter_class(paren: &ParenOp) -> MathClass {
+    match char::from(paren) {
+        '(' | '[' | '{' | '⌈' | '⌊' | '⟦' | '⟨' | '⟮' | '⦃' | '⦇' | '⦉' => {
+            MathClass::Open
+        }
+        ')' | ']' | '}' | '⌉' | '⌋' | '⟧' | '⟩' | '⟯' | '⦄' | '⦈' | '⦊' => {
+            MathClass::Close
+        }
+        _ => MathClass::Ord,
+    }
+}
+
+/// The space between two neighbouring atoms in mu, indexed by their classes.
+///
+/// We don't keep track of the math style, so the spaces which TeX leaves out in scripts are
+/// always included. The combinations which can't occur are given as zero.
+const SPACES: [[u8; 8]; 8] = [
+    // Ord Op Bin Rel Open Close Punct Inner
+    [0, 3, 4, 5, 0, 0, 0, 3], // Ord
+    [3, 3, 0, 5, 0, 0, 0, 3], // Op
+    [4, 4, 0, 0, 4, 0, 0, 4], // Bin
+    [5, 5, 0, 0, 5, 0, 0, 5], // Rel
+    [0, 0, 0, 0, 0, 0, 0, 0], // Open
+    [0, 3, 4, 5, 0, 0, 0, 3], // Close
+    [3, 3, 0, 3, 3, 3, 3, 3], // Punct
+    [3, 3, 4, 5, 3, 0, 3, 3], // Inner
+];
+
+/// The classes of the atoms after binary operators without operands have been turned into
+/// ordinary symbols, like the minus in `-x` or in `a = -b`.
+fn resolve_classes(atoms: &[Option<Atom>]) -> Vec<Option<MathClass>> {
+    let mut classes: Vec<Option<MathClass>> = atoms
+        .iter()
+        .map(|atom| atom.map(|atom| atom.class))
+        .collect();
+    for i in 0..classes.len() {
+        let prev = if i > 0 { classes[i - 1] } else { None };
+        match classes[i] {
+            Some(MathClass::Bin)
+                if i == 0
+                    || matches!(
+                        prev,
+                        Some(
+                            MathClass::Bin
+                                | MathClass::Op
+                                | MathClass::Rel
+                                | MathClass::Open
+                                | MathClass::Punct
+                        )
+                    ) =>
+            {
+                classes[i] = Some(MathClass::Ord);
+            }
+            Some(MathClass::Rel | MathClass::Close | MathClass::Punct)
+                if prev == Some(MathClass::Bin) =>
+            {
+                classes[i - 1] = Some(MathClass::Ord);
+            }
+            _ => {}
+        }
+    }
+    if let Some(last @ Some(MathClass::Bin)) = classes.last_mut() {
+        *last = Some(MathClass::Ord);
+    }
+    classes
+}
+
+/// The spacing of an operator, in mu.
+#[derive(Debug, Clone, Copy)]
+struct OpSpacing {
+    /// The spacing which the browser gives the operator, if we know it.
+    natural: Option<(u8, u8)>,
+    left: Option<u8>,
+    right: Option<u8>,
+    /// Whether the operator has scripts, so that we can't change its spacing.
+    fixed: bool,
+}
+
+impl OpSpacing {
+    fn left(&self) -> u8 {
+        self.left
+            .or(self.natural.map(|(left, _)| left))
+            .unwrap_or(0)
+    }
+
+    fn right(&self) -> u8 {
+        self.right
+            .or(self.natural.map(|(_, right)| right))
+            .unwrap_or(0)
+    }
+
+    fn is_adjustable(op: &Option<OpSpacing>) -> bool {
+        op.is_some_and(|op| !op.fixed)
+    }
+}
+
+/// The spacing of an operator, which is either taken from `SPACES` or zero.
+fn mu_to_spacing(mu: u8) -> MathSpacing {
+    match mu {
+        0 => MathSpacing::Zero,
+        3 => MathSpacing::ThreeMu,
+        4 => MathSpacing::FourMu,
+        5 => MathSpacing::FiveMu,
+        _ => unreachable!("the spacing of operators is zero or taken from `SPACES`"),
+    }
+}
+
+/// The width of a space, which is taken from `SPACES` and not zero, in em.
+fn mu_to_space(mu: u8) -> &'static str {
+    match mu {
+        3 => "0.1667",
+        4 => "0.2222",
+        5 => "0.2778",
+        _ => unreachable!("spaces are only inserted with a non-zero width from `SPACES`"),
+    }
+}
+
+/// Apply TeX's spacing to a sequence of nodes.
+///
+/// `atoms` gives the atom of each node, if it is known. Where the spacing differs from what
+/// the browser would do, operators get explicit `lspace` and `rspace` attributes, and spaces
+/// are inserted between nodes which aren't operators.
+pub(crate) fn apply_spacing<'arena>(
+    arena: &'arena Arena,
+    nodes: Vec<&'arena Node<'arena>>,
+    atoms: &[Option<Atom>],
+) -> Vec<&'arena Node<'arena>> {
+    debug_assert_eq!(nodes.len(), atoms.len());
+    let mut atoms = atoms.to_vec();
+    let mut ops: Vec<Option<OpSpacing>> = Vec::with_capacity(nodes.len());
+    for (i, (node, atom)) in nodes.iter().zip(atoms.iter_mut()).enumerate() {
+        let Some(this) = atom else {
+            ops.push(None);
+            continue;
+        };
+        let op = match node {
+            Node::Operator(op, None) => {
+                let natural = this.natural_spacing(Some(op), i == 0);
+                Some(OpSpacing {
+                    natural,
+                    left: None,
+                    right: None,
+                    fixed: false,
+                })
+            }
+            // An explicit class overrides the spacing of operators like `:`.
+            Node::OperatorWithSpacing { .. } if this.explicit => Some(OpSpacing {
+                natural: None,
+                left: None,
+                right: None,
+                fixed: false,
+            }),
+            // Operators which already have explicit spacing or attributes are left alone.
+            Node::Operator(_, Some(_)) | Node::OperatorWithSpacing { .. } => {
+                *atom = None;
+                None
+            }
+            _ if this.scripted
+                && !this.explicit
+                && matches!(
+                    this.class,
+                    MathClass::Bin | MathClass::Rel | MathClass::Punct
+                ) =>
+            {
+                // The browser spaces an operator with scripts like the operator itself.
+                match this.natural_spacing(None, i == 0) {
+                    natural @ Some(_) => Some(OpSpacing {
+                        natural,
+                        left: None,
+                        right: None,
+                        fixed: true,
+                    }),
+                    None => {
+                        *atom = None;
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        ops.push(op);
+    }
+    let classes = resolve_classes(&atoms);
+    for ((op, atom), class) in ops.iter_mut().zip(&atoms).zip(&classes) {
+        if let (Some(op), Some(atom), Some(class)) = (op, atom, class) {
+            // If we don't know the natural spacing, or the operator has been turned into an
+            // ordinary symbol, we set the spacing explicitly.
+            if !op.fixed && (op.natural.is_none() || *class != atom.class) {
+                op.left = Some(0);
+                op.right = Some(0);
+            }
+        }
+    }
+    // The spaces to insert after each node.
+    let mut spaces = vec![0u8; nodes.len()];
+
+    for i in 1..nodes.len() {
+        let (Some(left), Some(right)) = (classes[i - 1], classes[i]) else {
+            continue;
+        };
+        let space = SPACES[left as usize][right as usize];
+        let (before, after) = ops.split_at_mut(i);
+        let (left_op, right_op) = (&mut before[i - 1], &mut after[0]);
+        let left_space = left_op.map_or(0, |op| op.right());
+        let right_space = right_op.map_or(0, |op| op.left());
+        if left_space + right_space == space {
+            continue;
+        }
+        // Preferably, we only change the operator on the right.
+        if OpSpacing::is_adjustable(right_op) && (left_space == 0 || left_space == space) {
+            if let Some(op) = right_op {
+                op.left = Some(space - left_space);
+            }
+        } else if OpSpacing::is_adjustable(left_op) {
+            if let Some(op) = left_op {
+                op.right = Some(space);
+            }
+            if OpSpacing::is_adjustable(right_op) {
+                if let Some(op) = right_op {
+                    op.left = Some(0);
+                }
+            }
+        } else if left_op.is_none() && right_op.is_none() {
+            spaces[i - 1] = space;
+        }
+    }
+
+    let mut result = Vec::with_capacity(nodes.len());
+    for ((node, op_spacing), space) in nodes.into_iter().zip(ops).zip(spaces) {
+        let node = match (node, op_spacing) {
+            (Node::Operator(op, None) | Node::OperatorWithSpacing { op, .. }, Some(spacing)) => {
+                // Only set the spacing where it differs from the natural spacing.
+                let left = spacing
+                    .left
+                    .filter(|left| spacing.natural.is_none_or(|(natural, _)| natural != *left));
+                let right = spacing
+                    .right
+                    .filter(|right| spacing.natural.is_none_or(|(_, natural)| natural != *right));
+                if left.is_some() || right.is_some() {
+                    arena.push(Node::OperatorWithSpacing {
+                        op: *op,
+                        left: left.map(mu_to_spacing),
+                        right: right.map(mu_to_spacing),
+                    })
+                } else {
+                    node
+                }
+            }
+            _ => node,
+        };
+        result.push(node);
+        if space > 0 {
+            result.push(arena.push(Node::Space(mu_to_space(space))));
+        }
+    }
+    result
+}
@@ -1,18 +1,22 @@
 //! Functions for parsing specifications in LaTeX commands.
 
-use std::num::NonZeroU32;
-
 use mathml_renderer::length::{
     AbsoluteLength, FONT_RELATIVE_CONV, FontRelativeLength, FontRelativeUnit, PT_IN_LEN, PX_IN_LEN,
     SpecifiedLength,
 };
 
-const TEN: NonZeroU32 = NonZeroU32::new(10).unwrap();
-
+/// Parse a TeX dimension like `1.5pt`, `-3mu` or `2 cm`.
+///
+/// Absolute units are converted with TeX's ratios relative to `pt`, and `mu` (a math unit)
+/// is converted to `em`, with 18 mu in an em.
 pub(crate) fn parse_length_specification(s: &str) -> Result<SpecifiedLength, ()> {
-    let len = s.len();
+    let s = s.trim();
+    let (is_negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
     // We need at least 2 characters to have a unit.
-    let Some(unit_offset) = len.checked_sub(2) else {
+    let Some(unit_offset) = s.len().checked_sub(2) else {
         return Err(());
     };
     // Check whether we can split the string at the unit offset.
@@ -20,16 +24,51 @@ pub(crate) fn parse_length_specification(s: &str) -> Result<SpecifiedLength, ()>
     let Some((digits, unit)) = s.split_at_checked(unit_offset) else {
         return Err(());
     };
-    let (font_relative_unit, conv) = match unit {
-        "pt" => (None, PT_IN_LEN),
-        "px" => (None, PX_IN_LEN),
-        "ex" => (Some(FontRelativeUnit::Ex), FONT_RELATIVE_CONV),
-        "em" => (Some(FontRelativeUnit::Em), FONT_RELATIVE_CONV),
+    // The conversion factor is given as a fraction.
+    let (font_relative_unit, conv, conv_div): (_, u64, u64) = match unit {
+        "pt" => (None, PT_IN_LEN.get().into(), 1),
+        "px" => (None, PX_IN_LEN.get().into(), 1),
+        // 1 pc = 12 pt
+        "pc" => (None, 12 * u64::from(PT_IN_LEN.get()), 1),
+        // 1 in = 72.27 pt
+        "in" => (None, 7227 * u64::from(PT_IN_LEN.get()), 100),
+        // 1 bp = 1/72 in
+        "bp" => (None, 7227 * u64::from(PT_IN_LEN.get()), 7200),
+        // 1 cm = 1/2.54 in
+        "cm" => (None, 7227 * u64::from(PT_IN_LEN.get()), 254),
+        "mm" => (None, 7227 * u64::from(PT_IN_LEN.get()), 2540),
+        // 1 dd = 1238/1157 pt
+        "dd" => (None, 1238 * u64::from(PT_IN_LEN.get()), 1157),
+        // 1 cc = 12 dd
+        "cc" => (None, 12 * 1238 * u64::from(PT_IN_LEN.get()), 1157),
+        // 1 sp = 1/65536 pt
+        "sp" => (None, PT_IN_LEN.get().into(), 65536),
+        "ex" => (
+            Some(FontRelativeUnit::Ex),
+            FONT_RELATIVE_CONV.get().into(),
+            1,
+        ),
+        "em" => (
+            Some(FontRelativeUnit::Em),
+            FONT_RELATIVE_CONV.get().into(),
+            1,
+        ),
+        // 1 mu = 1/18 em
+        "mu" => (
+            Some(FontRelativeUnit::Em),
+            FONT_RELATIVE_CONV.get().into(),
+            18,
+        ),
         _ => return Err(()),
     };
+    // There may be whitespace between the number and the unit.
+    let digits = digits.trim_end();
+    if !digits.bytes().any(|digit| digit.is_ascii_digit()) {
+        return Err(());
+    }
     let mut digits = digits.bytes();
-    let mut acc: u32 = 0;
-    let mut div = const { NonZeroU32::new(1).unwrap() };
+    let mut acc: u64 = 0;
+    let mut div: u64 = 1;
     for digit in &mut digits {
         if digit == b'.' {
             break;
@@ -37,18 +76,24 @@ pub(crate) fn parse_length_specification(s: &str) -> Result<SpecifiedLength, ()>
         if !digit.is_ascii_digit() {
             return Err(());
         }
-        acc *= 10;
-        acc += u32::from(digit - b'0');
+        acc = acc.checked_mul(10).ok_or(())? + u64::from(digit - b'0');
     }
     for digit in &mut digits {
         if !digit.is_ascii_digit() {
             return Err(());
         }
-        acc *= 10;
-        acc += u32::from(digit - b'0');
-        div = div.saturating_mul(TEN);
+        acc = acc.checked_mul(10).ok_or(())? + u64::from(digit - b'0');
+        div = div.checked_mul(10).ok_or(())?;
     }
-    let value = (acc * conv.get() / div) as i32;
+    // Round to the nearest value which we can represent.
+    let div = div * conv_div;
+    let value = acc
+        .checked_mul(conv)
+        .and_then(|value| value.checked_add(div / 2))
+        .ok_or(())?
+        / div;
+    let value = i32::try_from(value).map_err(|_| ())?;
+    let value = if is_negative { -value } else { value };
     Ok(if let Some(unit) = font_relative_unit {
         SpecifiedLength::from_font_relative_length(FontRelativeLength { value, unit })
     } else {
@@ -137,4 +182,30 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn units() {
+        fn display(s: &str) -> String {
+            let mut output = String::new();
+            parse_length_specification(s)
+                .expect("valid")
+                .push_to_string(&mut output);
+            output
+        }
+        assert_eq!(display("1pc"), "12pt");
+        assert_eq!(display("1in"), "72.27pt");
+        assert_eq!(display("-2.5pt"), "-2.5pt");
+        assert_eq!(display("+2.5pt"), "2.5pt");
+        assert_eq!(display(" 1.5 em "), "1.5em");
+        assert_eq!(display("18mu"), "1em");
+        assert_eq!(display("-9mu"), "-0.5em");
+        assert_eq!(display("0sp"), "0");
+    }
+
+    #[test]
+    fn invalid() {
+        for s in ["", "pt", "1", "1xy", "--1pt", "1.2.3pt", "-pt", "1 2pt"] {
+            assert!(parse_length_specification(s).is_err(), "{s:?}");
+        }
+    }
 }
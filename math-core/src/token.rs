@@ -1,7 +1,9 @@
 use std::mem::discriminant;
 
 use mathml_renderer::ast::Node;
-use mathml_renderer::attribute::{FracAttr, MathVariant, OpAttr, Size, Style, TextTransform};
+use mathml_renderer::attribute::{
+    FracAttr, LineType, MathVariant, Notation, OpAttr, PaddedAttr, Size, Style, TextTransform,
+};
 use mathml_renderer::symbol::{Big, Bin, Op, ParenOp, Rel};
 use strum_macros::AsRefStr;
 
@@ -47,6 +49,8 @@ pub enum Token<'source> {
     #[strum(serialize = "^")]
     Circumflex,
     Binom(Option<FracAttr>),
+    /// An infix command like `\over`, which turns the current group into a fraction.
+    Infix(InfixFrac),
     #[strum(serialize = r"\overset")]
     Overset,
     #[strum(serialize = r"\underset")]
@@ -57,14 +61,30 @@ pub enum Token<'source> {
     Integral(Big),
     #[strum(serialize = r"\limits")]
     Limits,
+    #[strum(serialize = r"\nolimits")]
+    NoLimits,
     Lim(&'static str),
     Space(&'static str),
+    /// A command like `\hspace`, which takes a length in braces.
+    #[strum(serialize = r"\hspace")]
+    Hspace,
+    /// A command like `\kern`, which is directly followed by a length.
+    #[strum(serialize = r"\kern")]
+    Kern,
+    /// A command like `\hskip`, which is followed by glue, i.e., a length which may
+    /// have a stretch and a shrink component.
+    #[strum(serialize = r"\hskip")]
+    Skip,
+    #[strum(serialize = r"\rule")]
+    Rule,
     #[strum(serialize = "~")]
     NonBreakingSpace,
     Whitespace,
     Transform(MathVariant),
     Big(Size),
     OverUnder(Op, bool, Option<OpAttr>),
+    /// An arrow like `\xrightarrow`, which stretches to fit its labels.
+    ExtensibleArrow(Op),
     Relation(Rel),
     #[strum(serialize = "binary operator")]
     BinaryOp(Bin),
@@ -85,16 +105,84 @@ pub enum Token<'source> {
     Function(&'static str),
     #[strum(serialize = r"\operatorname")]
     OperatorName,
+    /// A command like `\mathbin`, which turns its argument into an atom of the given class.
+    MathClass(MathClass),
     Slashed,
+    /// `\phantom`, or `\hphantom` and `\vphantom`, which keep only one dimension.
+    Phantom(Option<PaddedAttr>),
+    #[strum(serialize = r"\smash")]
+    Smash,
+    /// A command like `\llap`, whose argument is text.
+    Lap(PaddedAttr),
+    /// A command like `\mathllap`, whose argument is math.
+    MathLap(PaddedAttr),
+    /// A command like `\boxed` or `\cancel`, whose argument is math.
+    Enclose(Notation),
+    /// `\fbox` or `\framebox`, whose argument is text.
+    #[strum(serialize = r"\fbox")]
+    FBox,
+    #[strum(serialize = r"\cancelto")]
+    CancelTo,
+    #[strum(serialize = r"\sideset")]
+    Sideset,
+    #[strum(serialize = r"\prescript")]
+    Prescript,
+    #[strum(serialize = r"\tensor")]
+    Tensor,
+    #[strum(serialize = r"\indices")]
+    Indices,
+    #[strum(serialize = r"\substack")]
+    Substack,
     #[strum(serialize = r"\not")]
     Not,
     #[strum(serialize = r"\text*")]
     Text(Option<TextTransform>),
     Style(Style),
+    #[strum(serialize = r"\color")]
     Color,
+    #[strum(serialize = r"\textcolor")]
+    TextColor,
+    #[strum(serialize = r"\colorbox")]
+    ColorBox,
+    #[strum(serialize = r"\fcolorbox")]
+    FColorBox,
+    #[strum(serialize = r"\definecolor")]
+    DefineColor,
     CustomCmd(usize, &'static Node<'static>),
+    #[strum(serialize = r"\newcommand")]
+    NewCommand,
+    #[strum(serialize = r"\renewcommand")]
+    RenewCommand,
+    #[strum(serialize = r"\def")]
+    Def,
+    /// A command that was defined by the user with `\newcommand` or `\def`.
+    UserCmd(&'source str),
+    /// A parameter like `#1` in the body of a user-defined command.
+    #[strum(serialize = "#")]
+    CustomCmdArg(u8),
     GetCollectedLetters,
     HardcodedMathML(&'static str),
+    #[strum(serialize = r"\intertext")]
+    Intertext,
+    #[strum(serialize = r"\tag")]
+    Tag,
+    #[strum(serialize = r"\notag")]
+    NoTag,
+    #[strum(serialize = r"\label")]
+    Label,
+    #[strum(serialize = r"\eqref")]
+    EqRef,
+    #[strum(serialize = r"\hline")]
+    HLine(LineType),
+    #[strum(serialize = r"\cline")]
+    CLine,
+    /// An arrow in a commutative diagram, like `@>>>`.
+    #[strum(serialize = "@")]
+    CDArrow(CDArrow),
+    /// The end of a label of an arrow in a commutative diagram, like the second `>`
+    /// in `@>f>>`.
+    #[strum(serialize = "end of arrow label")]
+    CDLabelEnd,
     UnknownCommand(&'source str),
 }
 
@@ -137,6 +225,80 @@ impl TryFrom<char> for Digit {
     }
 }
 
+/// The arrows of the `CD` environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CDArrow {
+    /// `@>>>`
+    Right,
+    /// `@<<<`
+    Left,
+    /// `@VVV`
+    Down,
+    /// `@AAA`
+    Up,
+    /// `@=`
+    Equals,
+    /// `@|`
+    Parallel,
+    /// `@.`, an empty space in place of an arrow.
+    Empty,
+}
+
+impl CDArrow {
+    /// Whether the arrow goes between the rows of the diagram, rather than between
+    /// the objects in a row.
+    pub(crate) fn is_vertical(self) -> bool {
+        matches!(self, CDArrow::Down | CDArrow::Up | CDArrow::Parallel)
+    }
+}
+
+/// The infix commands of plain TeX, which make a fraction of everything before and after
+/// them in the current group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfixFrac {
+    /// `\over`, a fraction with the default rule.
+    Over,
+    /// `\atop`, a fraction without a rule.
+    Atop,
+    /// `\above`, which is followed by the thickness of the rule.
+    Above,
+    /// `\choose`, which is `\atop` in parentheses.
+    Choose,
+    /// `\brace`, which is `\atop` in braces.
+    Brace,
+    /// `\brack`, which is `\atop` in square brackets.
+    Brack,
+    /// `\overwithdelims`, which is followed by two delimiters.
+    OverWithDelims,
+    /// `\atopwithdelims`, which is followed by two delimiters.
+    AtopWithDelims,
+    /// `\abovewithdelims`, which is followed by two delimiters and the thickness of the rule.
+    AboveWithDelims,
+}
+
+/// The classes of atoms in a formula, which determine the spacing between them.
+///
+/// See chapter 17 of *The TeXbook*.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathClass {
+    /// An ordinary symbol, like a letter or a number.
+    Ord,
+    /// A large operator, like `\sum` or `\sin`.
+    Op,
+    /// A binary operator, like `+`.
+    Bin,
+    /// A relation, like `=`.
+    Rel,
+    /// An opening delimiter, like `(`.
+    Open,
+    /// A closing delimiter, like `)`.
+    Close,
+    /// A punctuation mark, like `,`.
+    Punct,
+    /// A delimited subformula, like `\left( x \right)`.
+    Inner,
+}
+
 #[derive(Debug)]
 pub struct TokLoc<'source>(pub usize, pub Token<'source>);
 
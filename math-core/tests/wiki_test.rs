@@ -4,7 +4,7 @@ use insta::assert_snapshot;
 use regex::Regex;
 // use similar::{ChangeTag, TextDiff};
 
-use math_core::{Display, latex_to_mathml};
+use math_core::{MathCoreConfig, latex_to_mathml};
 
 #[test]
 fn wiki_test() {
@@ -873,9 +873,10 @@ fn wiki_test() {
     let mut n_match = 0usize;
     let mut n_diff = 0usize;
     let mut n_fail = 0usize;
+    let config = MathCoreConfig::default();
     for (i, (latex, correct)) in problems.into_iter().enumerate() {
         let with_row = "{".to_string() + latex + "}";
-        let mathml = latex_to_mathml(&with_row, Display::Inline, false);
+        let mathml = latex_to_mathml(&with_row, &config);
         match mathml {
             Ok(mathml) => {
                 if mathml != correct {
@@ -905,8 +906,8 @@ fn wiki_test() {
         }
     }
     assert_eq!(n_match, 10);
-    assert_eq!(n_diff, 177);
-    assert_eq!(n_fail, 31);
+    assert_eq!(n_diff, 184);
+    assert_eq!(n_fail, 24);
 }
 
 /// Prettify HTML input
@@ -943,6 +944,7 @@ pub fn prettify(input: &str) -> String {
 }
 
 #[test]
+#[allow(clippy::expect_fun_call)]
 fn test_nonfailing_wiki_tests() {
     let problems = [
         (0, r"\alpha"),
@@ -1378,8 +1380,12 @@ fn test_nonfailing_wiki_tests() {
         ),
     ];
 
+    let config = MathCoreConfig {
+        pretty: true,
+        ..Default::default()
+    };
     for (num, problem) in problems.into_iter() {
-        let mathml = latex_to_mathml(problem, crate::Display::Inline, true)
+        let mathml = latex_to_mathml(problem, &config)
             .expect(format!("failed to convert `{}`", problem).as_str());
         let name = format!("wiki{:03}", num);
         assert_snapshot!(name.as_str(), &mathml, problem);
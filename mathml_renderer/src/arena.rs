@@ -1,6 +1,6 @@
 use bumpalo::Bump;
 
-use crate::ast::Node;
+use crate::ast::{ArraySpec, ColumnSpec, Node, ScriptPair};
 
 pub struct Arena {
     bump: Bump,
@@ -45,11 +45,38 @@ impl Arena {
         self.bump.alloc_slice_copy(nodes)
     }
 
-    fn alloc_str(&self, src: &str) -> &str {
+    pub fn alloc_str(&self, src: &str) -> &str {
         self.bump
             .try_alloc_str(src)
             .unwrap_or_else(|_| std::process::abort())
     }
+
+    pub fn alloc_array_spec<'arena>(
+        &'arena self,
+        spec: ArraySpec<'arena>,
+    ) -> &'arena ArraySpec<'arena> {
+        self.bump
+            .try_alloc_with(|| spec)
+            .unwrap_or_else(|_| std::process::abort())
+    }
+
+    pub fn push_column_specs<'arena>(
+        &'arena self,
+        columns: &[ColumnSpec<'arena>],
+    ) -> &'arena [ColumnSpec<'arena>] {
+        self.bump
+            .try_alloc_slice_copy(columns)
+            .unwrap_or_else(|_| std::process::abort())
+    }
+
+    pub fn push_script_pairs<'arena>(
+        &'arena self,
+        pairs: &[ScriptPair<'arena>],
+    ) -> &'arena [ScriptPair<'arena>] {
+        self.bump
+            .try_alloc_slice_copy(pairs)
+            .unwrap_or_else(|_| std::process::abort())
+    }
 }
 
 impl Default for Arena {
@@ -1,14 +1,14 @@
-use std::mem;
-
 #[cfg(feature = "serde")]
 use serde::Serialize;
 
 use crate::attribute::{
-    Align, FracAttr, MathSpacing, MathVariant, OpAttr, RowAttr, Size, StretchMode, Stretchy, Style,
+    Align, ColumnAlign, FracAttr, LineType, MathSpacing, MathVariant, Notation, OpAttr, PaddedAttr,
+    RowAttr, Size, StretchMode, Stretchy, Style,
 };
 use crate::itoa::append_u8_as_hex;
 use crate::length::SpecifiedLength;
 use crate::symbol::{Op, ParenOp};
+use crate::visit::{self, Visit};
 
 /// AST node
 #[derive(Debug)]
@@ -27,8 +27,22 @@ pub enum Node<'arena> {
         right: Option<MathSpacing>,
     },
     MultiLetterIdent(&'arena str),
+    /// An identifier which is used as a large operator, like `T` in `\mathop{T}_n`.
+    ///
+    /// Only operators can have limits which become scripts outside of display style,
+    /// so it is rendered as an operator with `movablelimits`. The spacing around it is
+    /// left to the surrounding nodes.
+    IdentOp(&'arena str),
     CollectedLetters(&'arena str),
     Space(&'static str),
+    /// A space of the given width, like `\hspace{1cm}` or `\kern-3mu`.
+    Kern(SpecifiedLength),
+    /// A filled box, like `\rule[raise]{width}{height}`.
+    Rule {
+        width: SpecifiedLength,
+        height: SpecifiedLength,
+        raise: Option<SpecifiedLength>,
+    },
     Subscript {
         target: &'arena Node<'arena>,
         symbol: &'arena Node<'arena>,
@@ -85,23 +99,107 @@ pub enum Node<'arena> {
         align: Align,
         attr: Option<FracAttr>,
     },
+    /// An `array` environment, where each column has its own alignment and rules.
+    Array {
+        content: &'arena [&'arena Node<'arena>],
+        array_spec: &'arena ArraySpec<'arena>,
+    },
     ColumnSeparator,
     RowSeparator,
+    /// The equation number or tag of the current row of a table, like `(1)`.
+    ///
+    /// An empty tag, as from `\notag`, suppresses the automatic number.
+    Tag(&'arena str),
+    /// A `\label`, which becomes an anchor that references to the equation can link to.
+    ///
+    /// In a table, the row with the label gets it as its `id`.
+    Label(&'arena str),
+    /// A reference to a labelled equation from `\eqref`, showing its number.
+    ///
+    /// It links to the anchor of the equation with the `href` attribute of MathML.
+    Reference {
+        label: &'arena str,
+        number: &'arena Node<'arena>,
+    },
+    /// Text from `\intertext` or `\shortintertext`, which takes up a whole row of a table.
+    Intertext(&'arena Node<'arena>),
+    /// A rule above the current row of an array, from `\hline`, `\hdashline` or `\cline`.
+    ///
+    /// If `columns` is set, the rule only spans the given columns (counting from 1).
+    HLine {
+        line: LineType,
+        columns: Option<(u8, u8)>,
+    },
     Slashed(&'arena Node<'arena>),
+    Phantom(&'arena Node<'arena>),
+    Padded {
+        attr: PaddedAttr,
+        content: &'arena Node<'arena>,
+    },
+    /// Content with a frame or a line through it, like from `\boxed` or `\cancel`.
+    Enclose {
+        content: &'arena Node<'arena>,
+        notation: Notation,
+    },
+    /// Content on a colored background, like from `\colorbox`, optionally with a
+    /// colored frame, like from `\fcolorbox`.
+    ColorBox {
+        content: &'arena Node<'arena>,
+        background: (u8, u8, u8),
+        frame: Option<(u8, u8, u8)>,
+    },
+    /// A base with scripts on both sides, like from `\sideset` or `\tensor`.
+    ///
+    /// The first `num_postscripts` pairs of scripts follow the base, and the rest
+    /// precede it.
     Multiscript {
         base: &'arena Node<'arena>,
-        sub: &'arena Node<'arena>,
+        scripts: &'arena [ScriptPair<'arena>],
+        num_postscripts: u32,
     },
     TextTransform {
         tf: MathVariant,
         content: &'arena Node<'arena>,
     },
     CustomCmd {
-        predefined: &'static Node<'static>,
+        predefined: &'arena Node<'arena>,
         args: &'arena [&'arena Node<'arena>],
     },
     CustomCmdArg(usize),
     HardcodedMathML(&'static str),
+    /// A part of the input which could not be parsed.
+    Error(&'arena str),
+}
+
+/// The column specification of an `array`, like `{l|c@{.}r}`.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ArraySpec<'arena> {
+    /// A rule before the first column.
+    pub beginning_line: Option<LineType>,
+    /// Material from `@{...}` before the first column.
+    pub beginning_separator: Option<&'arena Node<'arena>>,
+    pub columns: &'arena [ColumnSpec<'arena>],
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ColumnSpec<'arena> {
+    pub align: ColumnAlign,
+    /// A rule after the column.
+    pub line_after: Option<LineType>,
+    /// Material from `@{...}` after the column, which replaces the space between columns.
+    pub separator_after: Option<&'arena Node<'arena>>,
+}
+
+/// A subscript and a superscript above each other in a `Node::Multiscript`.
+///
+/// `None` leaves the place of the script empty.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ScriptPair<'arena> {
+    pub sub: Option<&'arena Node<'arena>>,
+    pub sup: Option<&'arena Node<'arena>>,
 }
 
 impl PartialEq for &'static Node<'static> {
@@ -110,8 +208,6 @@ impl PartialEq for &'static Node<'static> {
     }
 }
 
-const INDENT: &str = "    ";
-
 macro_rules! push {
     ($buf:expr, @ $c:expr $(,)?) => {{
         $buf.push($c.into());
@@ -130,25 +226,37 @@ macro_rules! push {
 }
 
 macro_rules! pushln {
-    ($buf:expr, $indent:expr, $($tail:tt)+) => {
-        new_line_and_indent($buf, $indent);
-        push!($buf, $($tail)+)
+    ($emitter:expr, $indent:expr, $($tail:tt)+) => {
+        $emitter.new_line_and_indent($indent);
+        push!($emitter.s, $($tail)+)
     };
 }
 
 pub struct MathMLEmitter<'arena> {
     s: String,
     var: Option<MathVariant>,
-    custom_cmd_args: Option<&'arena [&'arena Node<'arena>]>,
+    /// The number of spaces per level of indentation.
+    indent_width: usize,
+    /// The arguments of the custom commands we are currently in, innermost last.
+    custom_cmd_args: Vec<&'arena [&'arena Node<'arena>]>,
+    /// The labels which are the `id` of the table rows we are currently in.
+    row_labels: Vec<&'arena str>,
 }
 
 impl<'arena> MathMLEmitter<'arena> {
     #[inline]
     pub fn new() -> Self {
+        Self::with_indent_width(4)
+    }
+
+    #[inline]
+    pub fn with_indent_width(indent_width: usize) -> Self {
         Self {
             s: String::new(),
             var: None,
-            custom_cmd_args: None,
+            indent_width,
+            custom_cmd_args: Vec::new(),
+            row_labels: Vec::new(),
         }
     }
 
@@ -177,6 +285,27 @@ impl<'arena> MathMLEmitter<'arena> {
         self.s.push_str(s);
     }
 
+    /// Push text, escaping the characters which have a special meaning in XML.
+    pub fn push_escaped(&mut self, text: &str) {
+        for c in text.chars() {
+            match c {
+                '&' => self.s.push_str("&amp;"),
+                '<' => self.s.push_str("&lt;"),
+                '>' => self.s.push_str("&gt;"),
+                c => self.s.push(c),
+            }
+        }
+    }
+
+    /// Start a new line with the given level of indentation, unless the level is 0.
+    pub fn new_line_and_indent(&mut self, indent_num: usize) {
+        if indent_num > 0 {
+            self.s.push('\n');
+        }
+        let num_spaces = indent_num * self.indent_width;
+        self.s.extend(std::iter::repeat_n(' ', num_spaces));
+    }
+
     pub fn emit(&mut self, node: &'arena Node<'arena>, base_indent: usize) {
         // Compute the indent for the children of the node.
         let child_indent = if base_indent > 0 {
@@ -189,12 +318,16 @@ impl<'arena> MathMLEmitter<'arena> {
             node,
             Node::ColumnSeparator
                 | Node::RowSeparator
+                | Node::HLine { .. }
+                | Node::Tag(_)
+                | Node::Label(_)
+                | Node::Intertext(_)
                 | Node::TextTransform { .. }
                 | Node::CustomCmd { .. }
                 | Node::CustomCmdArg(_)
         ) {
             // Get the base indent out of the way.
-            new_line_and_indent(&mut self.s, base_indent);
+            self.new_line_and_indent(base_indent);
         }
 
         match node {
@@ -226,7 +359,7 @@ impl<'arena> MathMLEmitter<'arena> {
                 push!(self.s, @c, "</mi>");
             }
             Node::TextTransform { content, tf } => {
-                let old_var = mem::replace(&mut self.var, Some(*tf));
+                let old_var = self.var.replace(*tf);
                 self.emit(content, base_indent);
                 self.var = old_var;
             }
@@ -271,6 +404,14 @@ impl<'arena> MathMLEmitter<'arena> {
             Node::MultiLetterIdent(letters) => {
                 push!(self.s, "<mi>", letters, "</mi>");
             }
+            Node::IdentOp(letters) => {
+                push!(
+                    self.s,
+                    r#"<mo lspace="0em" rspace="0em" movablelimits="true">"#,
+                    letters,
+                    "</mo>"
+                );
+            }
             node @ (Node::CollectedLetters(letters) | Node::Text(letters)) => {
                 let (open, close) = match node {
                     Node::CollectedLetters(_) => ("<mi>", "</mi>"),
@@ -288,6 +429,31 @@ impl<'arena> MathMLEmitter<'arena> {
                 push!(self.s, close);
             }
             Node::Space(space) => push!(self.s, "<mspace width=\"", space, "em\"/>"),
+            Node::Kern(width) => {
+                push!(self.s, "<mspace width=\"");
+                width.push_to_string(&mut self.s);
+                push!(self.s, "\"/>");
+            }
+            Node::Rule {
+                width,
+                height,
+                raise,
+            } => {
+                if let Some(raise) = raise {
+                    push!(self.s, "<mpadded voffset=\"");
+                    raise.push_to_string(&mut self.s);
+                    push!(self.s, "\">");
+                    self.new_line_and_indent(child_indent);
+                }
+                push!(self.s, "<mspace width=\"");
+                width.push_to_string(&mut self.s);
+                push!(self.s, "\" height=\"");
+                height.push_to_string(&mut self.s);
+                push!(self.s, "\" style=\"background: currentcolor\"/>");
+                if raise.is_some() {
+                    pushln!(self, base_indent, "</mpadded>");
+                }
+            }
             // The following nodes have exactly two children.
             node @ (Node::Subscript {
                 symbol: second,
@@ -318,7 +484,7 @@ impl<'arena> MathMLEmitter<'arena> {
                 push!(self.s, open);
                 self.emit(first, child_indent);
                 self.emit(second, child_indent);
-                pushln!(&mut self.s, base_indent, close);
+                pushln!(self, base_indent, close);
             }
             // The following nodes have exactly three children.
             node @ (Node::SubSup {
@@ -341,36 +507,62 @@ impl<'arena> MathMLEmitter<'arena> {
                 self.emit(first, child_indent);
                 self.emit(second, child_indent);
                 self.emit(third, child_indent);
-                pushln!(&mut self.s, base_indent, close);
+                pushln!(self, base_indent, close);
             }
-            Node::Multiscript { base, sub } => {
+            Node::Multiscript {
+                base,
+                scripts,
+                num_postscripts,
+            } => {
                 push!(self.s, "<mmultiscripts>");
                 self.emit(base, child_indent);
-                pushln!(&mut self.s, child_indent, "<mprescripts/>");
-                self.emit(sub, child_indent);
-                pushln!(&mut self.s, child_indent, "<mrow></mrow>");
-                pushln!(&mut self.s, base_indent, "</mmultiscripts>");
+                for (i, pair) in scripts.iter().enumerate() {
+                    if i == *num_postscripts as usize {
+                        pushln!(self, child_indent, "<mprescripts/>");
+                    }
+                    for script in [pair.sub, pair.sup] {
+                        match script {
+                            Some(script) => self.emit(script, child_indent),
+                            None => {
+                                pushln!(self, child_indent, "<mrow></mrow>");
+                            }
+                        }
+                    }
+                }
+                pushln!(self, base_indent, "</mmultiscripts>");
             }
             Node::OverOp(op, attr, target) => {
                 push!(self.s, "<mover>");
                 self.emit(target, child_indent);
-                pushln!(&mut self.s, child_indent, "<mo accent=\"true\"");
+                pushln!(self, child_indent, "<mo accent=\"true\"");
                 if let Some(attr) = attr {
                     push!(self.s, attr);
                 }
                 push!(self.s, ">", @op, "</mo>");
-                pushln!(&mut self.s, base_indent, "</mover>");
+                pushln!(self, base_indent, "</mover>");
             }
             Node::UnderOp(op, target) => {
                 push!(self.s, "<munder>");
                 self.emit(target, child_indent);
-                pushln!(&mut self.s, child_indent, "<mo accent=\"true\">", @op, "</mo>");
-                pushln!(&mut self.s, base_indent, "</munder>");
+                pushln!(self, child_indent, "<mo accent=\"true\">", @op, "</mo>");
+                pushln!(self, base_indent, "</munder>");
+            }
+            Node::Label(label) => {
+                // Outside of a table, or if the row already has another label,
+                // an empty row serves as the anchor.
+                if !self.row_labels.contains(label) {
+                    pushln!(self, base_indent, "<mrow id=\"", label, "\"></mrow>");
+                }
+            }
+            Node::Reference { label, number } => {
+                push!(self.s, "<mrow href=\"#", label, "\">");
+                self.emit(number, child_indent);
+                pushln!(self, base_indent, "</mrow>");
             }
             Node::Sqrt(content) => {
                 push!(self.s, "<msqrt>");
                 self.emit(content, child_indent);
-                pushln!(&mut self.s, base_indent, "</msqrt>");
+                pushln!(self, base_indent, "</msqrt>");
             }
             Node::Frac { num, den, lt, attr } => {
                 push!(self.s, "<mfrac");
@@ -385,7 +577,7 @@ impl<'arena> MathMLEmitter<'arena> {
                 push!(self.s, ">");
                 self.emit(num, child_indent);
                 self.emit(den, child_indent);
-                pushln!(&mut self.s, base_indent, "</mfrac>");
+                pushln!(self, base_indent, "</mfrac>");
             }
             Node::Row { nodes, attr: style } => {
                 match style {
@@ -402,7 +594,7 @@ impl<'arena> MathMLEmitter<'arena> {
                 for node in nodes.iter() {
                     self.emit(node, child_indent);
                 }
-                pushln!(&mut self.s, base_indent, "</mrow>");
+                pushln!(self, base_indent, "</mrow>");
             }
             Node::Fenced {
                 open,
@@ -414,12 +606,12 @@ impl<'arena> MathMLEmitter<'arena> {
                     Some(style) => push!(self.s, "<mrow", style, ">"),
                     None => push!(self.s, "<mrow>"),
                 }
-                new_line_and_indent(&mut self.s, child_indent);
+                self.new_line_and_indent(child_indent);
                 self.emit_stretchy_op(StretchMode::Fence, open);
                 self.emit(content, child_indent);
-                new_line_and_indent(&mut self.s, child_indent);
+                self.new_line_and_indent(child_indent);
                 self.emit_stretchy_op(StretchMode::Fence, close);
-                pushln!(&mut self.s, base_indent, "</mrow>");
+                pushln!(self, base_indent, "</mrow>");
             }
             Node::SizedParen(size, paren) => {
                 push!(self.s, "<mo maxsize=\"", size, "\" minsize=\"", size, "\"");
@@ -428,6 +620,65 @@ impl<'arena> MathMLEmitter<'arena> {
                 }
                 push!(self.s, ">", @*paren, "</mo>");
             }
+            Node::Phantom(content) => {
+                push!(self.s, "<mphantom>");
+                self.emit(content, child_indent);
+                pushln!(self, base_indent, "</mphantom>");
+            }
+            Node::Padded { attr, content } => {
+                push!(self.s, "<mpadded", attr, ">");
+                // MathML Core has no `lspace="-1width"`, so we shift the content with CSS.
+                let shift = match attr {
+                    PaddedAttr::LeftOverlap => Some("-100%"),
+                    PaddedAttr::CenterOverlap => Some("-50%"),
+                    _ => None,
+                };
+                if let Some(shift) = shift {
+                    let child_indent2 = if base_indent > 0 {
+                        child_indent.saturating_add(1)
+                    } else {
+                        0
+                    };
+                    pushln!(
+                        self,
+                        child_indent,
+                        "<mrow style=\"transform: translateX(",
+                        shift,
+                        ")\">"
+                    );
+                    self.emit(content, child_indent2);
+                    pushln!(self, child_indent, "</mrow>");
+                } else {
+                    self.emit(content, child_indent);
+                }
+                pushln!(self, base_indent, "</mpadded>");
+            }
+            Node::Enclose { content, notation } => {
+                push!(self.s, "<mrow style=\"", notation.as_style(), "\">");
+                self.emit(content, child_indent);
+                pushln!(self, base_indent, "</mrow>");
+            }
+            Node::ColorBox {
+                content,
+                background: (r, g, b),
+                frame,
+            } => {
+                push!(self.s, "<mrow style=\"");
+                if let Some((fr, fg, fb)) = frame {
+                    push!(self.s, "border:0.4pt solid #");
+                    append_u8_as_hex(&mut self.s, *fr);
+                    append_u8_as_hex(&mut self.s, *fg);
+                    append_u8_as_hex(&mut self.s, *fb);
+                    push!(self.s, ";");
+                }
+                push!(self.s, "background-color:#");
+                append_u8_as_hex(&mut self.s, *r);
+                append_u8_as_hex(&mut self.s, *g);
+                append_u8_as_hex(&mut self.s, *b);
+                push!(self.s, ";padding:3pt;\">");
+                self.emit(content, child_indent);
+                pushln!(self, base_indent, "</mrow>");
+            }
             Node::Slashed(node) => match node {
                 Node::SingleLetterIdent(x, is_normal) => {
                     if *is_normal || matches!(self.var, Some(MathVariant::Normal)) {
@@ -456,78 +707,456 @@ impl<'arena> MathMLEmitter<'arena> {
                 } else {
                     0
                 };
+                // Script-level tables like `\substack` have compact rows.
+                let compact = matches!(
+                    attr,
+                    Some(FracAttr::SubstackStyle | FracAttr::SmallMatrixStyle)
+                );
                 let odd_col = match align {
-                    Align::Center => "<mtd>",
+                    Align::Center if compact => {
+                        r#"<mtd style="padding-top: 0; padding-bottom: 0">"#
+                    }
+                    Align::Left if compact => {
+                        r#"<mtd style="text-align: -webkit-left; text-align: -moz-left; padding-top: 0; padding-bottom: 0">"#
+                    }
+                    Align::Center | Align::Multline => "<mtd>",
                     Align::Left => {
                         r#"<mtd style="text-align: -webkit-left; text-align: -moz-left; padding-right: 0">"#
                     }
-                    Align::Alternating => {
+                    Align::Alternating | Align::AlternatingFullWidth => {
                         r#"<mtd style="text-align: -webkit-right; text-align: -moz-right; padding-right: 0">"#
                     }
+                    Align::AlternatingTight => {
+                        r#"<mtd style="text-align: -webkit-right; text-align: -moz-right; padding-right: 0; padding-left: 0">"#
+                    }
                 };
                 let even_col = match align {
-                    Align::Center => "<mtd>",
+                    Align::Center if compact => {
+                        r#"<mtd style="padding-top: 0; padding-bottom: 0">"#
+                    }
+                    Align::Left if compact => {
+                        r#"<mtd style="text-align: -webkit-left; text-align: -moz-left; padding-top: 0; padding-bottom: 0">"#
+                    }
+                    Align::Center | Align::Multline => "<mtd>",
                     Align::Left => {
                         "<mtd style=\"text-align: -webkit-left; text-align: -moz-left; padding-right: 0; padding-left: 1em\">"
                     }
-                    Align::Alternating => {
+                    Align::Alternating | Align::AlternatingFullWidth => {
                         "<mtd style=\"text-align: -webkit-left; text-align: -moz-left; padding-left: 0\">"
                     }
+                    Align::AlternatingTight => {
+                        "<mtd style=\"text-align: -webkit-left; text-align: -moz-left; padding-left: 0; padding-right: 0\">"
+                    }
                 };
+                let num_rows = content
+                    .iter()
+                    .filter(|node| matches!(node, Node::RowSeparator))
+                    .count()
+                    + 1;
+                // In `multline`, the first row is aligned left and the last row right.
+                let first_col = |row: usize| match align {
+                    Align::Multline if num_rows > 1 && row == 0 => {
+                        r#"<mtd style="text-align: -webkit-left; text-align: -moz-left">"#
+                    }
+                    Align::Multline if num_rows > 1 && row + 1 == num_rows => {
+                        r#"<mtd style="text-align: -webkit-right; text-align: -moz-right">"#
+                    }
+                    _ => odd_col,
+                };
+
+                // With equation numbers, every row gets an empty cell on the left and
+                // the number on the right. Both take up the remaining width in equal
+                // parts, so that the equations stay centered.
+                let numbered = content
+                    .iter()
+                    .any(|node| matches!(node, Node::Tag(tag) if !tag.is_empty()));
+                let num_columns = count_columns(content);
 
                 let mut col: usize = 1;
+                let mut row: usize = 0;
+                let mut row_is_empty = true;
+                let mut after_intertext = false;
+                let mut tag: Option<&str> = None;
                 push!(self.s, "<mtable");
                 if let Some(attr) = attr {
                     push!(self.s, attr);
                 }
+                if numbered || matches!(align, Align::AlternatingFullWidth | Align::Multline) {
+                    push!(self.s, r#" style="width: 100%""#);
+                }
                 push!(self.s, ">");
-                pushln!(&mut self.s, child_indent, "<mtr>");
-                pushln!(&mut self.s, child_indent2, odd_col);
-                for node in content.iter() {
+                // The labels of enclosing tables stay in place, but those of the
+                // rows in this table replace each other.
+                let outer_labels = self.row_labels.len();
+                // Where the current row starts in the output.
+                let mut row_start = self.s.len();
+                self.start_table_row(
+                    child_indent,
+                    numbered,
+                    first_col(row),
+                    content,
+                    outer_labels,
+                );
+                for (index, node) in content.iter().enumerate() {
                     match node {
                         Node::ColumnSeparator => {
-                            pushln!(&mut self.s, child_indent2, "</mtd>");
+                            pushln!(self, child_indent2, "</mtd>");
                             col += 1;
                             pushln!(
-                                &mut self.s,
+                                self,
                                 child_indent2,
                                 if col % 2 == 0 { even_col } else { odd_col }
                             );
+                            row_is_empty = false;
+                            after_intertext = false;
                         }
                         Node::RowSeparator => {
-                            pushln!(&mut self.s, child_indent2, "</mtd>");
-                            pushln!(&mut self.s, child_indent, "</mtr>");
-                            pushln!(&mut self.s, child_indent, "<mtr>");
-                            pushln!(&mut self.s, child_indent2, odd_col);
+                            self.end_table_row(
+                                child_indent,
+                                numbered,
+                                num_columns.saturating_sub(col),
+                                tag,
+                            );
+                            row += 1;
+                            row_start = self.s.len();
+                            row_is_empty = true;
+                            after_intertext = false;
+                            tag = None;
+                            self.start_table_row(
+                                child_indent,
+                                numbered,
+                                first_col(row),
+                                &content[index + 1..],
+                                outer_labels,
+                            );
+                            col = 1;
+                        }
+                        Node::Tag(label) => {
+                            tag = Some(label);
+                        }
+                        Node::Intertext(text) => {
+                            // The text gets a row of its own, so we either replace
+                            // the empty row we just started, or we end the current row.
+                            if row_is_empty {
+                                self.s.truncate(row_start);
+                            } else {
+                                self.end_table_row(
+                                    child_indent,
+                                    numbered,
+                                    num_columns.saturating_sub(col),
+                                    tag,
+                                );
+                            }
+                            tag = None;
+                            pushln!(self, child_indent, "<mtr>");
+                            self.new_line_and_indent(child_indent2);
+                            push!(self.s, "<mtd columnspan=\"");
+                            let span = if numbered {
+                                num_columns + 2
+                            } else {
+                                num_columns
+                            };
+                            self.s.push_str(&span.to_string());
+                            push!(
+                                self.s,
+                                r#"" style="text-align: -webkit-left; text-align: -moz-left">"#
+                            );
+                            self.emit(text, child_indent3);
+                            pushln!(self, child_indent2, "</mtd>");
+                            pushln!(self, child_indent, "</mtr>");
+                            row_start = self.s.len();
+                            row_is_empty = true;
+                            after_intertext = true;
+                            self.start_table_row(
+                                child_indent,
+                                numbered,
+                                first_col(row),
+                                &content[index + 1..],
+                                outer_labels,
+                            );
                             col = 1;
                         }
                         node => {
                             self.emit(node, child_indent3);
+                            row_is_empty = false;
+                            after_intertext = false;
+                        }
+                    }
+                }
+                if row_is_empty && after_intertext {
+                    // Don't leave an empty row after `\intertext`.
+                    self.s.truncate(row_start);
+                } else {
+                    self.end_table_row(
+                        child_indent,
+                        numbered,
+                        num_columns.saturating_sub(col),
+                        tag,
+                    );
+                }
+                self.row_labels.truncate(outer_labels);
+                pushln!(self, base_indent, "</mtable>");
+            }
+            Node::Array {
+                content,
+                array_spec,
+            } => {
+                let child_indent2 = if base_indent > 0 {
+                    child_indent.saturating_add(1)
+                } else {
+                    0
+                };
+                let child_indent3 = if base_indent > 0 {
+                    child_indent2.saturating_add(1)
+                } else {
+                    0
+                };
+                let mut rows: Vec<&[&Node]> = content
+                    .split(|node| matches!(node, Node::RowSeparator))
+                    .collect();
+                // A trailing `\\` doesn't start a new row, but any rules after it
+                // are drawn below the last row.
+                let mut bottom_rules: &[&Node] = &[];
+                if rows.len() > 1 {
+                    if let Some(last) = rows.last() {
+                        if last.iter().all(|node| matches!(node, Node::HLine { .. })) {
+                            bottom_rules = last;
+                            rows.pop();
+                        }
+                    }
+                }
+                let num_rows = rows.len();
+
+                push!(self.s, "<mtable>");
+                for (row_index, row) in rows.into_iter().enumerate() {
+                    let bottom = if row_index + 1 == num_rows {
+                        bottom_rules
+                    } else {
+                        &[]
+                    };
+                    pushln!(self, child_indent, "<mtr>");
+                    let cells: Vec<&[&Node]> = row
+                        .split(|node| matches!(node, Node::ColumnSeparator))
+                        .collect();
+                    let num_columns = cells.len().max(array_spec.columns.len());
+                    for col in 0..num_columns {
+                        let column = array_spec.columns.get(col);
+                        let separator_before = if col == 0 {
+                            array_spec.beginning_separator
+                        } else {
+                            array_spec
+                                .columns
+                                .get(col - 1)
+                                .and_then(|c| c.separator_after)
+                        };
+                        if col == 0 {
+                            if let Some(separator) = array_spec.beginning_separator {
+                                self.emit_array_separator(
+                                    separator,
+                                    row,
+                                    bottom,
+                                    child_indent2,
+                                    child_indent3,
+                                );
+                            }
+                        }
+                        self.new_line_and_indent(child_indent2);
+                        self.push_mtd(&CellStyle {
+                            align: column.map_or(ColumnAlign::Center, |c| c.align),
+                            left: if col == 0 {
+                                array_spec.beginning_line
+                            } else {
+                                None
+                            },
+                            right: column.and_then(|c| c.line_after),
+                            top: rule_for_column(row, Some(col)),
+                            bottom: rule_for_column(bottom, Some(col)),
+                            no_padding_left: separator_before.is_some(),
+                            no_padding_right: column.is_some_and(|c| c.separator_after.is_some()),
+                        });
+                        if let Some(cell) = cells.get(col) {
+                            for node in cell.iter() {
+                                if !matches!(node, Node::HLine { .. }) {
+                                    self.emit(node, child_indent3);
+                                }
+                            }
+                        }
+                        pushln!(self, child_indent2, "</mtd>");
+                        if let Some(separator) = column.and_then(|c| c.separator_after) {
+                            self.emit_array_separator(
+                                separator,
+                                row,
+                                bottom,
+                                child_indent2,
+                                child_indent3,
+                            );
                         }
                     }
+                    pushln!(self, child_indent, "</mtr>");
                 }
-                pushln!(&mut self.s, child_indent2, "</mtd>");
-                pushln!(&mut self.s, child_indent, "</mtr>");
-                pushln!(&mut self.s, base_indent, "</mtable>");
+                pushln!(self, base_indent, "</mtable>");
             }
-            Node::ColumnSeparator | Node::RowSeparator => (),
+            Node::Intertext(text) => self.emit(text, base_indent),
+            Node::ColumnSeparator | Node::RowSeparator | Node::HLine { .. } | Node::Tag(_) => (),
             Node::CustomCmd { predefined, args } => {
-                let old_args = mem::replace(&mut self.custom_cmd_args, Some(args));
+                self.custom_cmd_args.push(args);
                 self.emit(predefined, base_indent);
-                self.custom_cmd_args = old_args;
+                self.custom_cmd_args.pop();
             }
             Node::CustomCmdArg(index) => {
-                if let Some(arg) = self
-                    .custom_cmd_args
-                    .as_ref()
-                    .and_then(|args| args.get(*index))
-                {
-                    self.emit(arg, base_indent);
+                // The argument was parsed outside of the custom command, so any
+                // `CustomCmdArg` within it refers to the arguments of the enclosing command.
+                if let Some(args) = self.custom_cmd_args.pop() {
+                    if let Some(arg) = args.get(*index) {
+                        self.emit(arg, base_indent);
+                    }
+                    self.custom_cmd_args.push(args);
                 }
             }
             Node::HardcodedMathML(mathml) => {
                 push!(self.s, mathml);
             }
+            Node::Error(text) => {
+                push!(self.s, "<merror><mtext>");
+                self.push_escaped(text);
+                push!(self.s, "</mtext></merror>");
+            }
+        }
+    }
+
+    /// Start a row of a table, with the empty cell on the left if the table is numbered.
+    ///
+    /// The row consists of the nodes at the start of `rest`. If it contains a `\label`,
+    /// the first one becomes the `id` of the row, in place of the label that the previous
+    /// row of the same table had, which is after the first `outer_labels` labels.
+    fn start_table_row(
+        &mut self,
+        row_indent: usize,
+        numbered: bool,
+        first_col: &str,
+        rest: &[&'arena Node<'arena>],
+        outer_labels: usize,
+    ) {
+        let cell_indent = if row_indent > 0 { row_indent + 1 } else { 0 };
+        self.row_labels.truncate(outer_labels);
+        let row_end = rest
+            .iter()
+            .position(|node| matches!(node, Node::RowSeparator | Node::Intertext(_)))
+            .unwrap_or(rest.len());
+        let mut finder = LabelFinder {
+            placed: &self.row_labels,
+            found: None,
+        };
+        visit::walk(&mut finder, &rest[..row_end]);
+        match finder.found {
+            Some(label) => {
+                pushln!(self, row_indent, "<mtr id=\"", label, "\">");
+                self.row_labels.push(label);
+            }
+            None => {
+                pushln!(self, row_indent, "<mtr>");
+            }
+        }
+        if numbered {
+            pushln!(self, cell_indent, r#"<mtd style="width: 50%">"#);
+            pushln!(self, cell_indent, "</mtd>");
+        }
+        pushln!(self, cell_indent, first_col);
+    }
+
+    /// End a row of a table, with the equation number on the right if the table is
+    /// numbered.
+    ///
+    /// `missing_columns` is the number of columns which this row has less than the
+    /// widest row; they are filled up so that the numbers line up.
+    fn end_table_row(
+        &mut self,
+        row_indent: usize,
+        numbered: bool,
+        missing_columns: usize,
+        tag: Option<&str>,
+    ) {
+        let cell_indent = if row_indent > 0 { row_indent + 1 } else { 0 };
+        pushln!(self, cell_indent, "</mtd>");
+        if numbered {
+            for _ in 0..missing_columns {
+                pushln!(self, cell_indent, "<mtd>");
+                pushln!(self, cell_indent, "</mtd>");
+            }
+            pushln!(
+                self,
+                cell_indent,
+                r#"<mtd style="width: 50%; text-align: -webkit-right; text-align: -moz-right">"#
+            );
+            if let Some(tag) = tag.filter(|tag| !tag.is_empty()) {
+                let content_indent = if row_indent > 0 { row_indent + 2 } else { 0 };
+                pushln!(self, content_indent, "<mtext>", tag, "</mtext>");
+            }
+            pushln!(self, cell_indent, "</mtd>");
+        }
+        pushln!(self, row_indent, "</mtr>");
+    }
+
+    /// Emit the material from an `@{...}` column separator in its own cell.
+    fn emit_array_separator(
+        &mut self,
+        separator: &'arena Node<'arena>,
+        row: &[&Node],
+        bottom: &[&Node],
+        cell_indent: usize,
+        content_indent: usize,
+    ) {
+        self.new_line_and_indent(cell_indent);
+        self.push_mtd(&CellStyle {
+            align: ColumnAlign::Center,
+            left: None,
+            right: None,
+            top: rule_for_column(row, None),
+            bottom: rule_for_column(bottom, None),
+            no_padding_left: true,
+            no_padding_right: true,
+        });
+        self.emit(separator, content_indent);
+        pushln!(self, cell_indent, "</mtd>");
+    }
+
+    fn push_mtd(&mut self, style: &CellStyle) {
+        push!(self.s, "<mtd style=\"");
+        let style_start = self.s.len();
+        match style.align {
+            ColumnAlign::Left => push!(self.s, "text-align: -webkit-left; text-align: -moz-left; "),
+            ColumnAlign::Center => (),
+            ColumnAlign::Right => {
+                push!(
+                    self.s,
+                    "text-align: -webkit-right; text-align: -moz-right; "
+                )
+            }
+        }
+        for (side, line) in [
+            ("border-left: ", style.left),
+            ("border-right: ", style.right),
+            ("border-top: ", style.top),
+            ("border-bottom: ", style.bottom),
+        ] {
+            if let Some(line) = line {
+                push!(self.s, side, line, "; ");
+            }
+        }
+        if style.no_padding_left {
+            push!(self.s, "padding-left: 0; ");
+        }
+        if style.no_padding_right {
+            push!(self.s, "padding-right: 0; ");
+        }
+        if self.s.len() == style_start {
+            // No style at all; remove the attribute again.
+            self.s.truncate(style_start - " style=\"".len());
+            push!(self.s, ">");
+        } else {
+            // Remove the trailing "; ".
+            self.s.truncate(self.s.len() - 2);
+            push!(self.s, "\">");
         }
     }
 
@@ -555,26 +1184,79 @@ impl<'arena> MathMLEmitter<'arena> {
     }
 }
 
-impl Default for MathMLEmitter<'static> {
-    fn default() -> Self {
-        Self::new()
-    }
+/// Finds the first `\label` in a tree which is not in `placed` yet.
+struct LabelFinder<'placed, 'arena> {
+    placed: &'placed [&'arena str],
+    found: Option<&'arena str>,
 }
 
-fn new_line_and_indent(s: &mut String, indent_num: usize) {
-    if indent_num > 0 {
-        s.push('\n');
+impl<'arena> Visit<'arena> for LabelFinder<'_, 'arena> {
+    fn enter(&mut self, node: &'arena Node<'arena>) -> bool {
+        if let Node::Label(label) = node {
+            if self.found.is_none() && !self.placed.contains(label) {
+                self.found = Some(label);
+            }
+        }
+        self.found.is_none()
     }
-    for _ in 0..indent_num {
-        s.push_str(INDENT);
+}
+
+/// The number of columns of the widest row of a table.
+fn count_columns(content: &[&Node]) -> usize {
+    content
+        .split(|node| matches!(node, Node::RowSeparator | Node::Intertext(_)))
+        .map(|row| {
+            row.iter()
+                .filter(|node| matches!(node, Node::ColumnSeparator))
+                .count()
+                + 1
+        })
+        .max()
+        .unwrap_or(1)
+}
+
+/// The style of a cell in an `array`.
+struct CellStyle {
+    align: ColumnAlign,
+    left: Option<LineType>,
+    right: Option<LineType>,
+    top: Option<LineType>,
+    bottom: Option<LineType>,
+    no_padding_left: bool,
+    no_padding_right: bool,
+}
+
+/// Find the rule among `nodes` which is drawn above the given column (counting from 0).
+///
+/// If `column` is `None`, only rules which span the whole array are considered.
+fn rule_for_column(nodes: &[&Node], column: Option<usize>) -> Option<LineType> {
+    nodes.iter().find_map(|node| match node {
+        Node::HLine {
+            line,
+            columns: None,
+        } => Some(*line),
+        Node::HLine {
+            line,
+            columns: Some((first, last)),
+        } => column
+            .is_some_and(|col| (*first as usize..=*last as usize).contains(&(col + 1)))
+            .then_some(*line),
+        _ => None,
+    })
+}
+
+impl Default for MathMLEmitter<'static> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{MathMLEmitter, Node};
+    use super::{MathMLEmitter, Node, ScriptPair};
     use crate::attribute::{
-        FracAttr, MathSpacing, MathVariant, OpAttr, RowAttr, Style, TextTransform,
+        FracAttr, MathSpacing, MathVariant, Notation, OpAttr, PaddedAttr, RowAttr, Style,
+        TextTransform,
     };
     use crate::length::{AbsoluteLength, FontRelativeLength};
     use crate::symbol;
@@ -663,6 +1345,10 @@ mod tests {
     #[test]
     fn render_multi_letter_ident() {
         assert_eq!(render(&Node::MultiLetterIdent("sin")), "<mi>sin</mi>");
+        assert_eq!(
+            render(&Node::IdentOp("Tr")),
+            r#"<mo lspace="0em" rspace="0em" movablelimits="true">Tr</mo>"#
+        );
     }
 
     #[test]
@@ -710,6 +1396,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::useless_conversion)]
     fn render_over_op() {
         assert_eq!(
             render(&Node::OverOp(
@@ -730,6 +1417,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::useless_conversion)]
     fn render_under_op() {
         assert_eq!(
             render(&Node::UnderOp(
@@ -934,6 +1622,14 @@ mod tests {
         assert_eq!(render(&Node::Text("hello")), "<mtext>hello</mtext>");
     }
 
+    #[test]
+    fn render_error() {
+        assert_eq!(
+            render(&Node::Error("\\x<y")),
+            "<merror><mtext>\\x&lt;y</mtext></merror>"
+        );
+    }
+
     #[test]
     fn render_table() {
         let nodes = [
@@ -964,15 +1660,128 @@ mod tests {
         );
     }
 
+    #[test]
+    fn render_kern() {
+        assert_eq!(
+            render(&Node::Kern(AbsoluteLength::from_pt(-2).into())),
+            "<mspace width=\"-2pt\"/>"
+        );
+    }
+
+    #[test]
+    fn render_rule() {
+        assert_eq!(
+            render(&Node::Rule {
+                width: FontRelativeLength::from_em(1).into(),
+                height: AbsoluteLength::from_pt(2).into(),
+                raise: None,
+            }),
+            "<mspace width=\"1em\" height=\"2pt\" style=\"background: currentcolor\"/>"
+        );
+        assert_eq!(
+            render(&Node::Rule {
+                width: FontRelativeLength::from_em(1).into(),
+                height: AbsoluteLength::from_pt(2).into(),
+                raise: Some(FontRelativeLength::from_ex(-1).into()),
+            }),
+            "<mpadded voffset=\"-1ex\"><mspace width=\"1em\" height=\"2pt\" style=\"background: currentcolor\"/></mpadded>"
+        );
+    }
+
+    #[test]
+    fn render_phantom() {
+        assert_eq!(
+            render(&Node::Phantom(&Node::SingleLetterIdent('x', false))),
+            "<mphantom><mi>x</mi></mphantom>"
+        );
+    }
+
+    #[test]
+    fn render_padded() {
+        assert_eq!(
+            render(&Node::Padded {
+                attr: PaddedAttr::ZeroHeightDepth,
+                content: &Node::SingleLetterIdent('x', false),
+            }),
+            "<mpadded height=\"0\" depth=\"0\"><mi>x</mi></mpadded>"
+        );
+        assert_eq!(
+            render(&Node::Padded {
+                attr: PaddedAttr::LeftOverlap,
+                content: &Node::SingleLetterIdent('x', false),
+            }),
+            "<mpadded width=\"0\"><mrow style=\"transform: translateX(-100%)\"><mi>x</mi></mrow></mpadded>"
+        );
+    }
+
+    #[test]
+    fn render_enclose() {
+        assert_eq!(
+            render(&Node::Enclose {
+                content: &Node::SingleLetterIdent('x', false),
+                notation: Notation::Box,
+            }),
+            "<mrow style=\"border:0.4pt solid;padding:3pt;\"><mi>x</mi></mrow>"
+        );
+        assert_eq!(
+            render(&Node::Enclose {
+                content: &Node::SingleLetterIdent('x', false),
+                notation: Notation::HorizontalStrike,
+            }),
+            "<mrow style=\"background:linear-gradient(to bottom,transparent calc(50% - 0.4pt),currentcolor calc(50% - 0.4pt) calc(50% + 0.4pt),transparent calc(50% + 0.4pt));\"><mi>x</mi></mrow>"
+        );
+    }
+
+    #[test]
+    fn render_color_box() {
+        assert_eq!(
+            render(&Node::ColorBox {
+                content: &Node::SingleLetterIdent('x', false),
+                background: (255, 0, 0),
+                frame: None,
+            }),
+            "<mrow style=\"background-color:#FF0000;padding:3pt;\"><mi>x</mi></mrow>"
+        );
+        assert_eq!(
+            render(&Node::ColorBox {
+                content: &Node::SingleLetterIdent('x', false),
+                background: (255, 255, 0),
+                frame: Some((0, 0, 255)),
+            }),
+            "<mrow style=\"border:0.4pt solid #0000FF;background-color:#FFFF00;padding:3pt;\"><mi>x</mi></mrow>"
+        );
+    }
+
     #[test]
     fn render_multiscript() {
         assert_eq!(
             render(&Node::Multiscript {
                 base: &Node::SingleLetterIdent('x', false),
-                sub: &Node::Number("1"),
+                scripts: &[ScriptPair {
+                    sub: Some(&Node::Number("1")),
+                    sup: None,
+                }],
+                num_postscripts: 0,
             }),
             "<mmultiscripts><mi>x</mi><mprescripts/><mn>1</mn><mrow></mrow></mmultiscripts>"
         );
+        assert_eq!(
+            render(&Node::Multiscript {
+                base: &Node::SingleLetterIdent('x', false),
+                scripts: &[
+                    ScriptPair {
+                        sub: None,
+                        sup: Some(&Node::Number("1")),
+                    },
+                    ScriptPair {
+                        sub: Some(&Node::Number("2")),
+                        sup: Some(&Node::Number("3")),
+                    },
+                ],
+                num_postscripts: 1,
+            }),
+            "<mmultiscripts><mi>x</mi><mrow></mrow><mn>1</mn><mprescripts/><mn>2</mn><mn>3</mn></mmultiscripts>"
+        );
     }
 
     #[test]
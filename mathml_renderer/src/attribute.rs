@@ -20,8 +20,10 @@ pub enum OpAttr {
     StretchyFalse,
     #[strum(serialize = r#" movablelimits="false""#)]
     NoMovableLimits,
-    #[strum(serialize = r#" form="prefix""#)]
-    FormPrefix,
+    /// An operator which stretches to the width of the labels above and below it,
+    /// and leaves the spacing around it to the surrounding nodes.
+    #[strum(serialize = r#" lspace="0em" rspace="0em" stretchy="true""#)]
+    Extensible,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, AsRefStr)]
@@ -71,6 +73,13 @@ pub enum FracAttr {
     DisplayStyleFalse,
     #[strum(serialize = r#" displaystyle="true" scriptlevel="0" style="padding-top: 0.1667em""#)]
     CFracStyle,
+    /// For `\substack` and `subarray`, which are already in a script.
+    /// Tables with this style have compact rows.
+    #[strum(serialize = r#" displaystyle="false""#)]
+    SubstackStyle,
+    /// For `smallmatrix`. Tables with this style have compact rows.
+    #[strum(serialize = r#" displaystyle="false" scriptlevel="1""#)]
+    SmallMatrixStyle,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, AsRefStr)]
@@ -86,12 +95,39 @@ pub enum Style {
     ScriptScriptStyle,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum Align {
     Center,
     Left,
+    /// Pairs of right- and left-aligned columns, as in `align`.
     Alternating,
+    /// Like `Alternating`, but without space between the pairs of columns, as in `alignat`.
+    AlternatingTight,
+    /// Like `Alternating`, but spread over the full width, as in `flalign`.
+    AlternatingFullWidth,
+    /// The first row is aligned left, the last row right and all others centered,
+    /// as in `multline`.
+    Multline,
+}
+
+/// The alignment of a column in an `array`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum ColumnAlign {
+    Left,
+    Center,
+    Right,
+}
+
+/// The kind of a rule between the rows or columns of an `array`.
+#[derive(Debug, Clone, Copy, PartialEq, AsRefStr)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum LineType {
+    #[strum(serialize = "0.06em solid")]
+    Solid,
+    #[strum(serialize = "0.06em dashed")]
+    Dashed,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, AsRefStr)]
@@ -99,8 +135,83 @@ pub enum Align {
 pub enum MathSpacing {
     #[strum(serialize = "0em")]
     Zero = 1,
+    #[strum(serialize = "0.1667em")]
+    ThreeMu, // 3/18 of an em/\quad
     #[strum(serialize = "0.2222em")]
     FourMu, // 4/18 of an em/\quad
+    #[strum(serialize = "0.2778em")]
+    FiveMu, // 5/18 of an em/\quad
+}
+
+/// Which dimensions of its content an `<mpadded>` element hides.
+#[derive(Debug, Clone, Copy, PartialEq, AsRefStr)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum PaddedAttr {
+    #[strum(serialize = r#" height="0" depth="0""#)]
+    ZeroHeightDepth,
+    #[strum(serialize = r#" height="0""#)]
+    ZeroHeight,
+    #[strum(serialize = r#" depth="0""#)]
+    ZeroDepth,
+    /// The content overlaps to the right, like in `\rlap`.
+    #[strum(serialize = r#" width="0""#)]
+    ZeroWidth,
+    /// The content overlaps to the left, like in `\llap`.
+    #[strum(serialize = r#" width="0""#)]
+    LeftOverlap,
+    /// The content overlaps to both sides, like in `\clap`.
+    #[strum(serialize = r#" width="0""#)]
+    CenterOverlap,
+}
+
+/// A frame around the content or a line through it, like from `\boxed` or `\cancel`.
+///
+/// MathML Core has no `<menclose>`, so the notations are drawn with CSS borders and
+/// background gradients.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Notation {
+    /// A frame around the content, like from `\boxed`.
+    Box,
+    /// A line from the bottom left to the top right, like from `\cancel`.
+    UpDiagonalStrike,
+    /// A line from the top left to the bottom right, like from `\bcancel`.
+    DownDiagonalStrike,
+    /// Both diagonal lines, like from `\xcancel`.
+    CrossedOut,
+    /// A horizontal line through the middle, like from `\sout`.
+    HorizontalStrike,
+}
+
+/// A line of 0.8pt thickness through the middle of an element, perpendicular to
+/// the given gradient direction.
+macro_rules! strike {
+    ($direction:literal) => {
+        concat!(
+            "linear-gradient(",
+            $direction,
+            ",transparent calc(50% - 0.4pt),currentcolor calc(50% - 0.4pt) calc(50% + 0.4pt),transparent calc(50% + 0.4pt))"
+        )
+    };
+}
+
+impl Notation {
+    /// The CSS which draws the notation.
+    pub fn as_style(&self) -> &'static str {
+        match self {
+            Notation::Box => "border:0.4pt solid;padding:3pt;",
+            Notation::UpDiagonalStrike => concat!("background:", strike!("to top left"), ";"),
+            Notation::DownDiagonalStrike => concat!("background:", strike!("to top right"), ";"),
+            Notation::CrossedOut => concat!(
+                "background:",
+                strike!("to top left"),
+                ",",
+                strike!("to top right"),
+                ";"
+            ),
+            Notation::HorizontalStrike => concat!("background:", strike!("to bottom"), ";"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, AsRefStr)]
@@ -201,20 +201,21 @@ pub enum FontRelativeUnit {
 }
 
 fn write_impl(value: i32, output: &mut String, conv: NonZeroU32, unit: &str) {
-    if value < 0 {
+    // only write two decimal points, rounded to the nearest hundredth
+    let conv = u64::from(conv.get());
+    let hundredths = (u64::from(value.unsigned_abs()) * 100 + conv / 2) / conv;
+    if value < 0 && hundredths != 0 {
         output.push('-');
     }
-    let value = value.unsigned_abs();
     let mut buf = [MaybeUninit::uninit(); 10];
-    output.push_str(fmt_u32(value / conv, &mut buf));
-    let frac = value % conv;
+    // The rounded value is at most `u32::MAX / 100 + 1`, so this cannot truncate.
+    output.push_str(fmt_u32((hundredths / 100) as u32, &mut buf));
+    let frac = (hundredths % 100) as u32;
     if frac != 0 {
-        // only write two decimal points
         output.push('.');
-        output.push_str(fmt_u32(frac * 10 / conv, &mut buf));
-        let frac = (frac * 10) % conv;
-        if frac != 0 {
-            output.push_str(fmt_u32(frac * 10 / conv, &mut buf));
+        output.push_str(fmt_u32(frac / 10, &mut buf));
+        if frac % 10 != 0 {
+            output.push_str(fmt_u32(frac % 10, &mut buf));
         }
     }
     output.push_str(unit)
@@ -321,4 +322,24 @@ mod tests {
         assert_eq!(&output, "-546ex");
         output.clear();
     }
+
+    #[test]
+    fn write_rounded() {
+        let mut output = String::new();
+        // 1/6 em
+        FontRelativeLength {
+            value: 10,
+            unit: FontRelativeUnit::Em,
+        }
+        .push_to_string(&mut output);
+        assert_eq!(&output, "0.17em");
+        output.clear();
+        // 72.2694 pt
+        AbsoluteLength(26017).push_to_string(&mut output);
+        assert_eq!(&output, "72.27pt");
+        output.clear();
+        AbsoluteLength(-1).push_to_string(&mut output);
+        assert_eq!(&output, "0pt");
+        output.clear();
+    }
 }
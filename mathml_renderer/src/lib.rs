@@ -3,4 +3,7 @@ pub mod ast;
 pub mod attribute;
 mod itoa;
 pub mod length;
+pub mod render;
 pub mod symbol;
+pub mod text;
+pub mod visit;
@@ -0,0 +1,87 @@
+//! Output backends for the AST.
+use crate::ast::{MathMLEmitter, Node};
+
+/// A backend which turns the AST into some output format.
+///
+/// [`MathMLEmitter`] is the implementation for MathML Core.
+pub trait Renderer<'arena> {
+    /// The result of rendering, e.g. a `String`.
+    type Output;
+
+    /// Render a node and all its children.
+    fn render_node(&mut self, node: &'arena Node<'arena>);
+
+    /// Render a sequence of nodes, e.g. all the nodes of an equation.
+    fn render_nodes(&mut self, nodes: &[&'arena Node<'arena>]) {
+        for node in nodes {
+            self.render_node(node);
+        }
+    }
+
+    /// Return everything that was rendered.
+    fn finish(self) -> Self::Output;
+}
+
+impl<'arena> Renderer<'arena> for MathMLEmitter<'arena> {
+    type Output = String;
+
+    /// Emit the MathML for the node, without any whitespace between elements.
+    #[inline]
+    fn render_node(&mut self, node: &'arena Node<'arena>) {
+        self.emit(node, 0);
+    }
+
+    #[inline]
+    fn finish(self) -> String {
+        self.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Renderer;
+    use crate::ast::{MathMLEmitter, Node};
+    use crate::attribute::RowAttr;
+    use crate::symbol;
+
+    /// Counts the nodes at the top level of the tree.
+    struct Counter(usize);
+
+    impl<'arena> Renderer<'arena> for Counter {
+        type Output = usize;
+
+        fn render_node(&mut self, _node: &'arena Node<'arena>) {
+            self.0 += 1;
+        }
+
+        fn finish(self) -> usize {
+            self.0
+        }
+    }
+
+    fn render<'arena, R: Renderer<'arena>>(
+        mut renderer: R,
+        nodes: &[&'arena Node<'arena>],
+    ) -> R::Output {
+        renderer.render_nodes(nodes);
+        renderer.finish()
+    }
+
+    #[test]
+    fn render_with_different_backends() {
+        let x = Node::SingleLetterIdent('x', false);
+        let plus = Node::Operator(symbol::PLUS_SIGN.into(), None);
+        let one = Node::Number("1");
+        let row_nodes = [&x, &plus, &one];
+        let row = Node::Row {
+            nodes: &row_nodes,
+            attr: RowAttr::None,
+        };
+        let nodes = [&row, &x];
+        assert_eq!(
+            render(MathMLEmitter::new(), &nodes),
+            "<mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow><mi>x</mi>"
+        );
+        assert_eq!(render(Counter(0), &nodes), 2);
+    }
+}
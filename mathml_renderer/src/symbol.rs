@@ -217,7 +217,7 @@ pub const RIGHTWARDS_ARROW_WITH_STROKE: Rel = Rel('↛');
 // pub const RIGHTWARDS_WAVE_ARROW: Op = Op('↝');
 // pub const LEFTWARDS_TWO_HEADED_ARROW: Op = Op('↞');
 // pub const UPWARDS_TWO_HEADED_ARROW: Op = Op('↟');
-// pub const RIGHTWARDS_TWO_HEADED_ARROW: Op = Op('↠');
+pub const RIGHTWARDS_TWO_HEADED_ARROW: Rel = Rel('↠');
 // pub const DOWNWARDS_TWO_HEADED_ARROW: Op = Op('↡');
 pub const LEFTWARDS_ARROW_WITH_TAIL: Rel = Rel('↢');
 pub const RIGHTWARDS_ARROW_WITH_TAIL: Rel = Rel('↣');
@@ -0,0 +1,641 @@
+//! Rendering of the AST as plain Unicode text, e.g. `√(x²+1)⁄2`.
+use crate::ast::{Node, ScriptPair};
+use crate::attribute::{Align, MathVariant};
+use crate::length::{AbsoluteLength, FontRelativeLength, LengthKind, SpecifiedLength};
+use crate::render::Renderer;
+
+/// A renderer which produces readable Unicode text.
+///
+/// Sub- and superscripts use the Unicode sub- and superscript characters where all of
+/// them exist, and `_(…)` and `^(…)` otherwise. Roots are written as `√(…)` and
+/// fractions as `…⁄…`.
+pub struct TextRenderer<'arena> {
+    s: String,
+    var: Option<MathVariant>,
+    /// The arguments of the custom commands we are currently in, innermost last.
+    custom_cmd_args: Vec<&'arena [&'arena Node<'arena>]>,
+}
+
+impl<'arena> TextRenderer<'arena> {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            s: String::new(),
+            var: None,
+            custom_cmd_args: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> String {
+        self.s
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.s
+    }
+
+    pub fn render(&mut self, node: &'arena Node<'arena>) {
+        match node {
+            Node::Number(number) => self.push_transformed(number),
+            Node::SingleLetterIdent(letter, is_normal) => {
+                let is_normal = *is_normal || matches!(self.var, Some(MathVariant::Normal));
+                let c = match self.var {
+                    Some(MathVariant::Transform(tf)) => tf.transform(*letter, is_normal),
+                    _ => *letter,
+                };
+                self.s.push(c);
+            }
+            Node::TextTransform { content, tf } => {
+                let old_var = self.var.replace(*tf);
+                self.render(content);
+                self.var = old_var;
+            }
+            Node::Operator(op, _) | Node::OperatorWithSpacing { op, .. } => {
+                self.s.push(op.into());
+            }
+            Node::StretchableOp(op, _) | Node::SizedParen(_, op) => {
+                self.push_paren(char::from(*op))
+            }
+            Node::OpGreaterThan => self.s.push('>'),
+            Node::OpLessThan => self.s.push('<'),
+            Node::OpAmpersand => self.s.push('&'),
+            Node::MultiLetterIdent(letters) | Node::IdentOp(letters) | Node::Error(letters) => {
+                self.s.push_str(letters)
+            }
+            Node::CollectedLetters(letters) | Node::Text(letters) => self.push_transformed(letters),
+            Node::Space(space) => {
+                // Thin and negative spaces are left out.
+                if space.parse::<f32>().is_ok_and(|width| width > 0.2) {
+                    self.s.push(' ');
+                }
+            }
+            Node::Kern(width) => {
+                // Negative spaces are left out.
+                let is_positive = match width.kind() {
+                    LengthKind::AbsoluteLength(len) => len.0 > 0,
+                    LengthKind::FontRelativeLength(len) => len.value > 0,
+                };
+                if is_positive {
+                    self.s.push(' ');
+                }
+            }
+            Node::Subscript { target, symbol } | Node::Underset { target, symbol } => {
+                self.render(target);
+                self.render_script(symbol, Script::Sub);
+            }
+            Node::Superscript { target, symbol } | Node::Overset { target, symbol } => {
+                self.render(target);
+                self.render_script(symbol, Script::Super);
+            }
+            Node::SubSup {
+                target,
+                sub: under,
+                sup: over,
+            }
+            | Node::UnderOver {
+                target,
+                under,
+                over,
+            } => {
+                self.render(target);
+                self.render_script(under, Script::Sub);
+                self.render_script(over, Script::Super);
+            }
+            Node::Multiscript {
+                base,
+                scripts,
+                num_postscripts,
+            } => {
+                let (post, pre) = scripts.split_at((*num_postscripts as usize).min(scripts.len()));
+                self.render_script_pairs(pre);
+                self.render(base);
+                self.render_script_pairs(post);
+            }
+            Node::OverOp(op, _, target) => {
+                self.render_accent(target, char::from(op), combining_above(char::from(op)))
+            }
+            Node::UnderOp(op, target) => {
+                self.render_accent(target, char::from(op), combining_below(char::from(op)))
+            }
+            Node::Sqrt(content) => {
+                self.s.push('√');
+                self.render_grouped(content);
+            }
+            Node::Root(degree, content) => {
+                let degree_text = self.render_to_string(degree);
+                match degree_text.as_str() {
+                    "3" => self.s.push('∛'),
+                    "4" => self.s.push('∜'),
+                    _ => {
+                        self.push_script(&degree_text, Script::Super);
+                        self.s.push('√');
+                    }
+                }
+                self.render_grouped(content);
+            }
+            Node::Frac { num, den, lt, .. } => {
+                self.render_grouped(num);
+                // Fractions without a line, like in `\binom`, are just stacked.
+                self.s.push(if lt.is_some_and(is_zero) { ' ' } else { '⁄' });
+                self.render_grouped(den);
+            }
+            Node::Row { nodes, .. } => {
+                for node in nodes.iter() {
+                    self.render(node);
+                }
+            }
+            Node::Fenced {
+                open,
+                close,
+                content,
+                ..
+            } => {
+                self.push_paren(char::from(*open));
+                self.render(content);
+                self.push_paren(char::from(*close));
+            }
+            Node::Slashed(node) => {
+                self.render(node);
+                self.s.push('\u{338}');
+            }
+            Node::Padded { content, .. }
+            | Node::Enclose { content, .. }
+            | Node::ColorBox { content, .. } => self.render(content),
+            Node::Table { content, align, .. } => {
+                for node in content.iter() {
+                    match node {
+                        // The columns of aligned equations belong together.
+                        Node::ColumnSeparator => {
+                            if matches!(align, Align::Center) {
+                                self.s.push(' ');
+                            }
+                        }
+                        Node::RowSeparator => self.s.push_str("; "),
+                        node => self.render(node),
+                    }
+                }
+            }
+            Node::Array {
+                content,
+                array_spec,
+            } => {
+                if let Some(separator) = array_spec.beginning_separator {
+                    self.render(separator);
+                }
+                let mut col = 0;
+                for node in content.iter() {
+                    match node {
+                        Node::ColumnSeparator => {
+                            // Material from `@{...}` replaces the space between columns.
+                            match array_spec.columns.get(col).and_then(|c| c.separator_after) {
+                                Some(separator) => self.render(separator),
+                                None => self.s.push(' '),
+                            }
+                            col += 1;
+                        }
+                        Node::RowSeparator => {
+                            self.s.push_str("; ");
+                            col = 0;
+                        }
+                        node => self.render(node),
+                    }
+                }
+            }
+            Node::Tag(tag) => {
+                if !tag.is_empty() {
+                    self.s.push(' ');
+                    self.s.push_str(tag);
+                }
+            }
+            Node::Intertext(text) => {
+                self.render(text);
+                self.s.push_str("; ");
+            }
+            Node::Reference { number, .. } => self.render(number),
+            Node::ColumnSeparator
+            | Node::RowSeparator
+            | Node::HLine { .. }
+            | Node::Label(_)
+            | Node::Phantom(_)
+            | Node::Rule { .. } => (),
+            Node::CustomCmd { predefined, args } => {
+                self.custom_cmd_args.push(args);
+                self.render(predefined);
+                self.custom_cmd_args.pop();
+            }
+            Node::CustomCmdArg(index) => {
+                // The argument was parsed outside of the custom command, so any
+                // `CustomCmdArg` within it refers to the arguments of the enclosing command.
+                if let Some(args) = self.custom_cmd_args.pop() {
+                    if let Some(arg) = args.get(*index) {
+                        self.render(arg);
+                    }
+                    self.custom_cmd_args.push(args);
+                }
+            }
+            Node::HardcodedMathML(mathml) => {
+                // Only keep the text content of the elements.
+                let mut in_tag = false;
+                for c in mathml.chars() {
+                    match c {
+                        '<' => in_tag = true,
+                        '>' => in_tag = false,
+                        c if !in_tag => self.s.push(c),
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+
+    fn push_transformed(&mut self, text: &str) {
+        match self.var {
+            Some(MathVariant::Transform(tf)) => {
+                self.s.extend(text.chars().map(|c| tf.transform(c, false)))
+            }
+            _ => self.s.push_str(text),
+        }
+    }
+
+    fn push_paren(&mut self, paren: char) {
+        // The null character stands for an invisible delimiter, like `\left.`.
+        if paren != '\0' {
+            self.s.push(paren);
+        }
+    }
+
+    /// Render `node` into a separate string.
+    fn render_to_string(&mut self, node: &'arena Node<'arena>) -> String {
+        let outer = std::mem::take(&mut self.s);
+        self.render(node);
+        std::mem::replace(&mut self.s, outer)
+    }
+
+    /// Render `node`, with parentheses around it unless it is a single symbol or number.
+    fn render_grouped(&mut self, node: &'arena Node<'arena>) {
+        let text = self.render_to_string(node);
+        let mut chars = text.chars();
+        let is_atom = chars.clone().count() == 1
+            || (!text.is_empty() && chars.all(|c| c.is_ascii_digit() || c == '.'));
+        if is_atom {
+            self.s.push_str(&text);
+        } else {
+            self.s.push('(');
+            self.s.push_str(&text);
+            self.s.push(')');
+        }
+    }
+
+    fn render_script_pairs(&mut self, pairs: &'arena [ScriptPair<'arena>]) {
+        for pair in pairs {
+            if let Some(sub) = pair.sub {
+                self.render_script(sub, Script::Sub);
+            }
+            if let Some(sup) = pair.sup {
+                self.render_script(sup, Script::Super);
+            }
+        }
+    }
+
+    fn render_script(&mut self, node: &'arena Node<'arena>, script: Script) {
+        let text = self.render_to_string(node);
+        self.push_script(&text, script);
+    }
+
+    /// Push `text` in Unicode sub- or superscript characters if all of them exist,
+    /// and with `_` or `^` otherwise.
+    fn push_script(&mut self, text: &str, script: Script) {
+        let convert = match script {
+            Script::Sub => subscript,
+            Script::Super => superscript,
+        };
+        if let Some(converted) = text.chars().map(convert).collect::<Option<String>>() {
+            self.s.push_str(&converted);
+            return;
+        }
+        self.s.push(match script {
+            Script::Sub => '_',
+            Script::Super => '^',
+        });
+        if text.chars().count() == 1 {
+            self.s.push_str(text);
+        } else {
+            self.s.push('(');
+            self.s.push_str(text);
+            self.s.push(')');
+        }
+    }
+
+    /// Render an accent, as a combining character if the target is a single character.
+    fn render_accent(&mut self, target: &'arena Node<'arena>, op: char, combining: Option<char>) {
+        let text = self.render_to_string(target);
+        match combining {
+            Some(combining) if text.chars().count() == 1 => {
+                self.s.push_str(&text);
+                self.s.push(combining);
+            }
+            _ => {
+                self.s.push('(');
+                self.s.push_str(&text);
+                self.s.push(')');
+                self.s.push(op);
+            }
+        }
+    }
+}
+
+impl Default for TextRenderer<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'arena> Renderer<'arena> for TextRenderer<'arena> {
+    type Output = String;
+
+    #[inline]
+    fn render_node(&mut self, node: &'arena Node<'arena>) {
+        self.render(node);
+    }
+
+    #[inline]
+    fn finish(self) -> String {
+        self.into_inner()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Script {
+    Sub,
+    Super,
+}
+
+fn is_zero(length: SpecifiedLength) -> bool {
+    matches!(
+        length.kind(),
+        LengthKind::AbsoluteLength(AbsoluteLength(0))
+            | LengthKind::FontRelativeLength(FontRelativeLength { value: 0, .. })
+    )
+}
+
+fn superscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4'..='9' => char::from_u32(c as u32 - '4' as u32 + '⁴' as u32)?,
+        '+' => '⁺',
+        '-' | '−' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        // Primes are already raised.
+        '′' | '″' | '‴' | '⁗' => c,
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        'A' => 'ᴬ',
+        'B' => 'ᴮ',
+        'D' => 'ᴰ',
+        'E' => 'ᴱ',
+        'G' => 'ᴳ',
+        'H' => 'ᴴ',
+        'I' => 'ᴵ',
+        'J' => 'ᴶ',
+        'K' => 'ᴷ',
+        'L' => 'ᴸ',
+        'M' => 'ᴹ',
+        'N' => 'ᴺ',
+        'O' => 'ᴼ',
+        'P' => 'ᴾ',
+        'R' => 'ᴿ',
+        'T' => 'ᵀ',
+        'U' => 'ᵁ',
+        'V' => 'ⱽ',
+        'W' => 'ᵂ',
+        'α' => 'ᵅ',
+        'β' => 'ᵝ',
+        'γ' => 'ᵞ',
+        'δ' => 'ᵟ',
+        'θ' => 'ᶿ',
+        'φ' | 'ϕ' => 'ᵠ',
+        'χ' => 'ᵡ',
+        _ => return None,
+    })
+}
+
+fn subscript(c: char) -> Option<char> {
+    Some(match c {
+        '0'..='9' => char::from_u32(c as u32 - '0' as u32 + '₀' as u32)?,
+        '+' => '₊',
+        '-' | '−' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        'β' => 'ᵦ',
+        'γ' => 'ᵧ',
+        'ρ' => 'ᵨ',
+        'φ' | 'ϕ' => 'ᵩ',
+        'χ' => 'ᵪ',
+        _ => return None,
+    })
+}
+
+/// The combining character for an accent above a letter.
+fn combining_above(accent: char) -> Option<char> {
+    Some(match accent {
+        '`' => '\u{300}',
+        '´' => '\u{301}',
+        '^' => '\u{302}',
+        '~' => '\u{303}',
+        '¯' => '\u{304}',
+        '‾' => '\u{305}',
+        '˘' => '\u{306}',
+        '˙' => '\u{307}',
+        '¨' => '\u{308}',
+        'ˇ' => '\u{30C}',
+        '←' => '\u{20D6}',
+        '→' => '\u{20D7}',
+        _ => return None,
+    })
+}
+
+/// The combining character for an accent below a letter.
+fn combining_below(accent: char) -> Option<char> {
+    match accent {
+        '_' => Some('\u{332}'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TextRenderer;
+    use crate::ast::{Node, ScriptPair};
+    use crate::attribute::{MathVariant, OpAttr, RowAttr, TextTransform};
+    use crate::length::AbsoluteLength;
+    use crate::symbol;
+
+    fn render<'a>(node: &'a Node<'a>) -> String {
+        let mut renderer = TextRenderer::new();
+        renderer.render(node);
+        renderer.into_inner()
+    }
+
+    #[test]
+    fn render_scripts() {
+        let x = Node::SingleLetterIdent('x', false);
+        let two = Node::Number("2");
+        let n = Node::SingleLetterIdent('n', false);
+        let q = Node::SingleLetterIdent('q', false);
+        assert_eq!(
+            render(&Node::Superscript {
+                target: &x,
+                symbol: &two
+            }),
+            "x²"
+        );
+        assert_eq!(
+            render(&Node::SubSup {
+                target: &x,
+                sub: &n,
+                sup: &two,
+            }),
+            "xₙ²"
+        );
+        assert_eq!(
+            render(&Node::Subscript {
+                target: &x,
+                symbol: &q
+            }),
+            "x_q"
+        );
+        let row_nodes = [&q, &two];
+        let row = Node::Row {
+            nodes: &row_nodes,
+            attr: RowAttr::None,
+        };
+        assert_eq!(
+            render(&Node::Superscript {
+                target: &x,
+                symbol: &row
+            }),
+            "x^(q2)"
+        );
+        assert_eq!(
+            render(&Node::Multiscript {
+                base: &x,
+                scripts: &[
+                    ScriptPair {
+                        sub: Some(&n),
+                        sup: None,
+                    },
+                    ScriptPair {
+                        sub: None,
+                        sup: Some(&two),
+                    },
+                ],
+                num_postscripts: 1,
+            }),
+            "²xₙ"
+        );
+    }
+
+    #[test]
+    fn render_sqrt_and_frac() {
+        let x = Node::SingleLetterIdent('x', false);
+        let plus = Node::Operator(symbol::PLUS_SIGN.into(), None);
+        let one = Node::Number("1");
+        let two = Node::Number("2");
+        let row_nodes = [&x, &plus, &one];
+        let row = Node::Row {
+            nodes: &row_nodes,
+            attr: RowAttr::None,
+        };
+        assert_eq!(render(&Node::Sqrt(&two)), "√2");
+        assert_eq!(render(&Node::Sqrt(&row)), "√(x+1)");
+        assert_eq!(render(&Node::Root(&Node::Number("3"), &x)), "∛x");
+        assert_eq!(render(&Node::Root(&Node::Number("5"), &x)), "⁵√x");
+        assert_eq!(
+            render(&Node::Frac {
+                num: &row,
+                den: &two,
+                lt: None,
+                attr: None
+            }),
+            "(x+1)⁄2"
+        );
+        assert_eq!(
+            render(&Node::Frac {
+                num: &x,
+                den: &two,
+                lt: Some(AbsoluteLength(0).into()),
+                attr: None
+            }),
+            "x 2"
+        );
+    }
+
+    #[test]
+    fn render_transform_and_accent() {
+        let r = Node::SingleLetterIdent('R', false);
+        assert_eq!(
+            render(&Node::TextTransform {
+                tf: MathVariant::Transform(TextTransform::DoubleStruck),
+                content: &r,
+            }),
+            "ℝ"
+        );
+        let x = Node::SingleLetterIdent('x', false);
+        assert_eq!(
+            render(&Node::OverOp(
+                symbol::CIRCUMFLEX_ACCENT,
+                Some(OpAttr::StretchyFalse),
+                &x
+            )),
+            "x\u{302}"
+        );
+    }
+}
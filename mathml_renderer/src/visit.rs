@@ -0,0 +1,514 @@
+//! Traversal of the AST.
+//!
+//! [`Visit`] walks over the nodes without changing them, [`Fold`] rebuilds the tree
+//! in an arena, with the chance to replace any node along the way.
+use crate::arena::Arena;
+use crate::ast::{ArraySpec, ColumnSpec, Node, ScriptPair};
+
+/// A visitor which is called for every node in the tree.
+///
+/// Use [`walk`] to run it. The arguments of custom commands are visited in place of
+/// the `CustomCmdArg` nodes which refer to them, so the visitor sees the tree as if
+/// the commands had been expanded. The `CustomCmd` node itself is still visited.
+pub trait Visit<'arena> {
+    /// Called before the children of `node` are visited.
+    ///
+    /// If this returns `false`, the children of `node` are skipped, and `leave`
+    /// is not called for it.
+    fn enter(&mut self, node: &'arena Node<'arena>) -> bool {
+        let _ = node;
+        true
+    }
+
+    /// Called after the children of `node` have been visited.
+    fn leave(&mut self, node: &'arena Node<'arena>) {
+        let _ = node;
+    }
+}
+
+/// Visit all the given nodes and their children, in order.
+pub fn walk<'arena, V: Visit<'arena> + ?Sized>(visitor: &mut V, nodes: &[&'arena Node<'arena>]) {
+    let mut walker = Walker {
+        visitor,
+        custom_cmd_args: Vec::new(),
+    };
+    for node in nodes {
+        walker.walk(node);
+    }
+}
+
+struct Walker<'visitor, 'arena, V: ?Sized> {
+    visitor: &'visitor mut V,
+    /// The arguments of the custom commands we are currently in, innermost last.
+    custom_cmd_args: Vec<&'arena [&'arena Node<'arena>]>,
+}
+
+impl<'arena, V: Visit<'arena> + ?Sized> Walker<'_, 'arena, V> {
+    fn walk(&mut self, node: &'arena Node<'arena>) {
+        if let Node::CustomCmdArg(index) = node {
+            // The argument was parsed outside of the custom command, so any
+            // `CustomCmdArg` within it refers to the arguments of the enclosing command.
+            if let Some(args) = self.custom_cmd_args.pop() {
+                if let Some(arg) = args.get(*index) {
+                    self.walk(arg);
+                }
+                self.custom_cmd_args.push(args);
+                return;
+            }
+        }
+        if !self.visitor.enter(node) {
+            return;
+        }
+        match node {
+            Node::Number(_)
+            | Node::SingleLetterIdent(_, _)
+            | Node::Operator(_, _)
+            | Node::StretchableOp(_, _)
+            | Node::OpGreaterThan
+            | Node::OpLessThan
+            | Node::OpAmpersand
+            | Node::OperatorWithSpacing { .. }
+            | Node::MultiLetterIdent(_)
+            | Node::IdentOp(_)
+            | Node::CollectedLetters(_)
+            | Node::Space(_)
+            | Node::Kern(_)
+            | Node::Rule { .. }
+            | Node::SizedParen(_, _)
+            | Node::Text(_)
+            | Node::ColumnSeparator
+            | Node::RowSeparator
+            | Node::HLine { .. }
+            | Node::Tag(_)
+            | Node::Label(_)
+            | Node::CustomCmdArg(_)
+            | Node::HardcodedMathML(_)
+            | Node::Error(_) => {}
+            Node::Subscript { target, symbol } | Node::Superscript { target, symbol } => {
+                self.walk(target);
+                self.walk(symbol);
+            }
+            Node::SubSup { target, sub, sup } => {
+                self.walk(target);
+                self.walk(sub);
+                self.walk(sup);
+            }
+            Node::OverOp(_, _, target) | Node::UnderOp(_, target) => self.walk(target),
+            Node::Overset { symbol, target } | Node::Underset { symbol, target } => {
+                self.walk(symbol);
+                self.walk(target);
+            }
+            Node::UnderOver {
+                target,
+                under,
+                over,
+            } => {
+                self.walk(target);
+                self.walk(under);
+                self.walk(over);
+            }
+            Node::Sqrt(content)
+            | Node::Slashed(content)
+            | Node::Intertext(content)
+            | Node::Phantom(content)
+            | Node::Padded { content, .. }
+            | Node::Enclose { content, .. }
+            | Node::ColorBox { content, .. } => self.walk(content),
+            Node::Reference { number, .. } => self.walk(number),
+            Node::Root(degree, content) => {
+                self.walk(degree);
+                self.walk(content);
+            }
+            Node::Frac { num, den, .. } => {
+                self.walk(num);
+                self.walk(den);
+            }
+            Node::Row { nodes, .. } => {
+                for node in nodes.iter() {
+                    self.walk(node);
+                }
+            }
+            Node::Fenced { content, .. } | Node::TextTransform { content, .. } => {
+                self.walk(content)
+            }
+            Node::Table { content, .. } => {
+                for node in content.iter() {
+                    self.walk(node);
+                }
+            }
+            Node::Array {
+                content,
+                array_spec,
+            } => {
+                let separators = array_spec
+                    .columns
+                    .iter()
+                    .filter_map(|column| column.separator_after);
+                for separator in array_spec.beginning_separator.into_iter().chain(separators) {
+                    self.walk(separator);
+                }
+                for node in content.iter() {
+                    self.walk(node);
+                }
+            }
+            Node::Multiscript { base, scripts, .. } => {
+                self.walk(base);
+                for pair in scripts.iter() {
+                    for script in [pair.sub, pair.sup].into_iter().flatten() {
+                        self.walk(script);
+                    }
+                }
+            }
+            Node::CustomCmd { predefined, args } => {
+                self.custom_cmd_args.push(args);
+                self.walk(predefined);
+                self.custom_cmd_args.pop();
+            }
+        }
+        self.visitor.leave(node);
+    }
+}
+
+/// A transformation of the tree, which builds new nodes in an arena.
+///
+/// The default implementation of [`fold_node`](Fold::fold_node) rebuilds the node with
+/// [`fold_children`]. Implementors override it to replace the nodes they are interested
+/// in, and call [`fold_children`] for the rest.
+pub trait Fold<'arena> {
+    fn fold_node(
+        &mut self,
+        arena: &'arena Arena,
+        node: &'arena Node<'arena>,
+    ) -> &'arena Node<'arena> {
+        fold_children(self, arena, node)
+    }
+
+    /// Fold all nodes in a slice.
+    fn fold_nodes(
+        &mut self,
+        arena: &'arena Arena,
+        nodes: &'arena [&'arena Node<'arena>],
+    ) -> &'arena [&'arena Node<'arena>] {
+        let folded: Vec<_> = nodes
+            .iter()
+            .map(|node| self.fold_node(arena, node))
+            .collect();
+        arena.push_slice(&folded)
+    }
+}
+
+/// Rebuild `node` with all its children folded by `folder`.
+///
+/// Nodes without children are returned as they are. Custom commands are expanded:
+/// their arguments are substituted into the body, which is then folded in place of
+/// the `CustomCmd` node.
+pub fn fold_children<'arena, F: Fold<'arena> + ?Sized>(
+    folder: &mut F,
+    arena: &'arena Arena,
+    node: &'arena Node<'arena>,
+) -> &'arena Node<'arena> {
+    let new_node = match *node {
+        Node::Number(_)
+        | Node::SingleLetterIdent(_, _)
+        | Node::Operator(_, _)
+        | Node::StretchableOp(_, _)
+        | Node::OpGreaterThan
+        | Node::OpLessThan
+        | Node::OpAmpersand
+        | Node::OperatorWithSpacing { .. }
+        | Node::MultiLetterIdent(_)
+        | Node::IdentOp(_)
+        | Node::CollectedLetters(_)
+        | Node::Space(_)
+        | Node::Kern(_)
+        | Node::Rule { .. }
+        | Node::SizedParen(_, _)
+        | Node::Text(_)
+        | Node::ColumnSeparator
+        | Node::RowSeparator
+        | Node::HLine { .. }
+        | Node::Tag(_)
+        | Node::Label(_)
+        | Node::CustomCmdArg(_)
+        | Node::HardcodedMathML(_)
+        | Node::Error(_) => return node,
+        Node::Subscript { target, symbol } => Node::Subscript {
+            target: folder.fold_node(arena, target),
+            symbol: folder.fold_node(arena, symbol),
+        },
+        Node::Superscript { target, symbol } => Node::Superscript {
+            target: folder.fold_node(arena, target),
+            symbol: folder.fold_node(arena, symbol),
+        },
+        Node::SubSup { target, sub, sup } => Node::SubSup {
+            target: folder.fold_node(arena, target),
+            sub: folder.fold_node(arena, sub),
+            sup: folder.fold_node(arena, sup),
+        },
+        Node::OverOp(op, attr, target) => Node::OverOp(op, attr, folder.fold_node(arena, target)),
+        Node::UnderOp(op, target) => Node::UnderOp(op, folder.fold_node(arena, target)),
+        Node::Overset { symbol, target } => Node::Overset {
+            symbol: folder.fold_node(arena, symbol),
+            target: folder.fold_node(arena, target),
+        },
+        Node::Underset { symbol, target } => Node::Underset {
+            symbol: folder.fold_node(arena, symbol),
+            target: folder.fold_node(arena, target),
+        },
+        Node::UnderOver {
+            target,
+            under,
+            over,
+        } => Node::UnderOver {
+            target: folder.fold_node(arena, target),
+            under: folder.fold_node(arena, under),
+            over: folder.fold_node(arena, over),
+        },
+        Node::Sqrt(content) => Node::Sqrt(folder.fold_node(arena, content)),
+        Node::Root(degree, content) => Node::Root(
+            folder.fold_node(arena, degree),
+            folder.fold_node(arena, content),
+        ),
+        Node::Frac { num, den, lt, attr } => Node::Frac {
+            num: folder.fold_node(arena, num),
+            den: folder.fold_node(arena, den),
+            lt,
+            attr,
+        },
+        Node::Row { nodes, attr } => Node::Row {
+            nodes: folder.fold_nodes(arena, nodes),
+            attr,
+        },
+        Node::Fenced {
+            style,
+            open,
+            close,
+            content,
+        } => Node::Fenced {
+            style,
+            open,
+            close,
+            content: folder.fold_node(arena, content),
+        },
+        Node::Table {
+            content,
+            align,
+            attr,
+        } => Node::Table {
+            content: folder.fold_nodes(arena, content),
+            align,
+            attr,
+        },
+        Node::Array {
+            content,
+            array_spec,
+        } => {
+            let columns: Vec<ColumnSpec> = array_spec
+                .columns
+                .iter()
+                .map(|column| ColumnSpec {
+                    separator_after: column
+                        .separator_after
+                        .map(|separator| folder.fold_node(arena, separator)),
+                    ..*column
+                })
+                .collect();
+            let array_spec = arena.alloc_array_spec(ArraySpec {
+                beginning_line: array_spec.beginning_line,
+                beginning_separator: array_spec
+                    .beginning_separator
+                    .map(|separator| folder.fold_node(arena, separator)),
+                columns: arena.push_column_specs(&columns),
+            });
+            Node::Array {
+                content: folder.fold_nodes(arena, content),
+                array_spec,
+            }
+        }
+        Node::Slashed(content) => Node::Slashed(folder.fold_node(arena, content)),
+        Node::Intertext(content) => Node::Intertext(folder.fold_node(arena, content)),
+        Node::Phantom(content) => Node::Phantom(folder.fold_node(arena, content)),
+        Node::Padded { attr, content } => Node::Padded {
+            attr,
+            content: folder.fold_node(arena, content),
+        },
+        Node::Enclose { content, notation } => Node::Enclose {
+            content: folder.fold_node(arena, content),
+            notation,
+        },
+        Node::ColorBox {
+            content,
+            background,
+            frame,
+        } => Node::ColorBox {
+            content: folder.fold_node(arena, content),
+            background,
+            frame,
+        },
+        Node::Reference { label, number } => Node::Reference {
+            label,
+            number: folder.fold_node(arena, number),
+        },
+        Node::Multiscript {
+            base,
+            scripts,
+            num_postscripts,
+        } => {
+            let scripts: Vec<_> = scripts
+                .iter()
+                .map(|pair| ScriptPair {
+                    sub: pair.sub.map(|sub| folder.fold_node(arena, sub)),
+                    sup: pair.sup.map(|sup| folder.fold_node(arena, sup)),
+                })
+                .collect();
+            Node::Multiscript {
+                base: folder.fold_node(arena, base),
+                scripts: arena.push_script_pairs(&scripts),
+                num_postscripts,
+            }
+        }
+        Node::TextTransform { tf, content } => Node::TextTransform {
+            tf,
+            content: folder.fold_node(arena, content),
+        },
+        Node::CustomCmd { predefined, args } => {
+            let expanded = Substitute { args }.fold_node(arena, predefined);
+            return folder.fold_node(arena, expanded);
+        }
+    };
+    arena.push(new_node)
+}
+
+/// Replaces the `CustomCmdArg` nodes in the body of a custom command with the arguments.
+struct Substitute<'arena> {
+    args: &'arena [&'arena Node<'arena>],
+}
+
+impl<'arena> Fold<'arena> for Substitute<'arena> {
+    fn fold_node(
+        &mut self,
+        arena: &'arena Arena,
+        node: &'arena Node<'arena>,
+    ) -> &'arena Node<'arena> {
+        match *node {
+            Node::CustomCmdArg(index) => self.args.get(index).copied().unwrap_or(node),
+            // The body of a nested command refers to its own arguments,
+            // but the arguments themselves may refer to ours.
+            Node::CustomCmd { predefined, args } => arena.push(Node::CustomCmd {
+                predefined,
+                args: self.fold_nodes(arena, args),
+            }),
+            _ => fold_children(self, arena, node),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fold, Visit, fold_children, walk};
+    use crate::arena::Arena;
+    use crate::ast::{MathMLEmitter, Node};
+    use crate::attribute::RowAttr;
+    use crate::symbol;
+
+    /// Collects all single-letter identifiers.
+    struct Letters(String);
+
+    impl<'arena> Visit<'arena> for Letters {
+        fn enter(&mut self, node: &'arena Node<'arena>) -> bool {
+            if let Node::SingleLetterIdent(c, _) = node {
+                self.0.push(*c);
+            }
+            true
+        }
+    }
+
+    /// Turns every `x` into a `y`.
+    struct XToY;
+
+    impl<'arena> Fold<'arena> for XToY {
+        fn fold_node(
+            &mut self,
+            arena: &'arena Arena,
+            node: &'arena Node<'arena>,
+        ) -> &'arena Node<'arena> {
+            match node {
+                Node::SingleLetterIdent('x', is_normal) => {
+                    arena.push(Node::SingleLetterIdent('y', *is_normal))
+                }
+                _ => fold_children(self, arena, node),
+            }
+        }
+    }
+
+    /// Builds `\foo{a}{x}` with `\newcommand{\foo}[2]{\frac{#2}{#1}}`, followed by `b`.
+    fn custom_cmd(arena: &Arena) -> Vec<&Node<'_>> {
+        let body = arena.push(Node::Frac {
+            num: arena.push(Node::CustomCmdArg(1)),
+            den: arena.push(Node::CustomCmdArg(0)),
+            lt: None,
+            attr: None,
+        });
+        let args = [
+            &*arena.push(Node::SingleLetterIdent('a', false)),
+            &*arena.push(Node::SingleLetterIdent('x', false)),
+        ];
+        vec![
+            arena.push(Node::CustomCmd {
+                predefined: body,
+                args: arena.push_slice(&args),
+            }),
+            arena.push(Node::SingleLetterIdent('b', false)),
+        ]
+    }
+
+    #[test]
+    fn visit_custom_cmd_args() {
+        let arena = Arena::new();
+        let nodes = custom_cmd(&arena);
+        let mut letters = Letters(String::new());
+        walk(&mut letters, &nodes);
+        assert_eq!(letters.0, "xab");
+    }
+
+    #[test]
+    fn visit_skip_children() {
+        struct SkipFrac(usize);
+        impl<'arena> Visit<'arena> for SkipFrac {
+            fn enter(&mut self, node: &'arena Node<'arena>) -> bool {
+                self.0 += 1;
+                !matches!(node, Node::Frac { .. })
+            }
+        }
+        let arena = Arena::new();
+        let nodes = custom_cmd(&arena);
+        let mut counter = SkipFrac(0);
+        walk(&mut counter, &nodes);
+        // The custom command, the fraction and `b`.
+        assert_eq!(counter.0, 3);
+    }
+
+    #[test]
+    fn fold_expands_custom_cmd() {
+        let arena = Arena::new();
+        let nodes = custom_cmd(&arena);
+        let row = arena.push(Node::Row {
+            nodes: arena.push_slice(&nodes),
+            attr: RowAttr::None,
+        });
+        let folded = XToY.fold_node(&arena, row);
+        let mut emitter = MathMLEmitter::new();
+        emitter.emit(folded, 0);
+        assert_eq!(
+            emitter.into_inner(),
+            "<mrow><mfrac><mi>y</mi><mi>a</mi></mfrac><mi>b</mi></mrow>"
+        );
+    }
+
+    #[test]
+    fn fold_leaves_unchanged_nodes() {
+        let arena = Arena::new();
+        let op = arena.push(Node::Operator(symbol::EQUALS_SIGN.into(), None));
+        assert!(std::ptr::eq(XToY.fold_node(&arena, op), op));
+    }
+}
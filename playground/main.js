@@ -24,7 +24,7 @@ document.addEventListener('DOMContentLoaded', () => {
     function updateOutput() {
         try {
             const input = inputField.value;
-            const output = convert(input, isBlock(), isPrettyPrint());
+            const output = convert(input, { displayMode: isBlock(), pretty: isPrettyPrint() });
             outputField.innerHTML = output;
             outputCode.textContent = output;
         } catch (error) {
@@ -1,9 +1,12 @@
 from enum import Enum
+from typing import Optional
 
 # maturin is configured to put the compiled library in `_latex2mmlc_rust`
+from ._latex2mmlc_rust import LatexError
+from ._latex2mmlc_rust import LatexToMathML as _LatexToMathML
 from ._latex2mmlc_rust import convert_latex as _convert_latex
 
-__all__ = ["Display", "convert_latex"]
+__all__ = ["Display", "ErrorPolicy", "LatexError", "LatexToMathML", "convert_latex"]
 
 
 class Display(Enum):
@@ -11,5 +14,99 @@ class Display(Enum):
     BLOCK = True
 
 
-def convert_latex(latex: str, display: Display = Display.INLINE, pretty: bool = True) -> str:
-    return _convert_latex(latex, display.value, pretty)
+class ErrorPolicy(Enum):
+    RAISE = False
+    RENDER = True
+
+
+def _options(
+    display: Display,
+    pretty: bool,
+    indent_width: int,
+    xmlns: bool,
+    annotation: bool,
+    error_policy: ErrorPolicy,
+    equation_counter: int,
+    macros: Optional[dict[str, str]],
+) -> dict:
+    return {
+        "block": display.value,
+        "pretty": pretty,
+        "indent_width": indent_width,
+        "xmlns": xmlns,
+        "annotation": annotation,
+        "render_errors": error_policy.value,
+        "equation_counter": equation_counter,
+        "macros": list(macros.items()) if macros else [],
+    }
+
+
+def convert_latex(
+    latex: str,
+    display: Display = Display.INLINE,
+    pretty: bool = True,
+    *,
+    indent_width: int = 4,
+    xmlns: bool = False,
+    annotation: bool = False,
+    error_policy: ErrorPolicy = ErrorPolicy.RAISE,
+    equation_counter: int = 0,
+    macros: Optional[dict[str, str]] = None,
+) -> str:
+    options = _options(
+        display,
+        pretty,
+        indent_width,
+        xmlns,
+        annotation,
+        error_policy,
+        equation_counter,
+        macros,
+    )
+    return _convert_latex(latex, options)
+
+
+class LatexToMathML:
+    """A converter which keeps user-defined commands around across many conversions."""
+
+    def __init__(
+        self,
+        preamble: str = "",
+        *,
+        display: Display = Display.INLINE,
+        pretty: bool = True,
+        indent_width: int = 4,
+        xmlns: bool = False,
+        annotation: bool = False,
+        error_policy: ErrorPolicy = ErrorPolicy.RAISE,
+        equation_counter: int = 0,
+        macros: Optional[dict[str, str]] = None,
+    ):
+        options = _options(
+            display,
+            pretty,
+            indent_width,
+            xmlns,
+            annotation,
+            error_policy,
+            equation_counter,
+            macros,
+        )
+        self._converter = _LatexToMathML(options)
+        if preamble:
+            self._converter.define_macros(preamble)
+
+    def define_macros(self, preamble: str) -> None:
+        self._converter.define_macros(preamble)
+
+    @property
+    def equation_counter(self) -> int:
+        """The number of the last numbered equation so far."""
+        return self._converter.equation_counter
+
+    @equation_counter.setter
+    def equation_counter(self, counter: int) -> None:
+        self._converter.equation_counter = counter
+
+    def convert(self, latex: str, display: Optional[Display] = None) -> str:
+        return self._converter.convert(latex, None if display is None else display.value)
@@ -1,3 +1,5 @@
+import pytest
+
 import latex2mmlc
 
 
@@ -6,3 +8,66 @@ def test_integral():
         latex2mmlc.convert_latex(r"\int")
         == '<math xmlns="http://www.w3.org/1998/Math/MathML" display="inline">\n<mo>∫</mo>\n</math>'
     )
+
+
+def test_converter_with_macros():
+    converter = latex2mmlc.LatexToMathML(r"\newcommand{\R}{\mathbb{R}}", pretty=False)
+    assert converter.convert(r"\R") == "<math><mi>ℝ</mi></math>"
+    converter.define_macros(r"\def\abs#1{|#1|}")
+    assert (
+        converter.convert(r"\abs{\R}", latex2mmlc.Display.BLOCK)
+        == '<math display="block"><mrow><mi>|</mi><mi>ℝ</mi><mi>|</mi></mrow></math>'
+    )
+
+
+def test_options():
+    assert (
+        latex2mmlc.convert_latex(
+            r"\R",
+            latex2mmlc.Display.BLOCK,
+            pretty=False,
+            xmlns=True,
+            macros={r"\R": r"\mathbb{R}"},
+        )
+        == '<math xmlns="http://www.w3.org/1998/Math/MathML" display="block"><mi>ℝ</mi></math>'
+    )
+    assert (
+        latex2mmlc.convert_latex(
+            r"\asdf", pretty=False, error_policy=latex2mmlc.ErrorPolicy.RENDER
+        )
+        == r"<math><merror><mtext>\asdf</mtext></merror></math>"
+    )
+
+
+def test_annotation():
+    assert (
+        latex2mmlc.convert_latex("x<1", pretty=False, annotation=True)
+        == '<math><semantics><mrow><mi>x</mi><mo>&lt;</mo><mn>1</mn></mrow>'
+        '<annotation encoding="application/x-tex">x&lt;1</annotation></semantics></math>'
+    )
+
+
+def test_error_span():
+    with pytest.raises(latex2mmlc.LatexError) as exc_info:
+        latex2mmlc.convert_latex("x + y\n= \\frac{α}{\\asdf}")
+    error = exc_info.value
+    assert (error.start, error.end) == (18, 23)
+    assert (error.line, error.column) == (2, 12)
+
+
+def test_equation_counter():
+    converter = latex2mmlc.LatexToMathML(pretty=False, equation_counter=2)
+    assert "<mtext>(3)</mtext>" in converter.convert(r"\begin{equation} x \end{equation}")
+    assert converter.equation_counter == 3
+    converter.equation_counter = 0
+    assert "<mtext>(1)</mtext>" in converter.convert(r"\begin{equation} x \end{equation}")
+
+
+def test_partial_options():
+    # Options which are missing from the dict take their default values.
+    from latex2mmlc._latex2mmlc_rust import LatexToMathML, convert_latex
+
+    assert convert_latex("x", {}) == "<math><mi>x</mi></math>"
+    assert convert_latex("x", {"block": True}) == '<math display="block"><mi>x</mi></math>'
+    converter = LatexToMathML({"equation_counter": 2})
+    assert "<mtext>(3)</mtext>" in converter.convert(r"\begin{equation} x \end{equation}")